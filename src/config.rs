@@ -1,12 +1,13 @@
 #![allow(dead_code)]
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{env, fs};
+use std::time::Duration;
+use std::{env, fmt, fs};
 
-use anyhow::Result;
-use clap::{ArgEnum, Parser};
+use anyhow::{anyhow, Result};
+use clap::{ArgEnum, Parser, Subcommand};
 use directories::BaseDirs;
 use log::debug;
 use regex::Regex;
@@ -15,6 +16,8 @@ use strum::{EnumIter, EnumString, EnumVariantNames, IntoEnumIterator};
 use sys_info::hostname;
 use which_crate::which;
 
+use super::schedule;
+use super::terminal::print_warning;
 use super::utils::editor;
 
 pub static EXAMPLE_CONFIG: &str = include_str!("../config.example.toml");
@@ -60,15 +63,161 @@ macro_rules! get_deprecated {
     };
 }
 
-type Commands = BTreeMap<String, String>;
+type Commands = BTreeMap<String, CustomCommand>;
 
-#[derive(ArgEnum, EnumString, EnumVariantNames, Debug, Clone, PartialEq, Eq, Deserialize, EnumIter, Copy)]
+/// A custom command, either a command line run by the default shell or a table with more options
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum CustomCommand {
+    Simple(String),
+    Detailed(DetailedCustomCommand),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DetailedCustomCommand {
+    command: String,
+    cwd: Option<String>,
+    shell: Option<String>,
+    depends_on: Option<String>,
+    timeout: Option<String>,
+    capture_output: Option<bool>,
+}
+
+impl CustomCommand {
+    /// The command line to run
+    pub fn command(&self) -> &str {
+        match self {
+            CustomCommand::Simple(command) => command,
+            CustomCommand::Detailed(detailed) => &detailed.command,
+        }
+    }
+
+    /// The working directory of the command
+    pub fn cwd(&self) -> Option<&str> {
+        match self {
+            CustomCommand::Simple(_) => None,
+            CustomCommand::Detailed(detailed) => detailed.cwd.as_deref(),
+        }
+    }
+
+    /// The shell running the command. `none` executes the command directly
+    pub fn shell(&self) -> Option<&str> {
+        match self {
+            CustomCommand::Simple(_) => None,
+            CustomCommand::Detailed(detailed) => detailed.shell.as_deref(),
+        }
+    }
+
+    /// How long the command may run before it's killed
+    pub fn timeout(&self) -> Result<Option<Duration>> {
+        match self {
+            CustomCommand::Detailed(DetailedCustomCommand {
+                timeout: Some(timeout), ..
+            }) => humantime::parse_duration(timeout)
+                .map(Some)
+                .map_err(|e| anyhow!("Invalid timeout {}: {}", timeout, e)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether to keep the end of the output to show it in the summary when the command fails
+    pub fn capture_output(&self) -> bool {
+        match self {
+            CustomCommand::Simple(_) => false,
+            CustomCommand::Detailed(detailed) => detailed.capture_output.unwrap_or(false),
+        }
+    }
+
+    /// The custom command which has to succeed before this one runs
+    pub fn depends_on(&self) -> Option<&str> {
+        match self {
+            CustomCommand::Simple(_) => None,
+            CustomCommand::Detailed(detailed) => detailed.depends_on.as_deref(),
+        }
+    }
+}
+
+/// Make sure the timeouts of the steps and custom commands are valid durations, so that a typo stops the run
+/// before any step rather than when the step is reached
+fn check_timeouts(config_file: &ConfigFile) -> Result<()> {
+    let commands = [
+        &config_file.pre_commands,
+        &config_file.commands,
+        &config_file.post_commands,
+    ];
+    for (name, command) in commands.iter().copied().flatten().flatten() {
+        command
+            .timeout()
+            .map_err(|e| anyhow!("{} in the custom command {}", e, name))?;
+    }
+    for (step, command) in config_file.post_app_update.iter().flatten() {
+        command
+            .timeout()
+            .map_err(|e| anyhow!("{} in the post_app_update of {:?}", e, step))?;
+    }
+    for (step, timeout) in config_file.timeouts.iter().flatten() {
+        humantime::parse_duration(timeout).map_err(|e| anyhow!("Invalid timeout {} of {:?}: {}", timeout, step, e))?;
+    }
+
+    Ok(())
+}
+
+/// Make sure every dependency of a custom command exists and that there are no cycles
+fn check_command_dependencies(commands: &Commands) -> Result<()> {
+    for name in commands.keys() {
+        let mut chain = vec![name.as_str()];
+        let mut current = name.as_str();
+        while let Some(dependency) = commands[current].depends_on() {
+            if !commands.contains_key(dependency) {
+                return Err(anyhow!(
+                    "Custom command {} depends on {}, which doesn't exist",
+                    current,
+                    dependency
+                ));
+            }
+            if chain.contains(&dependency) {
+                chain.push(dependency);
+                return Err(anyhow!("Cyclic dependency of custom commands: {}", chain.join(" -> ")));
+            }
+            chain.push(dependency);
+            current = dependency;
+        }
+    }
+
+    Ok(())
+}
+
+/// The custom commands ordered so that every command comes after its dependency
+pub fn commands_in_dependency_order(commands: &Commands) -> Vec<(&String, &CustomCommand)> {
+    fn visit<'a>(name: &'a String, commands: &'a Commands, ordered: &mut Vec<(&'a String, &'a CustomCommand)>) {
+        if ordered.iter().any(|(n, _)| *n == name) {
+            return;
+        }
+        let command = &commands[name];
+        if let Some((dependency, _)) = command.depends_on().and_then(|d| commands.get_key_value(d)) {
+            visit(dependency, commands, ordered);
+        }
+        ordered.push((name, command));
+    }
+
+    let mut ordered = Vec::with_capacity(commands.len());
+    for name in commands.keys() {
+        visit(name, commands, &mut ordered);
+    }
+    ordered
+}
+
+#[derive(ArgEnum, EnumString, EnumVariantNames, Debug, Clone, PartialEq, Eq, Hash, Deserialize, EnumIter, Copy)]
 #[clap(rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum Step {
+    AndroidSdk,
+    AppImage,
     Asdf,
     Atom,
+    Backup,
     BrewCask,
     BrewFormula,
     Bun,
@@ -82,14 +231,19 @@ pub enum Step {
     ConfigUpdate,
     Containers,
     CustomCommands,
+    Dart,
     DebGet,
     Deno,
+    Distrobox,
+    Dkms,
     Dotnet,
     Emacs,
     Firmware,
     Flatpak,
     Flutter,
+    Fnm,
     Fossil,
+    FreebsdPkg,
     Gcloud,
     Gem,
     GithubCliExtensions,
@@ -99,36 +253,51 @@ pub enum Step {
     Haxelib,
     GnomeShellExtensions,
     HomeManager,
+    #[clap(name = "jetbrains")]
+    #[serde(rename = "jetbrains")]
+    #[strum(serialize = "jetbrains")]
+    JetBrains,
     Jetpack,
     Julia,
     Kakoune,
     Krew,
+    Kubernetes,
     Macports,
     Mas,
     Micro,
     Myrepos,
     Nix,
     Node,
+    Nvm,
     Opam,
     Pacdef,
+    #[clap(name = "packagekit")]
+    #[serde(rename = "packagekit")]
+    #[strum(serialize = "packagekit")]
+    PackageKit,
     Pacstall,
     Pearl,
     Pipx,
     Pip3,
     Pkg,
     Pkgin,
+    Plugins,
     Powershell,
     Protonup,
+    Pyenv,
     Raco,
     Remotes,
     Restarts,
+    Ros,
     Rtcl,
     Rustup,
     Scoop,
+    SelfUpdate,
     Sdkman,
     Sheldon,
     Shell,
     Snap,
+    Snapshot,
     Sparkle,
     Spicetify,
     Stack,
@@ -142,9 +311,239 @@ pub enum Step {
     Vim,
     Winget,
     Wsl,
+    XcodeCommandLineTools,
     Yadm,
 }
 
+impl Step {
+    /// Whether the step needs the network, and is skipped in offline mode
+    pub fn requires_network(self) -> bool {
+        // Custom commands are up to the user
+        !matches!(
+            self,
+            Step::Backup
+                | Step::ConfigUpdate
+                | Step::CustomCommands
+                | Step::Dkms
+                | Step::Restarts
+                | Step::Ros
+                | Step::Snapshot
+        )
+    }
+
+    /// The executables of the step, one of which has to be installed for it to run. `--list-steps` looks for
+    /// them instead of running the step. Steps relying on paths or on the system have none
+    pub fn tools(self) -> &'static [&'static str] {
+        match self {
+            Step::AndroidSdk => &["sdkmanager"],
+            Step::AppImage => &["appimageupdatetool"],
+            Step::Asdf => &["asdf"],
+            Step::Atom => &["apm"],
+            Step::Backup => &["restic", "borgmatic"],
+            Step::BrewCask | Step::BrewFormula => &["brew"],
+            Step::Bun => &["bun"],
+            Step::Bin => &["bin"],
+            Step::Cargo => &["cargo-install-update"],
+            Step::Chezmoi => &["chezmoi"],
+            Step::Chocolatey => &["choco"],
+            Step::Choosenim => &["choosenim"],
+            Step::Composer => &["composer"],
+            Step::Conda => &["conda"],
+            Step::ConfigUpdate => &["etc-update", "pacdiff"],
+            Step::Containers => &["docker", "podman"],
+            Step::Dart => &["dart"],
+            Step::DebGet => &["deb-get"],
+            Step::Deno => &["deno"],
+            Step::Distrobox => &["distrobox"],
+            Step::Dkms => &["dkms"],
+            Step::Dotnet => &["dotnet"],
+            Step::Emacs => &["emacs"],
+            Step::Firmware => &["fwupdmgr"],
+            Step::Flatpak => &["flatpak"],
+            Step::Flutter => &["flutter"],
+            Step::Fnm => &["fnm"],
+            Step::Fossil => &["fossil"],
+            Step::FreebsdPkg | Step::Pkg => &["pkg"],
+            Step::Gcloud => &["gcloud"],
+            Step::Gem => &["gem"],
+            Step::GithubCliExtensions => &["gh"],
+            Step::GitRepos => &["git"],
+            Step::Go => &["go"],
+            Step::Guix => &["guix"],
+            Step::Haxelib => &["haxelib"],
+            Step::GnomeShellExtensions => &["gdbus"],
+            Step::HomeManager => &["home-manager"],
+            Step::Jetpack => &["jetpack"],
+            Step::Julia => &["julia"],
+            Step::Kakoune => &["kak"],
+            Step::Krew => &["kubectl-krew"],
+            Step::Kubernetes => &["kubectl"],
+            Step::Macports => &["port"],
+            Step::Mas => &["mas"],
+            Step::Micro => &["micro"],
+            Step::Myrepos => &["mr"],
+            Step::Nix => &["nix", "nix-env"],
+            Step::Node => &["npm", "yarn", "pnpm"],
+            Step::Opam => &["opam"],
+            Step::Pacdef => &["pacdef"],
+            Step::PackageKit => &["pkcon"],
+            Step::Pacstall => &["pacstall"],
+            Step::Pearl => &["pearl"],
+            Step::Pipx => &["pipx"],
+            Step::Pip3 => &["pip3", "python3"],
+            Step::Pkgin => &["pkgin"],
+            Step::Powershell => &["pwsh", "powershell"],
+            Step::Protonup => &["protonup"],
+            Step::Pyenv => &["pyenv"],
+            Step::Raco => &["raco"],
+            Step::Remotes => &["ssh"],
+            Step::Restarts => &["needrestart", "needs-restarting"],
+            Step::Rtcl => &["rupdate"],
+            Step::Rustup => &["rustup"],
+            Step::Scoop => &["scoop"],
+            Step::Sheldon => &["sheldon"],
+            Step::Shell => &["zr", "antibody", "zsh", "fish", "bash"],
+            Step::Snap => &["snap"],
+            Step::Sparkle => &["sparkle"],
+            Step::Spicetify => &["spicetify"],
+            Step::Stack => &["stack"],
+            Step::Tldr => &["tldr"],
+            Step::Tlmgr => &["tlmgr"],
+            Step::Tmux => &["tmux"],
+            Step::Toolbx => &["toolbox"],
+            Step::Vagrant => &["vagrant"],
+            Step::Vcpkg => &["vcpkg"],
+            Step::Vim => &["vim", "nvim", "voom"],
+            Step::Winget => &["winget"],
+            Step::Wsl => &["wsl"],
+            Step::Yadm => &["yadm"],
+            Step::CustomCommands
+            | Step::JetBrains
+            | Step::Nvm
+            | Step::Plugins
+            | Step::Ros
+            | Step::SelfUpdate
+            | Step::Sdkman
+            | Step::Snapshot
+            | Step::System
+            | Step::XcodeCommandLineTools => &[],
+        }
+    }
+}
+
+/// Which skipped steps to show in the summary
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SummarySkipDisplay {
+    All,
+    /// Everything but the steps skipped because their tool isn't installed
+    Reasons,
+    None,
+}
+
+/// Format of the machine readable report of a run
+#[derive(ArgEnum, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    Json,
+}
+
+/// The `report` setting: a format, or a table with the format and the email sent after each run
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ReportSetting {
+    Format(ReportFormat),
+    Detailed(DetailedReport),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DetailedReport {
+    format: Option<ReportFormat>,
+    email: Option<Email>,
+}
+
+/// How the connection to the SMTP server is secured
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTls {
+    /// Upgrade the connection with STARTTLS, failing if the server doesn't support it
+    Starttls,
+    /// Connect with TLS, usually on port 465
+    Tls,
+    None,
+}
+
+/// The email with the summary of the run
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    host: String,
+    port: Option<u16>,
+    tls: Option<EmailTls>,
+    username: Option<String>,
+    password: Option<String>,
+    from: Option<String>,
+    to: Vec<String>,
+    output: Option<bool>,
+}
+
+impl Email {
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn tls(&self) -> EmailTls {
+        self.tls.unwrap_or(EmailTls::Starttls)
+    }
+
+    /// The port of the SMTP server, by default the usual one for `tls`
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.tls() {
+            EmailTls::Starttls => 587,
+            EmailTls::Tls => 465,
+            EmailTls::None => 25,
+        })
+    }
+
+    /// The username and password to log in with
+    pub fn credentials(&self) -> Option<(&str, &str)> {
+        self.username
+            .as_deref()
+            .map(|username| (username, self.password.as_deref().unwrap_or_default()))
+    }
+
+    /// The sender address, the username by default
+    pub fn from(&self) -> Option<&str> {
+        self.from.as_deref().or(self.username.as_deref())
+    }
+
+    pub fn to(&self) -> &[String] {
+        &self.to
+    }
+
+    /// Whether to add the output of the failed steps
+    pub fn output(&self) -> bool {
+        self.output.unwrap_or(false)
+    }
+}
+
+/// Steps to disable or enable on the hosts whose name matches the pattern of the section
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HostOverride {
+    disable: Option<Vec<Step>>,
+    enable: Option<Vec<Step>>,
+}
+
+/// What to do with the rest of the run when a step fails
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    Continue,
+    Abort,
+}
+
 #[derive(Deserialize, Default, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Git {
@@ -152,6 +551,19 @@ pub struct Git {
     arguments: Option<String>,
     repos: Option<Vec<String>>,
     pull_predefined: Option<bool>,
+    update_worktrees: Option<bool>,
+    pull_strategy: Option<GitPullStrategy>,
+    pull_strategies: Option<HashMap<String, GitPullStrategy>>,
+    autostash_repos: Option<Vec<String>>,
+}
+
+/// How `git pull` integrates the upstream changes
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GitPullStrategy {
+    Merge,
+    Rebase,
+    FfOnly,
 }
 
 #[derive(Deserialize, Default, Debug)]
@@ -203,7 +615,22 @@ pub struct Flatpak {
 #[serde(deny_unknown_fields)]
 pub struct Brew {
     greedy_cask: Option<bool>,
+    no_quarantine_cask: Option<bool>,
     autoremove: Option<bool>,
+    restart_services: Option<bool>,
+    services: Option<Vec<String>>,
+    doctor: Option<bool>,
+    report_pinned: Option<bool>,
+    update_taps: Option<bool>,
+    relink: Option<Vec<String>>,
+    isolate_failures: Option<bool>,
+    fetch_head: Option<bool>,
+    no_analytics: Option<bool>,
+    prefetch: Option<bool>,
+    overwrite_link_conflicts: Option<bool>,
+    parallel_variants: Option<bool>,
+    skip_if_nothing_outdated: Option<bool>,
+    json_report: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Clone, Copy)]
@@ -218,12 +645,21 @@ pub enum ArchPackageManager {
     Pamac,
 }
 
+/// The tool building a ROS workspace
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RosBuildTool {
+    Colcon,
+    Catkin,
+}
+
 #[derive(Deserialize, Default, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Linux {
     yay_arguments: Option<String>,
     arch_package_manager: Option<ArchPackageManager>,
     show_arch_news: Option<bool>,
+    arch_remove_orphans: Option<bool>,
     trizen_arguments: Option<String>,
     pikaur_arguments: Option<String>,
     pamac_arguments: Option<String>,
@@ -231,11 +667,393 @@ pub struct Linux {
     apt_arguments: Option<String>,
     enable_tlmgr: Option<bool>,
     redhat_distro_sync: Option<bool>,
+    dnf_security_only: Option<bool>,
+    zypper_dist_upgrade: Option<bool>,
     rpm_ostree: Option<bool>,
     emerge_sync_flags: Option<String>,
     emerge_update_flags: Option<String>,
 }
 
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Freebsd {
+    update_ports: Option<bool>,
+}
+
+/// The tool making the backup before the upgrades
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupTool {
+    Restic,
+    Borgmatic,
+}
+
+/// The backup made before the upgrades
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Backup {
+    tool: BackupTool,
+    repository: Option<String>,
+    password_file: Option<String>,
+    paths: Option<Vec<String>>,
+    config: Option<String>,
+    arguments: Option<String>,
+    sudo: Option<bool>,
+}
+
+impl Backup {
+    pub fn tool(&self) -> BackupTool {
+        self.tool
+    }
+
+    /// The restic repository, from `RESTIC_REPOSITORY` otherwise
+    pub fn repository(&self) -> Option<&str> {
+        self.repository.as_deref()
+    }
+
+    /// The file with the password of the restic repository
+    pub fn password_file(&self) -> Option<&str> {
+        self.password_file.as_deref()
+    }
+
+    /// The paths restic backs up
+    pub fn paths(&self) -> &[String] {
+        self.paths.as_deref().unwrap_or_default()
+    }
+
+    /// The borgmatic configuration file, its default ones otherwise
+    pub fn config(&self) -> Option<&str> {
+        self.config.as_deref()
+    }
+
+    /// Extra arguments of the backup command
+    pub fn arguments(&self) -> Option<&str> {
+        self.arguments.as_deref()
+    }
+
+    /// Whether to run the backup with sudo
+    pub fn sudo(&self) -> bool {
+        self.sudo.unwrap_or(false)
+    }
+}
+
+/// What runs inside the containers of `[containers.exec]`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerStep {
+    /// The package manager of the distribution of the container
+    System,
+    Npm,
+    Pipx,
+}
+
+impl fmt::Display for ContainerStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ContainerStep::System => "System update",
+            ContainerStep::Npm => "npm",
+            ContainerStep::Pipx => "pipx",
+        })
+    }
+}
+
+/// A long-lived container in which steps run with `docker exec` or `podman exec`
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ExecContainer {
+    runtime: Option<String>,
+    user: Option<String>,
+    steps: Option<Vec<ContainerStep>>,
+}
+
+impl ExecContainer {
+    /// The container runtime, or podman and then docker
+    pub fn runtime(&self) -> Option<&str> {
+        self.runtime.as_deref()
+    }
+
+    /// The user running the steps in the container
+    pub fn user(&self) -> &str {
+        self.user.as_deref().unwrap_or("root")
+    }
+
+    pub fn steps(&self) -> &[ContainerStep] {
+        self.steps.as_deref().unwrap_or(&[ContainerStep::System])
+    }
+}
+
+/// A pod in which commands run with `kubectl exec`, like on a remote host
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct KubernetesTarget {
+    target: Option<String>,
+    context: Option<String>,
+    namespace: Option<String>,
+    container: Option<String>,
+    commands: Vec<String>,
+}
+
+impl KubernetesTarget {
+    /// The pod, or a resource with pods like "deployment/pihole", otherwise the name of the target
+    pub fn target<'a>(&'a self, name: &'a str) -> &'a str {
+        self.target.as_deref().unwrap_or(name)
+    }
+
+    /// The container of the pod, or its default one
+    pub fn container(&self) -> Option<&str> {
+        self.container.as_deref()
+    }
+
+    /// The commands run by the shell of the container
+    pub fn commands(&self) -> &[String] {
+        &self.commands
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Kubernetes {
+    context: Option<String>,
+    namespace: Option<String>,
+    targets: Option<BTreeMap<String, KubernetesTarget>>,
+}
+
+impl Kubernetes {
+    /// The targets, by name
+    pub fn targets(&self) -> impl Iterator<Item = (&String, &KubernetesTarget)> {
+        self.targets.iter().flatten()
+    }
+
+    /// The kubectl context of the target, or the current one
+    pub fn context<'a>(&'a self, target: &'a KubernetesTarget) -> Option<&'a str> {
+        target.context.as_deref().or(self.context.as_deref())
+    }
+
+    /// The namespace of the target, or the one of the context
+    pub fn namespace<'a>(&'a self, target: &'a KubernetesTarget) -> Option<&'a str> {
+        target.namespace.as_deref().or(self.namespace.as_deref())
+    }
+}
+
+/// The invocation of topgrade on one of the `remote_topgrades`, from `[remote.hosts."name"]`
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteHost {
+    only: Option<Vec<Step>>,
+    disable: Option<Vec<Step>>,
+    arguments: Option<String>,
+    ssh_arguments: Option<String>,
+    env: Option<BTreeMap<String, String>>,
+}
+
+impl RemoteHost {
+    /// The only steps run on the host
+    pub fn only(&self) -> &[Step] {
+        self.only.as_deref().unwrap_or_default()
+    }
+
+    /// The steps disabled on the host
+    pub fn disable(&self) -> &[Step] {
+        self.disable.as_deref().unwrap_or_default()
+    }
+
+    /// Extra arguments of topgrade on the host
+    pub fn arguments(&self) -> Option<&str> {
+        self.arguments.as_deref()
+    }
+
+    /// Extra SSH arguments for the host, after the `ssh_arguments` of all hosts
+    pub fn ssh_arguments(&self) -> Option<&str> {
+        self.ssh_arguments.as_deref()
+    }
+
+    /// The environment variables of topgrade on the host
+    pub fn env(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.env.iter().flatten()
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Remote {
+    hosts: Option<BTreeMap<String, RemoteHost>>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Containers {
+    exec: Option<BTreeMap<String, ExecContainer>>,
+}
+
+/// The tool taking the snapshot before the system update
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotBackend {
+    Snapper,
+    Timeshift,
+    Btrfs,
+    Zfs,
+}
+
+/// What to do when running on battery power or on a metered connection
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintPolicy {
+    Warn,
+    Abort,
+    /// Skip the `heavy_steps`
+    SkipHeavy,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Power {
+    on_battery: Option<ConstraintPolicy>,
+    heavy_steps: Option<Vec<Step>>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Network {
+    on_metered: Option<ConstraintPolicy>,
+    heavy_steps: Option<Vec<Step>>,
+}
+
+/// The snapshot of the filesystem taken before the system update
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Snapshot {
+    backend: SnapshotBackend,
+    snapper_config: Option<String>,
+    subvolume: Option<String>,
+    directory: Option<String>,
+    dataset: Option<String>,
+    keep: Option<usize>,
+}
+
+impl Snapshot {
+    pub fn backend(&self) -> SnapshotBackend {
+        self.backend
+    }
+
+    /// The snapper configuration to take the snapshot with, `root` by default
+    pub fn snapper_config(&self) -> &str {
+        self.snapper_config.as_deref().unwrap_or("root")
+    }
+
+    /// The btrfs subvolume to snapshot, `/` by default
+    pub fn subvolume(&self) -> &str {
+        self.subvolume.as_deref().unwrap_or("/")
+    }
+
+    /// The directory the btrfs snapshots are created in
+    pub fn directory(&self) -> Option<&str> {
+        self.directory.as_deref()
+    }
+
+    /// The ZFS dataset to snapshot
+    pub fn dataset(&self) -> Option<&str> {
+        self.dataset.as_deref()
+    }
+
+    /// Number of snapshots taken by Topgrade to keep with btrfs and ZFS
+    pub fn keep(&self) -> usize {
+        self.keep.unwrap_or(3)
+    }
+}
+
+/// How to retry a step automatically when it fails
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StepRetries {
+    retries: Option<u32>,
+    retry_delay: Option<String>,
+}
+
+/// When `topgrade daemon` runs the steps
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    interval: Option<String>,
+    cron: Option<String>,
+    quiet: Option<bool>,
+    notify: Option<bool>,
+}
+
+/// Where to tell that a run finished, besides the desktop notification
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Notifications {
+    webhook: Option<String>,
+    template: Option<String>,
+    only_failures: Option<bool>,
+    ntfy: Option<Ntfy>,
+    gotify: Option<Gotify>,
+    telegram: Option<Telegram>,
+}
+
+/// A topic of an ntfy server
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Ntfy {
+    server: Option<String>,
+    topic: String,
+    token: Option<String>,
+}
+
+impl Ntfy {
+    /// The URL of the server, ntfy.sh by default
+    pub fn server(&self) -> &str {
+        self.server.as_deref().unwrap_or("https://ntfy.sh")
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// The access token for protected topics
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+}
+
+/// An application of a Gotify server
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Gotify {
+    url: String,
+    token: String,
+}
+
+impl Gotify {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The token of the application
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+/// A chat a Telegram bot sends messages to
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Telegram {
+    bot_token: String,
+    chat_id: String,
+}
+
+impl Telegram {
+    pub fn bot_token(&self) -> &str {
+        &self.bot_token
+    }
+
+    pub fn chat_id(&self) -> &str {
+        &self.chat_id
+    }
+}
+
 #[derive(Deserialize, Default, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Composer {
@@ -255,14 +1073,26 @@ pub struct ConfigFile {
     pre_commands: Option<Commands>,
     post_commands: Option<Commands>,
     commands: Option<Commands>,
+    self_update_binaries: Option<BTreeMap<String, Vec<String>>>,
     git_repos: Option<Vec<String>>,
     predefined_git_repos: Option<bool>,
     disable: Option<Vec<Step>>,
+    host_overrides: Option<BTreeMap<String, HostOverride>>,
     ignore_failures: Option<Vec<Step>>,
+    step_failure_policy: Option<HashMap<Step, FailurePolicy>>,
+    post_app_update: Option<HashMap<Step, CustomCommand>>,
+    timeouts: Option<HashMap<Step, String>>,
+    step_retries: Option<HashMap<Step, StepRetries>>,
     remote_topgrades: Option<Vec<String>>,
+    remote_concurrency: Option<usize>,
+    remote_windows_hosts: Option<Vec<String>>,
+    remote_deploy: Option<bool>,
+    remote_deploy_cache: Option<String>,
     remote_topgrade_path: Option<String>,
     ssh_arguments: Option<String>,
+    remote: Option<Remote>,
     git_arguments: Option<String>,
+    git_repos_concurrency: Option<usize>,
     tmux_arguments: Option<String>,
     set_title: Option<bool>,
     display_time: Option<bool>,
@@ -274,11 +1104,47 @@ pub struct ConfigFile {
     notify_each_step: Option<bool>,
     accept_all_windows_updates: Option<bool>,
     skip_notify: Option<bool>,
+    sudo_command: Option<String>,
+    sudo_keep_alive: Option<bool>,
+    no_sudo: Option<bool>,
     bashit_branch: Option<String>,
     only: Option<Vec<Step>>,
+    min_step_interval: Option<String>,
+    show_changes: Option<bool>,
+    summary_skip_display: Option<SummarySkipDisplay>,
+    go_binaries: Option<Vec<String>>,
+    cargo_projects: Option<Vec<String>>,
+    cargo_audit: Option<bool>,
+    nix_gc: Option<bool>,
+    sdkman_offline_check: Option<bool>,
+    distrobox_containers: Option<Vec<String>>,
+    history_max_entries: Option<usize>,
+    ros_distro: Option<String>,
+    asdf_install_latest: Option<bool>,
+    pyenv_install_latest_patch: Option<bool>,
+    auto_offline: Option<bool>,
+    connectivity_probes: Option<Vec<String>>,
+    connectivity_wait: Option<String>,
+    min_free_space: Option<BTreeMap<String, String>>,
+    appimage_paths: Option<Vec<String>>,
+    max_concurrency: Option<usize>,
+    report: Option<ReportSetting>,
+    report_file: Option<String>,
+    metrics_file: Option<String>,
+    mas_ignore: Option<Vec<u64>>,
+    ros_workspaces: Option<BTreeMap<String, RosBuildTool>>,
     composer: Option<Composer>,
+    schedule: Option<Schedule>,
+    notifications: Option<Notifications>,
+    snapshot: Option<Snapshot>,
+    power: Option<Power>,
+    network: Option<Network>,
+    backup: Option<Backup>,
+    containers: Option<Containers>,
+    kubernetes: Option<Kubernetes>,
     brew: Option<Brew>,
     linux: Option<Linux>,
+    freebsd: Option<Freebsd>,
     git: Option<Git>,
     windows: Option<Windows>,
     npm: Option<NPM>,
@@ -289,7 +1155,7 @@ pub struct ConfigFile {
     flatpak: Option<Flatpak>,
 }
 
-fn config_directory(base_dirs: &BaseDirs) -> PathBuf {
+pub(crate) fn config_directory(base_dirs: &BaseDirs) -> PathBuf {
     #[cfg(not(target_os = "macos"))]
     return base_dirs.config_dir().to_owned();
 
@@ -297,6 +1163,146 @@ fn config_directory(base_dirs: &BaseDirs) -> PathBuf {
     return base_dirs.home_dir().join(".config");
 }
 
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Shorten serde's "unknown field" and "unknown variant" errors, which list every valid name,
+/// into one naming the offending key and the closest valid name
+fn explain_config_error(error: toml::de::Error) -> anyhow::Error {
+    let message = error.to_string();
+    let explained = ["field", "variant"].iter().find_map(|&kind| {
+        let (name, rest) = message.strip_prefix(&format!("unknown {} `", kind))?.split_once('`')?;
+        let (expected, key) = match rest.rsplit_once(" for key `") {
+            Some((expected, key)) => (expected, key.strip_suffix('`')),
+            None => (rest, None),
+        };
+        let suggestion = expected
+            .split('`')
+            .skip(1)
+            .step_by(2)
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= (name.len() / 3).max(1))
+            .min_by_key(|(distance, _)| *distance)?
+            .1;
+
+        Some(match (kind, key) {
+            ("field", Some(key)) => format!("Unknown key `{}` in [{}], did you mean `{}`?", name, key, suggestion),
+            ("field", None) => format!("Unknown key `{}`, did you mean `{}`?", name, suggestion),
+            (_, Some(key)) => format!("Unknown value `{}` for `{}`, did you mean `{}`?", name, key, suggestion),
+            (_, None) => format!("Unknown value `{}`, did you mean `{}`?", name, suggestion),
+        })
+    });
+
+    match explained {
+        Some(explained) => anyhow!(explained),
+        None => error.into(),
+    }
+}
+
+/// Top level keys whose values are left as is: step names, and commands which are expanded by the shell
+const UNEXPANDED_KEYS: &[&str] = &[
+    "disable",
+    "only",
+    "ignore_failures",
+    "step_failure_policy",
+    "pre_commands",
+    "post_commands",
+    "commands",
+    "post_app_update",
+    "self_update_binaries",
+];
+
+/// Expand `${ENV_VAR}` references in the string values of the configuration
+fn expand_env_vars_in_config(config: &mut toml::Value) {
+    if let toml::Value::Table(table) = config {
+        for (key, value) in table.iter_mut() {
+            if !UNEXPANDED_KEYS.contains(&key.as_str()) {
+                expand_env_vars(value);
+            }
+        }
+    }
+}
+
+fn expand_env_vars(value: &mut toml::Value) {
+    match value {
+        toml::Value::String(string) => *string = expand_env_vars_in_string(string),
+        toml::Value::Array(array) => {
+            for value in array.iter_mut() {
+                expand_env_vars(value);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                expand_env_vars(value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Replace every `${ENV_VAR}` in the string with the value of the variable.
+///
+/// References that are not valid variable names are kept as is, and so are the ones to unset variables, with a
+/// warning: they can be meant for a shell. Substituted values are not expanded again.
+fn expand_env_vars_in_string(string: &str) -> String {
+    let mut expanded = String::with_capacity(string.len());
+    let mut rest = string;
+
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let reference = &rest[start + 2..];
+
+        match reference
+            .find('}')
+            .map(|end| (&reference[..end], &reference[end + 1..]))
+        {
+            Some((name, after)) if is_env_var_name(name) => {
+                match env::var(name) {
+                    Ok(value) => {
+                        debug!("Expanded ${{{}}} in the configuration", name);
+                        expanded.push_str(&value);
+                    }
+                    Err(_) => {
+                        print_warning(format!(
+                            "Environment variable {} is referenced in the configuration but is not set",
+                            name
+                        ));
+                        expanded.push_str(&rest[start..start + 2 + name.len() + 1]);
+                    }
+                }
+                rest = after;
+            }
+            _ => {
+                expanded.push_str("${");
+                rest = reference;
+            }
+        }
+    }
+    expanded.push_str(rest);
+
+    expanded
+}
+
+fn is_env_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 impl ConfigFile {
     fn ensure(base_dirs: &BaseDirs) -> Result<PathBuf> {
         let config_directory = config_directory(base_dirs);
@@ -335,11 +1341,18 @@ impl ConfigFile {
             e
         })?;
 
-        let mut result: Self = toml::from_str(&contents).map_err(|e| {
+        let mut value: toml::Value = toml::from_str(&contents).map_err(|e| {
             log::error!("Failed to deserialize {}", config_path.display());
             e
         })?;
 
+        expand_env_vars_in_config(&mut value);
+
+        let mut result: Self = value.try_into().map_err(|e| {
+            log::error!("Failed to deserialize {}", config_path.display());
+            explain_config_error(e)
+        })?;
+
         if let Some(ref mut paths) = &mut result.git_repos {
             for path in paths.iter_mut() {
                 let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
@@ -356,6 +1369,64 @@ impl ConfigFile {
             }
         }
 
+        if let Some(paths) = result.git.as_mut().and_then(|git| git.autostash_repos.as_mut()) {
+            for path in paths.iter_mut() {
+                let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
+                debug!("Path {} expanded to {}", path, expanded);
+                *path = expanded;
+            }
+        }
+
+        if let Some(strategies) = result.git.as_mut().and_then(|git| git.pull_strategies.as_mut()) {
+            *strategies = strategies
+                .drain()
+                .map(|(path, strategy)| (shellexpand::tilde::<&str>(&path.as_ref()).into_owned(), strategy))
+                .collect();
+        }
+
+        if let Some(commands) = &result.commands {
+            check_command_dependencies(commands)
+                .inspect_err(|_| log::error!("Invalid custom commands in {}", config_path.display()))?;
+        }
+
+        if let Some(paths) = result.cargo_projects.as_mut() {
+            for path in paths.iter_mut() {
+                let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
+                debug!("Path {} expanded to {}", path, expanded);
+                *path = expanded;
+            }
+        }
+
+        if let Some(paths) = result.appimage_paths.as_mut() {
+            for path in paths.iter_mut() {
+                let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
+                debug!("Path {} expanded to {}", path, expanded);
+                *path = expanded;
+            }
+        }
+
+        if let Some(backup) = result.backup.as_mut() {
+            let files = vec![backup.password_file.as_mut(), backup.config.as_mut()];
+            for path in backup.paths.iter_mut().flatten().chain(files.into_iter().flatten()) {
+                let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
+                debug!("Path {} expanded to {}", path, expanded);
+                *path = expanded;
+            }
+        }
+
+        for path in vec![
+            result.report_file.as_mut(),
+            result.metrics_file.as_mut(),
+            result.remote_deploy_cache.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let expanded = shellexpand::tilde::<&str>(&path.as_ref()).into_owned();
+            debug!("Path {} expanded to {}", path, expanded);
+            *path = expanded;
+        }
+
         debug!("Loaded configuration: {:?}", result);
 
         Ok(result)
@@ -378,9 +1449,23 @@ impl ConfigFile {
     }
 }
 
+/// Commands run instead of the steps
+#[derive(Subcommand, Debug, Clone)]
+pub enum CliCommand {
+    /// Show the previous runs and the steps which kept failing
+    History {
+        /// Number of runs to show
+        #[clap(long = "count", default_value = "10")]
+        count: usize,
+    },
+
+    /// Keep running the steps on the schedule of the [schedule] section of the configuration
+    Daemon,
+}
+
 // Command line arguments
-#[derive(Parser, Debug)]
-#[clap(name = "Topgrade", version)]
+#[derive(Parser, Debug, Clone)]
+#[clap(name = "Topgrade", version, subcommand_precedence_over_arg = true)]
 pub struct CommandLineArgs {
     /// Edit the configuration file
     #[clap(long = "edit-config")]
@@ -414,7 +1499,7 @@ pub struct CommandLineArgs {
     #[clap(long = "only", arg_enum, multiple_values = true)]
     only: Vec<Step>,
 
-    /// Run only specific custom commands
+    /// Run only specific custom commands and plugins
     #[clap(long = "custom-commands")]
     custom_commands: Vec<String>,
 
@@ -453,9 +1538,88 @@ pub struct CommandLineArgs {
     /// Show the reason for skipped steps
     #[clap(long = "show-skipped")]
     show_skipped: bool,
+
+    /// Skip steps that succeeded within the given duration (e.g. "12h")
+    #[clap(long = "min-step-interval", parse(try_from_str = humantime::parse_duration))]
+    min_step_interval: Option<Duration>,
+
+    /// Print the steps that would run and exit without running them
+    #[clap(long = "print-steps")]
+    print_steps: bool,
+
+    /// List every step, whether it's enabled and whether its tools are installed, and exit without running
+    /// anything
+    #[clap(long = "list-steps")]
+    list_steps: bool,
+
+    /// Stay resident and run the steps every given interval (e.g. "6h")
+    #[clap(long = "watch", parse(try_from_str = humantime::parse_duration))]
+    watch: Option<Duration>,
+
+    /// Print the summaries of the last N runs (default: 1) and exit
+    #[clap(long = "last", value_name = "N")]
+    last: Option<Option<usize>>,
+
+    /// Wait for another running topgrade to finish instead of exiting
+    #[clap(long = "wait-for-lock")]
+    wait_for_lock: bool,
+
+    /// Skip the steps which need the network
+    #[clap(long = "offline")]
+    offline: bool,
+
+    /// Skip the steps which run commands as root
+    #[clap(long = "no-sudo")]
+    no_sudo: bool,
+
+    /// Abort when running on battery power
+    #[clap(long = "require-ac")]
+    require_ac: bool,
+
+    /// Only show the output of the failing steps, and the summary. Steps can't prompt for input
+    #[clap(long = "errors-only")]
+    errors_only: bool,
+
+    /// Width of the summary instead of the width of the terminal
+    #[clap(long = "output-width")]
+    output_width: Option<u16>,
+
+    /// Write a machine readable report of the run, to stdout unless --report-file is given
+    #[clap(long = "report", arg_enum)]
+    report: Option<ReportFormat>,
+
+    /// File to write the report of --report to
+    #[clap(long = "report-file")]
+    report_file: Option<PathBuf>,
+
+    /// Write the results of the run in the Prometheus text format to this file, for the textfile collector
+    #[clap(long = "metrics-file")]
+    metrics_file: Option<PathBuf>,
+
+    /// Show the steps with their status next to the output of the running step. Steps can't prompt for input
+    #[cfg(feature = "tui")]
+    #[clap(long = "tui")]
+    tui: bool,
+
+    /// Continue the last run which failed or was interrupted, skipping the steps which succeeded in it
+    #[clap(long = "resume")]
+    resume: bool,
+
+    #[clap(subcommand)]
+    command: Option<CliCommand>,
 }
 
 impl CommandLineArgs {
+    /// Number of previous runs to print instead of running the steps
+    pub fn last(&self) -> Option<usize> {
+        self.last.map(|count| count.unwrap_or(1))
+    }
+
+    /// The command to run instead of the steps
+    pub fn command(&self) -> Option<&CliCommand> {
+        self.command.as_ref()
+    }
+
     pub fn edit_config(&self) -> bool {
         self.edit_config
     }
@@ -467,6 +1631,11 @@ impl CommandLineArgs {
     pub fn env_variables(&self) -> &Vec<String> {
         &self.env
     }
+
+    /// Interval between runs when staying resident
+    pub fn watch(&self) -> Option<Duration> {
+        self.watch
+    }
 }
 
 /// Represents the application configuration
@@ -478,6 +1647,51 @@ pub struct Config {
     opt: CommandLineArgs,
     config_file: ConfigFile,
     allowed_steps: Vec<Step>,
+    host_disabled: Vec<Step>,
+}
+
+/// Whether both paths lead to the same file, when the configuration and the step spell it differently
+fn same_path(path: &Path, other: &Path) -> bool {
+    path == other
+        || path
+            .canonicalize()
+            .is_ok_and(|path| other.canonicalize().is_ok_and(|other| path == other))
+}
+
+/// Parse a size like "200M" or "2 GiB" of `min_free_space` into bytes. The units are binary
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.trim().parse().ok()?;
+
+    let shift = match unit.to_ascii_uppercase().trim_end_matches('B').trim_end_matches('I') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return None,
+    };
+    Some((number * (1u64 << shift) as f64) as u64)
+}
+
+/// The steps disabled and enabled by the `host_overrides` whose glob pattern matches `hostname`
+fn host_override_steps(config_file: &ConfigFile, hostname: &str) -> (Vec<Step>, Vec<Step>) {
+    let mut disabled = Vec::new();
+    let mut enabled = Vec::new();
+    for (pattern, host_override) in config_file.host_overrides.iter().flatten() {
+        match glob::Pattern::new(pattern) {
+            Ok(glob) if glob.matches(hostname) => {
+                debug!("Applying the host overrides of {}", pattern);
+                disabled.extend(host_override.disable.iter().flatten());
+                enabled.extend(host_override.enable.iter().flatten());
+            }
+            Ok(_) => (),
+            Err(e) => log::error!("Invalid host pattern {}: {}", pattern, e),
+        }
+    }
+    (disabled, enabled)
 }
 
 impl Config {
@@ -485,32 +1699,71 @@ impl Config {
     ///
     /// The function parses the command line arguments and reading the configuration file.
     pub fn load(base_dirs: &BaseDirs, opt: CommandLineArgs) -> Result<Self> {
+        let config_file = Self::read_config_file(base_dirs, &opt).unwrap_or_else(|e| {
+            // Inform the user about errors when loading the configuration,
+            // but fallback to the default config to at least attempt to do something
+            log::error!("failed to load configuration: {}", e);
+            ConfigFile::default()
+        });
+        check_timeouts(&config_file)?;
+
+        Ok(Self::new(opt, config_file))
+    }
+
+    /// Load the configuration again, keeping the current one if the configuration file fails to load
+    pub fn reload(self, base_dirs: &BaseDirs, opt: CommandLineArgs) -> Self {
+        match Self::read_config_file(base_dirs, &opt)
+            .and_then(|config_file| check_timeouts(&config_file).map(|()| config_file))
+        {
+            Ok(config_file) => Self::new(opt, config_file),
+            Err(e) => {
+                log::error!("failed to reload configuration, keeping the previous one: {}", e);
+                self
+            }
+        }
+    }
+
+    fn read_config_file(base_dirs: &BaseDirs, opt: &CommandLineArgs) -> Result<ConfigFile> {
         let config_directory = config_directory(base_dirs);
-        let config_file = if config_directory.is_dir() {
-            ConfigFile::read(base_dirs, opt.config.clone()).unwrap_or_else(|e| {
-                // Inform the user about errors when loading the configuration,
-                // but fallback to the default config to at least attempt to do something
-                log::error!("failed to load configuration: {}", e);
-                ConfigFile::default()
-            })
+        if config_directory.is_dir() {
+            ConfigFile::read(base_dirs, opt.config.clone())
         } else {
             log::debug!("Configuration directory {} does not exist", config_directory.display());
-            ConfigFile::default()
-        };
+            Ok(ConfigFile::default())
+        }
+    }
 
+    fn new(opt: CommandLineArgs, config_file: ConfigFile) -> Self {
         check_deprecated!(config_file, git_arguments, git, arguments);
         check_deprecated!(config_file, git_repos, git, repos);
         check_deprecated!(config_file, predefined_git_repos, git, pull_predefined);
         check_deprecated!(config_file, yay_arguments, linux, yay_arguments);
         check_deprecated!(config_file, accept_all_windows_updates, windows, accept_all_updates);
 
-        let allowed_steps = Self::allowed_steps(&opt, &config_file);
+        let (host_disabled, host_enabled) = match hostname() {
+            Ok(hostname) => host_override_steps(&config_file, &hostname),
+            Err(e) => {
+                if config_file.host_overrides.is_some() {
+                    log::error!("Failed to get the hostname, ignoring host_overrides: {}", e);
+                }
+                (Vec::new(), Vec::new())
+            }
+        };
+        let allowed_steps = Self::allowed_steps(&opt, &config_file, &host_disabled, &host_enabled);
 
-        Ok(Self {
+        let config = Self {
             opt,
             config_file,
             allowed_steps,
-        })
+            host_disabled,
+        };
+
+        // The output is only captured when it's held back until a step fails
+        if config.report_email().is_some_and(|email| email.output()) && !config.errors_only() {
+            print_warning("The email won't have the output of the failed steps: it's only kept with --errors-only or in topgrade daemon");
+        }
+
+        config
     }
 
     /// Launch an editor to edit the configuration
@@ -528,6 +1781,11 @@ impl Config {
         &self.config_file.post_commands
     }
 
+    /// Binaries that update themselves, with the arguments running the update
+    pub fn self_update_binaries(&self) -> Option<&BTreeMap<String, Vec<String>>> {
+        self.config_file.self_update_binaries.as_ref()
+    }
+
     /// The list of custom steps.
     pub fn commands(&self) -> &Option<Commands> {
         &self.config_file.commands
@@ -546,7 +1804,33 @@ impl Config {
         self.allowed_steps.contains(&step)
     }
 
-    fn allowed_steps(opt: &CommandLineArgs, config_file: &ConfigFile) -> Vec<Step> {
+    /// Tell why the specified step won't run, if it won't
+    pub fn disabled_reason(&self, step: Step) -> Option<&'static str> {
+        if self.should_run(step) {
+            None
+        } else if self.opt.disable.contains(&step)
+            || self
+                .config_file
+                .disable
+                .as_ref()
+                .map(|disabled| disabled.contains(&step))
+                .unwrap_or(false)
+        {
+            Some("disabled")
+        } else if self.host_disabled.contains(&step) {
+            Some("disabled on this host")
+        } else {
+            Some("not selected by only")
+        }
+    }
+
+    /// The steps to run. `host_enabled` steps are only enabled again when they're disabled, not added to `only`
+    fn allowed_steps(
+        opt: &CommandLineArgs,
+        config_file: &ConfigFile,
+        host_disabled: &[Step],
+        host_enabled: &[Step],
+    ) -> Vec<Step> {
         let mut enabled_steps: Vec<Step> = Vec::new();
         enabled_steps.extend(&opt.only);
 
@@ -559,10 +1843,13 @@ impl Config {
         }
 
         let mut disabled_steps: Vec<Step> = Vec::new();
-        disabled_steps.extend(&opt.disable);
         if let Some(disabled) = config_file.disable.as_ref() {
             disabled_steps.extend(disabled);
         }
+        disabled_steps.extend(host_disabled);
+        // The steps enabled for this host only override the configuration, never --disable
+        disabled_steps.retain(|step| !host_enabled.contains(step));
+        disabled_steps.extend(&opt.disable);
 
         enabled_steps.retain(|e| !disabled_steps.contains(e) || opt.only.contains(e));
         enabled_steps
@@ -580,12 +1867,25 @@ impl Config {
 
     /// Tell whether we are dry-running.
     pub fn dry_run(&self) -> bool {
-        self.opt.dry_run
+        // The steps run dry to detect their tools
+        self.opt.dry_run || self.opt.list_steps
+    }
+
+    /// Skip steps that succeeded more recently than this interval.
+    pub fn min_step_interval(&self) -> Option<Duration> {
+        self.opt.min_step_interval.or_else(|| {
+            self.config_file.min_step_interval.as_ref().and_then(|interval| {
+                humantime::parse_duration(interval)
+                    .map_err(|e| log::error!("Invalid min_step_interval {}: {}", interval, e))
+                    .ok()
+            })
+        })
     }
 
     /// Tell whether we should not attempt to retry anything.
     pub fn no_retry(&self) -> bool {
-        self.opt.no_retry || self.config_file.no_retry.unwrap_or(false)
+        // Nobody is there to answer in daemon mode, and the TUI can't ask
+        self.opt.no_retry || self.daemon() || self.tui() || self.config_file.no_retry.unwrap_or(false)
     }
 
     /// List of remote hosts to run Topgrade in
@@ -593,16 +1893,58 @@ impl Config {
         &self.config_file.remote_topgrades
     }
 
+    /// How many remote hosts are upgraded at the same time
+    pub fn remote_concurrency(&self) -> usize {
+        self.config_file.remote_concurrency.unwrap_or(1)
+    }
+
+    /// Whether to upload topgrade to the remote hosts which don't have it
+    pub fn remote_deploy(&self) -> bool {
+        self.config_file.remote_deploy.unwrap_or(false)
+    }
+
+    /// The directory of the topgrade binaries uploaded to the remote hosts, instead of the cache directory
+    pub fn remote_deploy_cache(&self) -> Option<&str> {
+        self.config_file.remote_deploy_cache.as_deref()
+    }
+
     /// Path to Topgrade executable used for all remote hosts
     pub fn remote_topgrade_path(&self) -> &str {
         self.config_file.remote_topgrade_path.as_deref().unwrap_or("topgrade")
     }
 
+    /// Whether the remote host runs Windows, where topgrade runs in PowerShell
+    pub fn remote_windows(&self, hostname: &str) -> bool {
+        self.config_file
+            .remote_windows_hosts
+            .iter()
+            .flatten()
+            .any(|host| host == hostname)
+    }
+
+    /// The path of topgrade on the remote host, which is `topgrade.exe` on Windows by default
+    pub fn remote_topgrade_path_of(&self, hostname: &str) -> &str {
+        match &self.config_file.remote_topgrade_path {
+            Some(path) => path,
+            None if self.remote_windows(hostname) => "topgrade.exe",
+            None => "topgrade",
+        }
+    }
+
     /// Extra SSH arguments
     pub fn ssh_arguments(&self) -> &Option<String> {
         &self.config_file.ssh_arguments
     }
 
+    /// The overrides of the invocation of topgrade on the remote host
+    pub fn remote_host(&self, hostname: &str) -> Option<&RemoteHost> {
+        self.config_file
+            .remote
+            .as_ref()
+            .and_then(|remote| remote.hosts.as_ref())
+            .and_then(|hosts| hosts.get(hostname))
+    }
+
     /// Extra Git arguments
     pub fn git_arguments(&self) -> &Option<String> {
         get_deprecated!(self.config_file, git_arguments, git, arguments)
@@ -621,6 +1963,10 @@ impl Config {
 
     /// Skip sending a notification at the end of a run
     pub fn skip_notify(&self) -> bool {
+        if self.daemon() {
+            return !self.schedule_option(|schedule| schedule.notify).unwrap_or(true);
+        }
+
         if let Some(yes) = self.config_file.skip_notify {
             return yes;
         }
@@ -628,6 +1974,53 @@ impl Config {
         self.opt.skip_notify
     }
 
+    fn notifications_option<'s, T>(&'s self, option: impl Fn(&'s Notifications) -> Option<T>) -> Option<T> {
+        self.config_file.notifications.as_ref().and_then(option)
+    }
+
+    /// The URL to post a summary of each run to
+    pub fn notification_webhook(&self) -> Option<&str> {
+        self.notifications_option(|notifications| notifications.webhook.as_deref())
+    }
+
+    /// The message sent to ntfy, Gotify and Telegram, see `notifications::render`
+    pub fn notification_template(&self) -> Option<&str> {
+        self.notifications_option(|notifications| notifications.template.as_deref())
+    }
+
+    /// Only send the message to ntfy, Gotify and Telegram when the run failed
+    pub fn notify_only_failures(&self) -> bool {
+        self.notifications_option(|notifications| notifications.only_failures)
+            .unwrap_or(false)
+    }
+
+    pub fn ntfy(&self) -> Option<&Ntfy> {
+        self.notifications_option(|notifications| notifications.ntfy.as_ref())
+    }
+
+    pub fn gotify(&self) -> Option<&Gotify> {
+        self.notifications_option(|notifications| notifications.gotify.as_ref())
+    }
+
+    pub fn telegram(&self) -> Option<&Telegram> {
+        self.notifications_option(|notifications| notifications.telegram.as_ref())
+    }
+
+    /// The tool running commands as root, instead of the first one of doas, sudo, gsudo, run0 and pkexec
+    pub fn sudo_command(&self) -> Option<&str> {
+        self.config_file.sudo_command.as_deref()
+    }
+
+    /// Whether to refresh the sudo credentials in the background, so the password is only asked once
+    pub fn sudo_keep_alive(&self) -> bool {
+        self.config_file.sudo_keep_alive.unwrap_or(false)
+    }
+
+    /// Whether the steps which run commands as root are skipped
+    pub fn no_sudo(&self) -> bool {
+        self.opt.no_sudo || self.config_file.no_sudo.unwrap_or(false)
+    }
+
     /// Whether to set the terminal title
     pub fn set_title(&self) -> bool {
         self.config_file.set_title.unwrap_or(true)
@@ -666,30 +2059,149 @@ impl Config {
         .unwrap_or(true)
     }
 
-    /// Whether to self rename the Topgrade executable during the run
-    pub fn self_rename(&self) -> bool {
+    /// Whether to self rename the Topgrade executable during the run
+    pub fn self_rename(&self) -> bool {
+        self.config_file
+            .windows
+            .as_ref()
+            .and_then(|w| w.self_rename)
+            .unwrap_or(false)
+    }
+
+    /// Whether Brew cask should be greedy
+    pub fn brew_cask_greedy(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.greedy_cask)
+            .unwrap_or(false)
+    }
+
+    /// Whether casks should be upgraded without the quarantine attribute
+    pub fn brew_cask_no_quarantine(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.no_quarantine_cask)
+            .unwrap_or(false)
+    }
+
+    /// Whether to opt out of Homebrew's analytics while upgrading
+    pub fn brew_no_analytics(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.no_analytics)
+            .unwrap_or(false)
+    }
+
+    /// Whether to download the bottles of all outdated formulae before upgrading them
+    pub fn brew_prefetch(&self) -> bool {
+        self.config_file.brew.as_ref().and_then(|c| c.prefetch).unwrap_or(false)
+    }
+
+    /// Whether to overwrite the files which keep upgraded formulae from being linked
+    pub fn brew_overwrite_link_conflicts(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.overwrite_link_conflicts)
+            .unwrap_or(false)
+    }
+
+    /// Whether to skip the formula and cask upgrades when `brew outdated` lists nothing
+    pub fn brew_skip_if_nothing_outdated(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.skip_if_nothing_outdated)
+            .unwrap_or(false)
+    }
+
+    /// Whether to keep the versions of the upgraded formulae in the step records of the run
+    pub fn brew_json_report(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.json_report)
+            .unwrap_or(false)
+    }
+
+    /// Whether to upgrade the formulae of the ARM and Intel brews at the same time
+    pub fn brew_parallel_variants(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.parallel_variants)
+            .unwrap_or(false)
+    }
+
+    /// Whether Brew should autoremove
+    pub fn brew_autoremove(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.autoremove)
+            .unwrap_or(false)
+    }
+
+    /// Whether to restart started Brew services after upgrading formulae
+    pub fn brew_restart_services(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.restart_services)
+            .unwrap_or(false)
+    }
+
+    /// Restrict the restarted Brew services to this list
+    pub fn brew_services(&self) -> Option<&Vec<String>> {
+        self.config_file.brew.as_ref().and_then(|c| c.services.as_ref())
+    }
+
+    /// Whether to upgrade outdated formulae one at a time so that a failing one doesn't stop the others
+    pub fn brew_isolate_failures(&self) -> bool {
         self.config_file
-            .windows
+            .brew
             .as_ref()
-            .and_then(|w| w.self_rename)
+            .and_then(|c| c.isolate_failures)
             .unwrap_or(false)
     }
 
-    /// Whether Brew cask should be greedy
-    pub fn brew_cask_greedy(&self) -> bool {
+    /// Whether to rebuild the formulae installed with `--HEAD` with `brew upgrade --fetch-HEAD`
+    pub fn brew_fetch_head(&self) -> bool {
         self.config_file
             .brew
             .as_ref()
-            .and_then(|c| c.greedy_cask)
+            .and_then(|c| c.fetch_head)
             .unwrap_or(false)
     }
 
-    /// Whether Brew should autoremove
-    pub fn brew_autoremove(&self) -> bool {
+    /// Formulae to run `brew link --overwrite` for after upgrading
+    pub fn brew_relink(&self) -> Option<&Vec<String>> {
+        self.config_file.brew.as_ref().and_then(|c| c.relink.as_ref())
+    }
+
+    /// Whether to run `brew doctor` after upgrading formulae
+    pub fn brew_doctor(&self) -> bool {
+        self.config_file.brew.as_ref().and_then(|c| c.doctor).unwrap_or(false)
+    }
+
+    /// Whether to list the pinned formulae after upgrading them
+    pub fn brew_report_pinned(&self) -> bool {
         self.config_file
             .brew
             .as_ref()
-            .and_then(|c| c.autoremove)
+            .and_then(|c| c.report_pinned)
+            .unwrap_or(false)
+    }
+
+    /// Whether to report which taps changed when updating Brew
+    pub fn brew_update_taps(&self) -> bool {
+        self.config_file
+            .brew
+            .as_ref()
+            .and_then(|c| c.update_taps)
             .unwrap_or(false)
     }
 
@@ -753,6 +2265,15 @@ impl Config {
             .unwrap_or(true)
     }
 
+    /// Remove orphaned packages on Arch Linux during cleanup
+    pub fn arch_remove_orphans(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|s| s.arch_remove_orphans)
+            .unwrap_or(false)
+    }
+
     /// Get the package manager of an Arch Linux system
     pub fn arch_package_manager(&self) -> ArchPackageManager {
         self.config_file
@@ -785,9 +2306,45 @@ impl Config {
             .and_then(|linux| linux.dnf_arguments.as_deref())
     }
 
-    /// Concurrency limit for git
+    /// Concurrency limit for git, `git_repos_concurrency` or the `max_concurrency` of `[git]`
     pub fn git_concurrency_limit(&self) -> Option<usize> {
-        self.config_file.git.as_ref().and_then(|git| git.max_concurrency)
+        self.config_file
+            .git_repos_concurrency
+            .or_else(|| self.config_file.git.as_ref().and_then(|git| git.max_concurrency))
+            .map(|limit| limit.max(1))
+    }
+
+    /// Whether to also pull the linked worktrees of the git repositories
+    pub fn git_update_worktrees(&self) -> bool {
+        self.config_file
+            .git
+            .as_ref()
+            .and_then(|git| git.update_worktrees)
+            .unwrap_or(false)
+    }
+
+    /// How to pull the given repository (default: ff-only)
+    pub fn git_pull_strategy(&self, repo: &str) -> GitPullStrategy {
+        let git = self.config_file.git.as_ref();
+        let repo_path = Path::new(repo);
+        git.and_then(|git| git.pull_strategies.as_ref())
+            .and_then(|strategies| {
+                strategies
+                    .iter()
+                    .find_map(|(path, strategy)| same_path(Path::new(path), repo_path).then_some(*strategy))
+            })
+            .or_else(|| git.and_then(|git| git.pull_strategy))
+            .unwrap_or(GitPullStrategy::FfOnly)
+    }
+
+    /// Whether to stash the local changes of the given repository before pulling it, and pop them after
+    pub fn git_autostash(&self, repo: &str) -> bool {
+        let repo_path = Path::new(repo);
+        self.config_file
+            .git
+            .as_ref()
+            .and_then(|git| git.autostash_repos.as_ref())
+            .is_some_and(|repos| repos.iter().any(|path| same_path(Path::new(path), repo_path)))
     }
 
     /// Should we power on vagrant boxes if needed
@@ -829,6 +2386,24 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Only apply security updates in Red Hat based distributions
+    pub fn dnf_security_only(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.dnf_security_only)
+            .unwrap_or(false)
+    }
+
+    /// Use zypper dist-upgrade instead of zypper update in openSUSE
+    pub fn zypper_dist_upgrade(&self) -> bool {
+        self.config_file
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.zypper_dist_upgrade)
+            .unwrap_or(false)
+    }
+
     /// Use rpm-ostree in *when rpm-ostree is detected* (default: true)
     pub fn rpm_ostree(&self) -> bool {
         self.config_file
@@ -847,6 +2422,66 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// What to do when this step fails
+    pub fn failure_policy(&self, step: Step) -> FailurePolicy {
+        self.config_file
+            .step_failure_policy
+            .as_ref()
+            .and_then(|policies| policies.get(&step).copied())
+            .unwrap_or(if step == Step::Backup {
+                // Upgrading without a backup is what the backup step is there to prevent
+                FailurePolicy::Abort
+            } else {
+                FailurePolicy::Continue
+            })
+    }
+
+    /// How many times the given step is retried automatically when it fails
+    pub fn step_retries(&self, step: Step) -> u32 {
+        self.config_file
+            .step_retries
+            .as_ref()
+            .and_then(|retries| retries.get(&step))
+            .and_then(|retries| retries.retries)
+            .unwrap_or(0)
+    }
+
+    /// How long to wait before retrying the given step automatically (default: 10s)
+    pub fn step_retry_delay(&self, step: Step) -> Duration {
+        self.config_file
+            .step_retries
+            .as_ref()
+            .and_then(|retries| retries.get(&step))
+            .and_then(|retries| retries.retry_delay.as_ref())
+            .and_then(|delay| {
+                humantime::parse_duration(delay)
+                    .map_err(|e| log::error!("Invalid retry_delay {} of {:?}: {}", delay, step, e))
+                    .ok()
+            })
+            .unwrap_or(Duration::from_secs(10))
+    }
+
+    /// How long the given step may run before its commands are killed
+    pub fn step_timeout(&self, step: Step) -> Option<Duration> {
+        self.config_file
+            .timeouts
+            .as_ref()
+            .and_then(|timeouts| timeouts.get(&step))
+            .and_then(|timeout| {
+                humantime::parse_duration(timeout)
+                    .map_err(|e| log::error!("Invalid timeout {} of {:?}: {}", timeout, step, e))
+                    .ok()
+            })
+    }
+
+    /// The command to run after the step succeeded, such as one patching the app it updated
+    pub fn post_app_update(&self, step: Step) -> Option<&CustomCommand> {
+        self.config_file
+            .post_app_update
+            .as_ref()
+            .and_then(|commands| commands.get(&step))
+    }
+
     pub fn use_predefined_git_repos(&self) -> bool {
         !self.opt.disable_predefined_git_repos
             && get_deprecated!(self.config_file, predefined_git_repos, git, pull_predefined).unwrap_or(true)
@@ -856,8 +2491,320 @@ impl Config {
         self.opt.verbose
     }
 
-    pub fn show_skipped(&self) -> bool {
-        self.opt.show_skipped
+    /// Which skipped steps to show in the summary
+    pub fn summary_skip_display(&self) -> SummarySkipDisplay {
+        if self.opt.show_skipped {
+            return SummarySkipDisplay::All;
+        }
+
+        self.config_file.summary_skip_display.unwrap_or(if self.opt.verbose {
+            SummarySkipDisplay::All
+        } else {
+            SummarySkipDisplay::None
+        })
+    }
+
+    /// Whether to only print the steps that would run
+    pub fn print_steps(&self) -> bool {
+        self.opt.print_steps || self.opt.list_steps
+    }
+
+    /// Whether `--list-steps` lists every step with the detection of its tools, like `--print-steps`
+    pub fn list_steps(&self) -> bool {
+        self.opt.list_steps
+    }
+
+    /// Whether to show the progress of the run with `--tui`
+    pub fn tui(&self) -> bool {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "tui")] {
+                self.opt.tui
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Whether to skip the steps which succeeded in the last unfinished run
+    pub fn resume(&self) -> bool {
+        self.opt.resume
+    }
+
+    /// Whether to wait for another running topgrade to finish
+    pub fn wait_for_lock(&self) -> bool {
+        self.opt.wait_for_lock
+    }
+
+    /// Whether offline mode was requested on the command line
+    pub fn offline(&self) -> bool {
+        self.opt.offline
+    }
+
+    /// Whether to hold back the output of the steps and show it only when they fail
+    pub fn errors_only(&self) -> bool {
+        self.opt.errors_only || (self.daemon() && self.schedule_option(|schedule| schedule.quiet).unwrap_or(true))
+    }
+
+    /// Whether the steps run with `topgrade daemon`
+    fn daemon(&self) -> bool {
+        matches!(self.opt.command, Some(CliCommand::Daemon))
+    }
+
+    fn schedule_option<'s, T>(&'s self, option: impl Fn(&'s Schedule) -> Option<T>) -> Option<T> {
+        self.config_file.schedule.as_ref().and_then(option)
+    }
+
+    /// When `topgrade daemon` runs the steps
+    pub fn schedule(&self) -> Result<schedule::Schedule> {
+        match (
+            self.schedule_option(|schedule| schedule.interval.as_ref()),
+            self.schedule_option(|schedule| schedule.cron.as_ref()),
+        ) {
+            (Some(interval), None) => Ok(schedule::Schedule::Interval(
+                humantime::parse_duration(interval)
+                    .map_err(|e| anyhow!("Invalid schedule interval {}: {}", interval, e))?,
+            )),
+            (None, Some(cron)) => Ok(schedule::Schedule::Cron(schedule::Cron::parse(cron)?)),
+            (Some(_), Some(_)) => Err(anyhow!("Only one of interval and cron can be set in [schedule]")),
+            (None, None) => Err(anyhow!(
+                "Set the interval or cron of the [schedule] section to run as a daemon"
+            )),
+        }
+    }
+
+    /// Whether to check the connectivity at startup and go offline without it
+    pub fn auto_offline(&self) -> bool {
+        self.config_file.auto_offline.unwrap_or(false)
+    }
+
+    /// The endpoints probed by `auto_offline`, instead of the mirrors of the enabled steps
+    pub fn connectivity_probes(&self) -> Option<&[String]> {
+        self.config_file.connectivity_probes.as_deref()
+    }
+
+    /// How long `auto_offline` waits for the network before skipping the steps which need it
+    pub fn connectivity_wait(&self) -> Duration {
+        self.config_file
+            .connectivity_wait
+            .as_ref()
+            .and_then(|wait| {
+                humantime::parse_duration(wait)
+                    .map_err(|e| log::error!("Invalid connectivity_wait {}: {}", wait, e))
+                    .ok()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The free space required on each mount point before the steps, in bytes
+    pub fn min_free_space(&self) -> Vec<(&str, u64)> {
+        self.config_file
+            .min_free_space
+            .iter()
+            .flatten()
+            .filter_map(|(mount_point, size)| match parse_size(size) {
+                Some(bytes) => Some((mount_point.as_str(), bytes)),
+                None => {
+                    log::error!("Invalid size {} of {} in min_free_space", size, mount_point);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The width of the summary requested on the command line
+    pub fn output_width(&self) -> Option<u16> {
+        self.opt.output_width
+    }
+
+    /// Whether to print what steps changed in the summary
+    pub fn show_changes(&self) -> bool {
+        self.config_file.show_changes.unwrap_or(false)
+    }
+
+    /// Go module paths to reinstall with `go install <path>@latest`
+    pub fn go_binaries(&self) -> Option<&Vec<String>> {
+        self.config_file.go_binaries.as_ref()
+    }
+
+    /// IDs of the App Store apps which shouldn't be upgraded
+    pub fn mas_ignore(&self) -> Option<&Vec<u64>> {
+        self.config_file.mas_ignore.as_ref()
+    }
+
+    /// Whether to install and select the latest version of every asdf plugin
+    pub fn asdf_install_latest(&self) -> bool {
+        self.config_file.asdf_install_latest.unwrap_or(false)
+    }
+
+    /// Whether to install the latest patch release of each installed Python series with pyenv
+    pub fn pyenv_install_latest_patch(&self) -> bool {
+        self.config_file.pyenv_install_latest_patch.unwrap_or(false)
+    }
+
+    /// How many of the package manager, language, shell and tool steps run at the same time (default: 1)
+    pub fn max_concurrency(&self) -> usize {
+        self.config_file.max_concurrency.unwrap_or(1)
+    }
+
+    /// AppImage files, or directories containing them, to update with appimageupdatetool
+    pub fn appimage_paths(&self) -> Option<&Vec<String>> {
+        self.config_file.appimage_paths.as_ref()
+    }
+
+    /// The ROS distribution whose setup is sourced before building the workspaces
+    pub fn ros_distro(&self) -> Option<&str> {
+        self.config_file.ros_distro.as_deref()
+    }
+
+    /// ROS workspaces to build, with the tool building each of them
+    pub fn ros_workspaces(&self) -> Option<&BTreeMap<String, RosBuildTool>> {
+        self.config_file.ros_workspaces.as_ref()
+    }
+
+    /// Local Cargo projects whose lockfiles should be refreshed
+    pub fn cargo_projects(&self) -> Option<&Vec<String>> {
+        self.config_file.cargo_projects.as_ref()
+    }
+
+    /// Whether to audit the installed crates with cargo-audit
+    pub fn cargo_audit(&self) -> bool {
+        self.config_file.cargo_audit.unwrap_or(false)
+    }
+
+    /// Whether to delete old Nix generations during cleanup
+    pub fn nix_gc(&self) -> bool {
+        self.config_file.nix_gc.unwrap_or(false)
+    }
+
+    /// Whether to skip SDKMAN! when its servers can't be reached
+    pub fn sdkman_offline_check(&self) -> bool {
+        self.config_file.sdkman_offline_check.unwrap_or(true)
+    }
+
+    /// Format of the report of the run, if one should be written
+    pub fn report_format(&self) -> Option<ReportFormat> {
+        self.opt.report.or(match &self.config_file.report {
+            Some(ReportSetting::Format(format)) => Some(*format),
+            Some(ReportSetting::Detailed(detailed)) => detailed.format,
+            None => None,
+        })
+    }
+
+    /// The backup to make before the upgrades
+    pub fn backup(&self) -> Option<&Backup> {
+        self.config_file.backup.as_ref()
+    }
+
+    /// The containers in which to run steps, by name
+    pub fn exec_containers(&self) -> Option<&BTreeMap<String, ExecContainer>> {
+        self.config_file
+            .containers
+            .as_ref()
+            .and_then(|containers| containers.exec.as_ref())
+    }
+
+    /// The pods in which to run commands
+    pub fn kubernetes(&self) -> Option<&Kubernetes> {
+        self.config_file.kubernetes.as_ref()
+    }
+
+    /// The snapshot to take before the system update
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        self.config_file.snapshot.as_ref()
+    }
+
+    /// What to do when running on battery power. `--require-ac` aborts
+    pub fn battery_policy(&self) -> ConstraintPolicy {
+        if self.opt.require_ac {
+            return ConstraintPolicy::Abort;
+        }
+
+        self.config_file
+            .power
+            .as_ref()
+            .and_then(|power| power.on_battery)
+            .unwrap_or(ConstraintPolicy::Warn)
+    }
+
+    /// Whether the step is skipped on battery power, it's one of the `heavy_steps` (default: firmware)
+    pub fn skipped_on_battery(&self, step: Step) -> bool {
+        if self.battery_policy() != ConstraintPolicy::SkipHeavy {
+            return false;
+        }
+
+        match self
+            .config_file
+            .power
+            .as_ref()
+            .and_then(|power| power.heavy_steps.as_ref())
+        {
+            Some(steps) => steps.contains(&step),
+            None => step == Step::Firmware,
+        }
+    }
+
+    /// What to do on a metered connection
+    pub fn metered_policy(&self) -> ConstraintPolicy {
+        self.config_file
+            .network
+            .as_ref()
+            .and_then(|network| network.on_metered)
+            .unwrap_or(ConstraintPolicy::Warn)
+    }
+
+    /// Whether the step is skipped on a metered connection, it's one of the `heavy_steps` (default: the ones
+    /// downloading the most)
+    pub fn skipped_on_metered(&self, step: Step) -> bool {
+        if self.metered_policy() != ConstraintPolicy::SkipHeavy {
+            return false;
+        }
+
+        match self
+            .config_file
+            .network
+            .as_ref()
+            .and_then(|network| network.heavy_steps.as_ref())
+        {
+            Some(steps) => steps.contains(&step),
+            None => matches!(
+                step,
+                Step::System | Step::Firmware | Step::Containers | Step::Flatpak | Step::Snap
+            ),
+        }
+    }
+
+    /// The email with the summary sent after each run
+    pub fn report_email(&self) -> Option<&Email> {
+        match &self.config_file.report {
+            Some(ReportSetting::Detailed(detailed)) => detailed.email.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// File to write the report to instead of stdout
+    pub fn report_file(&self) -> Option<PathBuf> {
+        self.opt
+            .report_file
+            .clone()
+            .or_else(|| self.config_file.report_file.as_ref().map(PathBuf::from))
+    }
+
+    /// File to write the Prometheus metrics of the run to
+    pub fn metrics_file(&self) -> Option<PathBuf> {
+        self.opt
+            .metrics_file
+            .clone()
+            .or_else(|| self.config_file.metrics_file.as_ref().map(PathBuf::from))
+    }
+
+    /// Number of runs kept in the history file
+    pub fn history_max_entries(&self) -> usize {
+        self.config_file.history_max_entries.unwrap_or(100)
+    }
+
+    /// Distrobox containers to upgrade instead of all of them
+    pub fn distrobox_containers(&self) -> Option<&Vec<String>> {
+        self.config_file.distrobox_containers.as_ref()
     }
 
     pub fn open_remotes_in_new_terminal(&self) -> bool {
@@ -909,6 +2856,16 @@ impl Config {
     #[cfg(target_os = "linux")]
     str_value!(linux, emerge_update_flags);
 
+    /// Update the ports tree on FreeBSD
+    #[cfg(target_os = "freebsd")]
+    pub fn freebsd_update_ports(&self) -> bool {
+        self.config_file
+            .freebsd
+            .as_ref()
+            .and_then(|freebsd| freebsd.update_ports)
+            .unwrap_or(false)
+    }
+
     pub fn should_execute_remote(&self, remote: &str) -> bool {
         if let Ok(hostname) = hostname() {
             if remote == hostname {
@@ -945,3 +2902,252 @@ impl Config {
         self.opt.custom_commands.iter().any(|s| s == name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_timeouts() {
+        let config_file: ConfigFile = toml::from_str(
+            r#"
+            [commands]
+            "quick" = { command = "true", timeout = "5m" }
+            "#,
+        )
+        .unwrap();
+        assert!(check_timeouts(&config_file).is_ok());
+
+        let config_file: ConfigFile = toml::from_str(
+            r#"
+            [post_commands]
+            "slow" = { command = "sleep 1", timeout = "5 fortnights" }
+            "#,
+        )
+        .unwrap();
+        assert!(check_timeouts(&config_file).is_err());
+    }
+
+    #[test]
+    fn test_example_config() {
+        let config_file: ConfigFile = toml::from_str(EXAMPLE_CONFIG).unwrap();
+        assert!(check_timeouts(&config_file).is_ok());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("200M"), Some(200 << 20));
+        assert_eq!(parse_size("1.5 GiB"), Some(3 << 29));
+        assert_eq!(parse_size("2gb"), Some(2 << 30));
+        assert_eq!(parse_size("2 parsecs"), None);
+        assert_eq!(parse_size("G"), None);
+    }
+
+    fn expand(contents: &str) -> Result<toml::Value> {
+        let mut value: toml::Value = toml::from_str(contents)?;
+        expand_env_vars_in_config(&mut value);
+        Ok(value)
+    }
+
+    #[test]
+    fn test_expand_env_var() {
+        env::set_var("TOPGRADE_TEST_TOKEN", "secret");
+        let value = expand(r#"url = "https://${TOPGRADE_TEST_TOKEN}@github.com/${TOPGRADE_TEST_TOKEN}""#).unwrap();
+        assert_eq!(value["url"].as_str(), Some("https://secret@github.com/secret"));
+    }
+
+    #[test]
+    fn test_expand_unset_env_var() {
+        env::remove_var("TOPGRADE_TEST_UNSET");
+        let value = expand(r#"url = "a ${TOPGRADE_TEST_UNSET} b""#).unwrap();
+        assert_eq!(value["url"].as_str(), Some("a ${TOPGRADE_TEST_UNSET} b"));
+    }
+
+    #[test]
+    fn test_expand_empty_env_var() {
+        env::set_var("TOPGRADE_TEST_EMPTY", "");
+        let value = expand(r#"url = "a${TOPGRADE_TEST_EMPTY}b""#).unwrap();
+        assert_eq!(value["url"].as_str(), Some("ab"));
+    }
+
+    #[test]
+    fn test_expand_nested() {
+        env::set_var("TOPGRADE_TEST_NESTED", "repo");
+        env::set_var("TOPGRADE_TEST_OUTER", "${TOPGRADE_TEST_NESTED}");
+        let value = expand(
+            r#"
+            [git]
+            repos = ["~/${TOPGRADE_TEST_NESTED}", "~/${TOPGRADE_TEST_OUTER}"]
+            "#,
+        )
+        .unwrap();
+        let repos = value["git"]["repos"].as_array().unwrap();
+        assert_eq!(repos[0].as_str(), Some("~/repo"));
+        assert_eq!(repos[1].as_str(), Some("~/${TOPGRADE_TEST_NESTED}"));
+    }
+
+    #[test]
+    fn test_expand_ignores_invalid_references() {
+        let value = expand(r#"url = "${} ${1A} ${TOPGRADE TEST} ${unclosed""#).unwrap();
+        assert_eq!(value["url"].as_str(), Some("${} ${1A} ${TOPGRADE TEST} ${unclosed"));
+    }
+
+    #[test]
+    fn test_expand_skips_steps_and_commands() {
+        env::remove_var("TOPGRADE_TEST_UNSET");
+        let value = expand(
+            r#"
+            only = ["${TOPGRADE_TEST_UNSET}"]
+
+            [commands]
+            "Run" = "echo ${TOPGRADE_TEST_UNSET}"
+
+            [post_app_update]
+            brew = "echo ${TOPGRADE_TEST_TOKEN}"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(value["only"][0].as_str(), Some("${TOPGRADE_TEST_UNSET}"));
+        assert_eq!(value["commands"]["Run"].as_str(), Some("echo ${TOPGRADE_TEST_UNSET}"));
+        assert_eq!(
+            value["post_app_update"]["brew"].as_str(),
+            Some("echo ${TOPGRADE_TEST_TOKEN}")
+        );
+    }
+
+    fn commands(contents: &str) -> Commands {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn test_command_dependency_order() {
+        let commands = commands(
+            r#"
+            "a cache" = { command = "rebuild", depends_on = "b sync" }
+            "b sync" = "sync"
+            "c other" = "other"
+            "#,
+        );
+        check_command_dependencies(&commands).unwrap();
+        let names: Vec<&str> = commands_in_dependency_order(&commands)
+            .into_iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["b sync", "a cache", "c other"]);
+    }
+
+    #[test]
+    fn test_command_dependency_cycle() {
+        let commands = commands(
+            r#"
+            a = { command = "a", depends_on = "b" }
+            b = { command = "b", depends_on = "c" }
+            c = { command = "c", depends_on = "a" }
+            "#,
+        );
+        let error = check_command_dependencies(&commands).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cyclic dependency of custom commands: a -> b -> c -> a"
+        );
+    }
+
+    #[test]
+    fn test_command_missing_dependency() {
+        let commands = commands(r#"a = { command = "a", depends_on = "b" }"#);
+        assert!(check_command_dependencies(&commands).is_err());
+    }
+
+    fn config_error(contents: &str) -> String {
+        let value: toml::Value = toml::from_str(contents).unwrap();
+        explain_config_error(value.try_into::<ConfigFile>().unwrap_err()).to_string()
+    }
+
+    #[test]
+    fn test_unknown_key_suggestion() {
+        assert_eq!(
+            config_error("gti_repos = []"),
+            "Unknown key `gti_repos`, did you mean `git_repos`?"
+        );
+        assert_eq!(
+            config_error("[brew]\ngreedy_csk = true"),
+            "Unknown key `greedy_csk` in [brew], did you mean `greedy_cask`?"
+        );
+    }
+
+    #[test]
+    fn test_unknown_value_suggestion() {
+        assert_eq!(
+            config_error("[git]\npull_strategy = \"rebse\""),
+            "Unknown value `rebse` for `git.pull_strategy`, did you mean `rebase`?"
+        );
+        assert_eq!(
+            config_error("disable = [\"brew_formla\"]"),
+            "Unknown value `brew_formla` for `disable`, did you mean `brew_formula`?"
+        );
+    }
+
+    #[test]
+    fn test_unknown_key_without_suggestion() {
+        assert!(config_error("completely_different = 1")
+            .starts_with("unknown field `completely_different`, expected one of"));
+    }
+
+    #[test]
+    fn test_host_override_steps() {
+        let config_file: ConfigFile = toml::from_str(
+            r#"
+[host_overrides."laptop*"]
+enable = ["flatpak"]
+
+[host_overrides."server-*"]
+disable = ["flatpak", "gnome_shell_extensions"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            host_override_steps(&config_file, "server-01"),
+            (vec![Step::Flatpak, Step::GnomeShellExtensions], vec![])
+        );
+        assert_eq!(
+            host_override_steps(&config_file, "laptop"),
+            (vec![], vec![Step::Flatpak])
+        );
+        assert_eq!(host_override_steps(&config_file, "desktop"), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_host_enabled_overrides_config_only() {
+        let config_file: ConfigFile = toml::from_str(r#"disable = ["flatpak"]"#).unwrap();
+
+        let opt = CommandLineArgs::parse_from(["topgrade"]);
+        assert!(Config::allowed_steps(&opt, &config_file, &[], &[Step::Flatpak]).contains(&Step::Flatpak));
+
+        let opt = CommandLineArgs::parse_from(["topgrade", "--disable", "flatpak"]);
+        assert!(!Config::allowed_steps(&opt, &config_file, &[], &[Step::Flatpak]).contains(&Step::Flatpak));
+    }
+
+    #[test]
+    fn test_requires_network() {
+        for step in [
+            Step::System,
+            Step::BrewFormula,
+            Step::GitRepos,
+            Step::Rustup,
+            Step::Remotes,
+        ] {
+            assert!(step.requires_network(), "{:?}", step);
+        }
+        for step in [
+            Step::ConfigUpdate,
+            Step::CustomCommands,
+            Step::Dkms,
+            Step::Restarts,
+            Step::Ros,
+        ] {
+            assert!(!step.requires_network(), "{:?}", step);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! The connectivity check of `auto_offline`, probing the mirrors of the enabled steps before the run
+use std::collections::{BTreeMap, HashSet};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use strum::IntoEnumIterator;
+
+use crate::config::{Config, Step};
+use crate::terminal::print_info;
+use crate::utils::is_reachable;
+
+/// Probed besides the mirrors, for the steps which don't have one
+const DEFAULT_PROBE: &str = "https://github.com";
+
+/// How long each probe waits for its connection
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often the probes are retried while waiting for the network with `connectivity_wait`
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The well-known mirror the step downloads from
+fn mirror(step: Step) -> Option<&'static str> {
+    let mirror = match step {
+        Step::BrewCask | Step::BrewFormula => "https://ghcr.io",
+        Step::Cargo => "https://index.crates.io",
+        Step::Composer => "https://repo.packagist.org",
+        Step::Conda => "https://conda.anaconda.org",
+        Step::Containers => "https://registry-1.docker.io",
+        Step::Dotnet => "https://api.nuget.org",
+        Step::Firmware => "https://fwupd.org",
+        Step::Flatpak => "https://dl.flathub.org",
+        Step::Gem => "https://rubygems.org",
+        Step::GitRepos => "https://github.com",
+        Step::Go => "https://proxy.golang.org",
+        Step::Nix => "https://cache.nixos.org",
+        Step::Node => "https://registry.npmjs.org",
+        Step::Pip3 | Step::Pipx => "https://pypi.org",
+        Step::Rustup => "https://static.rust-lang.org",
+        Step::Snap => "https://api.snapcraft.io",
+        _ => return None,
+    };
+    Some(mirror)
+}
+
+/// The endpoints to probe, with the steps skipped when they are unreachable: the `connectivity_probes`, or
+/// the mirrors of the enabled steps
+fn probes(config: &Config) -> BTreeMap<&str, Vec<Step>> {
+    let mut probes: BTreeMap<&str, Vec<Step>> = BTreeMap::new();
+    match config.connectivity_probes() {
+        Some(endpoints) => probes.extend(endpoints.iter().map(|endpoint| (endpoint.as_str(), Vec::new()))),
+        None => {
+            probes.insert(DEFAULT_PROBE, Vec::new());
+            for step in Step::iter().filter(|step| config.should_run(*step)) {
+                if let Some(mirror) = mirror(step) {
+                    probes.entry(mirror).or_default().push(step);
+                }
+            }
+        }
+    }
+    probes
+}
+
+/// The outcome of the connectivity check
+#[derive(Debug, Default)]
+pub struct Connectivity {
+    /// None of the endpoints is reachable
+    pub offline: bool,
+    /// The steps whose mirror is unreachable while others are
+    pub unreachable: HashSet<Step>,
+}
+
+/// Probe the endpoints at the same time, waiting up to `connectivity_wait` for one of them to be reachable
+pub fn check(config: &Config) -> Connectivity {
+    let probes = probes(config);
+    let deadline = Instant::now() + config.connectivity_wait();
+    let mut waiting = false;
+
+    loop {
+        let probing: Vec<_> = probes
+            .keys()
+            .map(|endpoint| {
+                let endpoint = endpoint.to_string();
+                thread::spawn(move || is_reachable(&endpoint, PROBE_TIMEOUT))
+            })
+            .collect();
+        let reachable: Vec<bool> = probing.into_iter().map(|probe| probe.join().unwrap_or(false)).collect();
+
+        if reachable.iter().any(|reachable| *reachable) {
+            let unreachable = probes
+                .values()
+                .zip(reachable)
+                .filter(|(_, reachable)| !reachable)
+                .flat_map(|(steps, _)| steps.iter().copied())
+                .collect();
+            return Connectivity {
+                offline: false,
+                unreachable,
+            };
+        }
+
+        if Instant::now() + RETRY_INTERVAL > deadline {
+            return Connectivity {
+                offline: true,
+                unreachable: HashSet::new(),
+            };
+        }
+
+        if !waiting {
+            print_info("Waiting for the network...");
+            waiting = true;
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
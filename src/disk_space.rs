@@ -0,0 +1,94 @@
+//! The free space of the mount points of `min_free_space`, checked before the steps
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use log::debug;
+
+use crate::config::Config;
+
+/// The space available to the user on the filesystem of `path`, in bytes
+#[cfg(unix)]
+fn free_space(path: &Path) -> Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(path)?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+#[cfg(windows)]
+fn free_space(path: &Path) -> Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr::null_mut;
+    use winapi::shared::ntdef::ULARGE_INTEGER;
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let succeeded = unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, null_mut(), null_mut()) };
+    if succeeded == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(unsafe { *available.QuadPart() })
+}
+
+/// `bytes` in the largest binary unit under it, like "1.5 GiB"
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+
+    if unit == "B" {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, unit)
+    }
+}
+
+/// Fail when one of the mount points of `min_free_space` has less free space than required, before a
+/// package manager runs out of it in the middle of a transaction. Missing mount points are ignored
+pub fn check(config: &Config) -> Result<()> {
+    let mut missing = Vec::new();
+    for (mount_point, required) in config.min_free_space() {
+        let path = Path::new(mount_point);
+        if !path.exists() {
+            debug!("{} doesn't exist, not checking its free space", mount_point);
+            continue;
+        }
+
+        let free = free_space(path).map_err(|e| anyhow!("Failed to get the free space of {}: {}", mount_point, e))?;
+        debug!("{} free on {}", format_size(free), mount_point);
+        if free < required {
+            missing.push(format!(
+                "only {} free on {}, {} are required",
+                format_size(free),
+                mount_point,
+                format_size(required)
+            ));
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Not enough disk space: {}", missing.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(200 * 1024 * 1024), "200.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024 / 2), "1.5 GiB");
+    }
+}
@@ -1,4 +1,5 @@
 use std::process::ExitStatus;
+use std::time::Duration;
 
 use thiserror::Error;
 
@@ -27,15 +28,137 @@ pub enum TopgradeError {
 #[error("A step failed")]
 pub struct StepFailed;
 
+/// A command which failed, with the last lines of its output for the summary
+#[derive(Error, Debug)]
+#[error("{message}")]
+pub struct FailedWithOutput {
+    pub message: String,
+    pub output: String,
+    /// The exit code of the command, unless it timed out or was killed by a signal
+    pub exit_code: Option<i32>,
+}
+
+/// A step ran longer than its timeout in the `[timeouts]` configuration, and its commands were killed
+#[derive(Error, Debug)]
+#[error("Timed out after {}", humantime::format_duration(*.0))]
+pub struct TimedOut(pub Duration);
+
 #[derive(Error, Debug)]
 #[error("Dry running")]
 pub struct DryRun();
 
+/// Why a step was skipped, with a message for the summary
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The tool the step upgrades, or something it needs, isn't installed
+    #[error("{0}")]
+    NotInstalled(String),
+
+    /// The step doesn't make sense on this system or in this situation
+    #[error("{0}")]
+    NotApplicable(String),
+
+    /// The step has to be enabled in the configuration, or was turned off there
+    #[error("{0}")]
+    Disabled(String),
+
+    /// The step needs a network resource which isn't reachable
+    #[error("{0}")]
+    Offline(String),
+
+    /// There is nothing for the step to upgrade
+    #[error("{0}")]
+    NothingToDo(String),
+
+    /// The step succeeded recently, or in the run being resumed
+    #[error("{0}")]
+    AlreadySucceeded(String),
+
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl SkipReason {
+    /// The message shown in the summary
+    pub fn message(&self) -> &str {
+        match self {
+            SkipReason::NotInstalled(message)
+            | SkipReason::NotApplicable(message)
+            | SkipReason::Disabled(message)
+            | SkipReason::Offline(message)
+            | SkipReason::NothingToDo(message)
+            | SkipReason::AlreadySucceeded(message)
+            | SkipReason::Custom(message) => message,
+        }
+    }
+
+    /// A stable name for the kind of reason, for machine readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SkipReason::NotInstalled(_) => "not_installed",
+            SkipReason::NotApplicable(_) => "not_applicable",
+            SkipReason::Disabled(_) => "disabled",
+            SkipReason::Offline(_) => "offline",
+            SkipReason::NothingToDo(_) => "nothing_to_do",
+            SkipReason::AlreadySucceeded(_) => "already_succeeded",
+            SkipReason::Custom(_) => "custom",
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 #[error("{0}")]
-pub struct SkipStep(pub String);
+pub struct SkipStep(pub SkipReason);
+
+impl SkipStep {
+    pub fn not_installed(message: impl Into<String>) -> Self {
+        Self(SkipReason::NotInstalled(message.into()))
+    }
+
+    pub fn not_applicable(message: impl Into<String>) -> Self {
+        Self(SkipReason::NotApplicable(message.into()))
+    }
+
+    pub fn disabled(message: impl Into<String>) -> Self {
+        Self(SkipReason::Disabled(message.into()))
+    }
+
+    pub fn offline(message: impl Into<String>) -> Self {
+        Self(SkipReason::Offline(message.into()))
+    }
+
+    pub fn nothing_to_do(message: impl Into<String>) -> Self {
+        Self(SkipReason::NothingToDo(message.into()))
+    }
+
+    pub fn custom(message: impl Into<String>) -> Self {
+        Self(SkipReason::Custom(message.into()))
+    }
+}
 
 #[cfg(all(windows, feature = "self-update"))]
 #[derive(Error, Debug)]
 #[error("Topgrade Upgraded")]
 pub struct Upgraded(pub ExitStatus);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skip_reasons() {
+        let reasons = [
+            (SkipStep::not_installed("Cannot find \"foo\" in PATH"), "not_installed"),
+            (SkipStep::not_applicable("Should not run in WSL"), "not_applicable"),
+            (SkipStep::disabled("Winget is disabled by default"), "disabled"),
+            (SkipStep::offline("SDKMAN! is offline"), "offline"),
+            (SkipStep::nothing_to_do("No repositories to pull"), "nothing_to_do"),
+            (SkipStep::custom("Remote Topgrade launched in Tmux"), "custom"),
+        ];
+
+        for (skip, kind) in reasons {
+            assert_eq!(skip.0.kind(), kind);
+            assert_eq!(skip.to_string(), skip.0.message());
+        }
+    }
+}
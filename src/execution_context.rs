@@ -1,11 +1,62 @@
 #![allow(dead_code)]
+use crate::error::SkipStep;
 use crate::executor::RunType;
 use crate::git::Git;
-use crate::utils::require_option;
-use crate::{config::Config, executor::Executor};
+use crate::{
+    config::{Config, Step},
+    executor::Executor,
+};
 use anyhow::Result;
 use directories::BaseDirs;
+use log::debug;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// How often `start_sudo_keep_alive` refreshes the credentials, well within the default timeout of sudo
+const SUDO_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The tools running commands as root, see `utils::sudo`. They take different flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SudoKind {
+    Sudo,
+    Doas,
+    Run0,
+    Pkexec,
+    Gsudo,
+    /// Any other tool set with `sudo_command`
+    Other,
+}
+
+impl SudoKind {
+    pub fn detect(sudo: &Path) -> Self {
+        match sudo
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_lowercase())
+            .as_deref()
+        {
+            Some("sudo") => SudoKind::Sudo,
+            Some("doas") => SudoKind::Doas,
+            Some("run0") => SudoKind::Run0,
+            Some("pkexec") => SudoKind::Pkexec,
+            Some("gsudo") => SudoKind::Gsudo,
+            _ => SudoKind::Other,
+        }
+    }
+}
+
+/// The conditions of the machine which skip steps: the heavy ones with `[power]` and `[network]`, and the ones
+/// whose mirror is unreachable
+#[derive(Debug, Default)]
+pub struct Constraints {
+    pub on_battery: bool,
+    pub metered: bool,
+    pub unreachable: HashSet<Step>,
+}
 
 pub struct ExecutionContext<'a> {
     run_type: RunType,
@@ -13,6 +64,10 @@ pub struct ExecutionContext<'a> {
     git: &'a Git,
     config: &'a Config,
     base_dirs: &'a BaseDirs,
+    offline: bool,
+    constraints: Constraints,
+    /// Stops the refresh of the sudo credentials when dropped with the context
+    sudo_keep_alive: Mutex<Option<Sender<()>>>,
 }
 
 impl<'a> ExecutionContext<'a> {
@@ -22,6 +77,8 @@ impl<'a> ExecutionContext<'a> {
         git: &'a Git,
         config: &'a Config,
         base_dirs: &'a BaseDirs,
+        offline: bool,
+        constraints: Constraints,
     ) -> ExecutionContext<'a> {
         ExecutionContext {
             run_type,
@@ -29,25 +86,105 @@ impl<'a> ExecutionContext<'a> {
             git,
             config,
             base_dirs,
+            offline,
+            constraints,
+            sudo_keep_alive: Mutex::new(None),
+        }
+    }
+
+    /// Refresh the credentials of sudo in the background until the context is dropped, if the configuration
+    /// asks for it. The refresh is non-interactive: it takes effect once the first elevated step asked for the
+    /// password, and keeps its credentials alive until the end of the run
+    pub fn start_sudo_keep_alive(&self) {
+        let sudo = match self.sudo {
+            Some(sudo) => sudo,
+            None => return,
+        };
+        if !self.config.sudo_keep_alive() || self.run_type.dry() || SudoKind::detect(sudo) != SudoKind::Sudo {
+            return;
+        }
+
+        let mut keep_alive = self.sudo_keep_alive.lock().unwrap();
+        if keep_alive.is_some() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel::<()>();
+        let sudo = sudo.to_path_buf();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(SUDO_KEEP_ALIVE_INTERVAL) {
+                let refreshed = Command::new(&sudo)
+                    .args(["-n", "-v"])
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+                debug!("Refreshed the sudo credentials: {:?}", refreshed);
+            }
+        });
+        *keep_alive = Some(sender);
+    }
+
+    /// The tool running commands as root, skipping the step without it
+    pub fn require_sudo(&self) -> Result<&PathBuf> {
+        if self.config.no_sudo() {
+            return Err(SkipStep::disabled("Running commands as root is disabled by --no-sudo").into());
         }
+
+        self.sudo.as_ref().ok_or_else(|| {
+            SkipStep::not_installed(match self.config.sudo_command() {
+                Some(command) => format!("The sudo_command {} is not installed", command),
+                None => String::from("No sudo, doas, gsudo, run0 or pkexec found"),
+            })
+            .into()
+        })
     }
 
     pub fn execute_elevated(&self, command: &Path, interactive: bool) -> Result<Executor> {
-        let sudo = require_option(self.sudo.clone(), "Sudo is required for this operation".into())?;
-        let mut cmd = self.run_type.execute(&sudo);
+        let sudo = self.require_sudo()?;
+        let mut cmd = self.run_type.execute(sudo);
 
-        if sudo.ends_with("sudo") {
+        // Only sudo can keep single variables and run a login shell, the other tools run the command as it is
+        if SudoKind::detect(sudo) == SudoKind::Sudo {
             cmd.arg("--preserve-env=DIFFPROG");
-        }
 
-        if interactive {
-            cmd.arg("-i");
+            if interactive {
+                cmd.arg("-i");
+            }
         }
 
         cmd.arg(command);
         Ok(cmd)
     }
 
+    /// Whether the steps which need the network are skipped
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Why the step is skipped for the lack of network, offline or with its mirror unreachable
+    pub fn offline_reason(&self, step: Step) -> Option<&'static str> {
+        if self.offline && step.requires_network() {
+            Some("offline")
+        } else if self.constraints.unreachable.contains(&step) {
+            Some("its mirror is unreachable")
+        } else {
+            None
+        }
+    }
+
+    /// Why the step is skipped, when it's one of the heavy steps and the machine runs on battery power or on
+    /// a metered connection
+    pub fn constrained(&self, step: Step) -> Option<&'static str> {
+        if self.constraints.on_battery && self.config.skipped_on_battery(step) {
+            Some("on battery power")
+        } else if self.constraints.metered && self.config.skipped_on_metered(step) {
+            Some("on a metered connection")
+        } else {
+            None
+        }
+    }
+
     pub fn run_type(&self) -> RunType {
         self.run_type
     }
@@ -68,3 +205,17 @@ impl<'a> ExecutionContext<'a> {
         self.base_dirs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sudo_kind() {
+        assert_eq!(SudoKind::detect(Path::new("/usr/bin/sudo")), SudoKind::Sudo);
+        assert_eq!(SudoKind::detect(Path::new("/usr/bin/doas")), SudoKind::Doas);
+        assert_eq!(SudoKind::detect(Path::new("/usr/bin/run0")), SudoKind::Run0);
+        assert_eq!(SudoKind::detect(Path::new("gsudo.exe")), SudoKind::Gsudo);
+        assert_eq!(SudoKind::detect(Path::new("/usr/local/bin/please")), SudoKind::Other);
+    }
+}
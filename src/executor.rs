@@ -1,11 +1,216 @@
 //! Utilities for command execution
-use crate::error::{DryRun, TopgradeError};
+use crate::ctrlc;
+use crate::error::{DryRun, FailedWithOutput, TimedOut, TopgradeError};
+use crate::terminal;
 use crate::utils::{Check, CheckWithCodes};
 use anyhow::Result;
 use log::{debug, trace};
+use std::cell::Cell;
 use std::ffi::{OsStr, OsString};
+use std::io::{self, Read, Write};
 use std::path::Path;
-use std::process::{Child, Command, ExitStatus};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How many of the last lines of a failed command's output are kept by `Executor::check_run_with`
+const CAPTURED_LINES: usize = 20;
+
+thread_local! {
+    /// When the commands of the step running on this thread have to be finished, see `with_timeout`
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// Whether a command of the step was killed because it ran out of time
+    static TIMED_OUT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run the step `f`, killing the commands it runs through the executor once it ran for longer than `timeout`.
+///
+/// The step fails with `TimedOut` when one of its commands got killed, even if it ignored the failure
+pub fn with_timeout<T>(timeout: Option<Duration>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return f(),
+    };
+
+    let outer_deadline = DEADLINE.with(|deadline| deadline.replace(Some(Instant::now() + timeout)));
+    let outer_timed_out = TIMED_OUT.with(|timed_out| timed_out.replace(false));
+    let result = f();
+    DEADLINE.with(|deadline| deadline.set(outer_deadline));
+
+    if TIMED_OUT.with(|timed_out| timed_out.replace(outer_timed_out)) {
+        Err(TimedOut(timeout).into())
+    } else {
+        result
+    }
+}
+
+/// How long the commands of the step running on this thread may still run, if it has a timeout
+pub fn time_left() -> Option<Duration> {
+    DEADLINE
+        .with(|deadline| deadline.get())
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Record that a command of the step was killed because it ran out of time
+pub fn record_timeout() {
+    TIMED_OUT.with(|timed_out| timed_out.set(true));
+}
+
+/// Start the command in its own process group when the step has a timeout, so that its children are killed
+/// with it. Only for the commands whose input is `Stdio::null()`: reading from the terminal stops a background
+/// group, and Ctrl-C only reaches it through `interrupt`
+fn in_own_group(command: &mut Command) {
+    #[cfg(unix)]
+    if DEADLINE.with(|deadline| deadline.get()).is_some() {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(not(unix))]
+    let _ = command;
+}
+
+/// Kill the child, with its process group if it leads one
+fn kill(child: &mut Child) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{killpg, Signal};
+        use nix::unistd::Pid;
+
+        if killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL).is_ok() {
+            return Ok(());
+        }
+    }
+
+    child.kill()
+}
+
+/// Forward Ctrl-C to the process group of the child once, the terminal only sends it to its foreground group
+fn interrupt(child: &Child, forwarded: &mut bool) {
+    if *forwarded || !ctrlc::interrupted() {
+        return;
+    }
+    *forwarded = true;
+
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{killpg, Signal};
+        use nix::unistd::Pid;
+
+        // Fails when the child isn't in its own group, then the terminal sent it Ctrl-C already
+        killpg(Pid::from_raw(child.id() as i32), Signal::SIGINT).ok();
+    }
+    #[cfg(not(unix))]
+    let _ = child;
+}
+
+/// Wait for the child, killing it when the step runs out of time. Returns `None` when it was killed
+fn wait(child: &mut Child) -> io::Result<Option<ExitStatus>> {
+    let deadline = match DEADLINE.with(|deadline| deadline.get()) {
+        Some(deadline) => deadline,
+        None => return child.wait().map(Some),
+    };
+
+    let mut forwarded = false;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        interrupt(child, &mut forwarded);
+        if Instant::now() >= deadline {
+            kill(child)?;
+            child.wait()?;
+            record_timeout();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Like `Command::status`, but killing the command when the step runs out of time. The command can read from
+/// the terminal, so it stays in the group of Topgrade
+fn status(command: &mut Command) -> Result<ExitStatus> {
+    let mut child = command.spawn()?;
+    wait(&mut child)?.ok_or_else(|| anyhow::anyhow!("Killed after running out of time"))
+}
+
+/// Like `Command::output`, but killing the command when the step runs out of time
+fn output(command: &mut Command) -> io::Result<Output> {
+    if DEADLINE.with(|deadline| deadline.get()).is_none() {
+        return command.output();
+    }
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    in_own_group(command);
+    let mut child = command.spawn()?;
+    let stdout = Arc::new(Mutex::new(Vec::new()));
+    let stderr = Arc::new(Mutex::new(Vec::new()));
+    let readers = vec![
+        child.stdout.take().map(|pipe| tee(pipe, io::sink(), stdout.clone())),
+        child.stderr.take().map(|pipe| tee(pipe, io::sink(), stderr.clone())),
+    ];
+
+    let status = wait(&mut child)?;
+    for reader in readers.into_iter().flatten() {
+        reader.join().ok();
+    }
+
+    match status {
+        Some(status) => Ok(Output {
+            status,
+            stdout: stdout.lock().unwrap().clone(),
+            stderr: stderr.lock().unwrap().clone(),
+        }),
+        None => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "Killed after running out of time",
+        )),
+    }
+}
+
+/// Copy everything read from `reader` to `writer`, keeping a copy in `captured`
+fn tee<R, W>(mut reader: R, mut writer: W, captured: Arc<Mutex<Vec<u8>>>) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        while let Ok(read) = reader.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..read]).ok();
+            writer.flush().ok();
+            captured.lock().unwrap().extend_from_slice(&buffer[..read]);
+        }
+    })
+}
+
+/// Hands what's written to it to the sink of `terminal::set_live_output`
+struct LiveWriter;
+
+impl Write for LiveWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf).into_owned();
+        terminal::send_live(|| terminal::LiveEvent::Output(text));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The last `count` lines of the output
+fn last_lines(output: &[u8], count: usize) -> String {
+    let output = String::from_utf8_lossy(output);
+    let lines: Vec<&str> = output.trim_end().lines().collect();
+    lines[lines.len().saturating_sub(count)..].join("\n")
+}
 
 /// An enum telling whether Topgrade should perform dry runs or actually perform the steps.
 #[derive(Clone, Copy, Debug)]
@@ -153,7 +358,7 @@ impl Executor {
     /// See `std::process::Command::output`
     pub fn output(&mut self) -> Result<ExecutorOutput> {
         match self {
-            Executor::Wet(c) => Ok(ExecutorOutput::Wet(c.output()?)),
+            Executor::Wet(c) => Ok(ExecutorOutput::Wet(output(c)?)),
             Executor::Dry(c) => {
                 c.dry_run();
                 Ok(ExecutorOutput::Dry)
@@ -164,15 +369,181 @@ impl Executor {
     /// A convinence method for `spawn().wait().check()`.
     /// Returns an error if something went wrong during the execution or if the
     /// process exited with failure.
+    ///
+    /// While the output is captured by `terminal::capture_output`, the output of the command is captured too.
     pub fn check_run(&mut self) -> Result<()> {
-        self.spawn()?.wait()?.check()
+        match self.run_held_back()? {
+            Some(status) => status.check(),
+            None => self.run()?.check(),
+        }
+    }
+
+    /// Run the command to completion, killing it when the step runs out of time
+    fn run(&mut self) -> Result<ExecutorExitStatus> {
+        match self {
+            Executor::Wet(c) => {
+                debug!("Running {:?}", c);
+                status(c).map(ExecutorExitStatus::Wet)
+            }
+            Executor::Dry(c) => {
+                c.dry_run();
+                Ok(ExecutorExitStatus::Dry)
+            }
+        }
+    }
+
+    /// Run the command with its output handed to `terminal::capture_output` if this thread captures, or to
+    /// the sink of `terminal::set_live_output`
+    fn run_held_back(&mut self) -> Result<Option<ExitStatus>> {
+        match self {
+            Executor::Wet(c) if terminal::capturing() => {
+                debug!("Running {:?}", c);
+                let output = output(c)?;
+                terminal::print_output(String::from_utf8_lossy(&output.stdout));
+                terminal::print_output(String::from_utf8_lossy(&output.stderr));
+                Ok(Some(output.status))
+            }
+            Executor::Wet(c) if terminal::live() => {
+                c.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+                in_own_group(c);
+                debug!("Running {:?}", c);
+                let mut child = c.spawn()?;
+                let discarded = Arc::new(Mutex::new(Vec::new()));
+                let readers = vec![
+                    child.stdout.take().map(|pipe| tee(pipe, LiveWriter, discarded.clone())),
+                    child.stderr.take().map(|pipe| tee(pipe, LiveWriter, discarded.clone())),
+                ];
+
+                let status = wait(&mut child)?;
+                for reader in readers.into_iter().flatten() {
+                    reader.join().ok();
+                }
+                status
+                    .map(Some)
+                    .ok_or_else(|| anyhow::anyhow!("Killed after running out of time"))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Like `check_run`, but kills the command when it runs longer than `timeout`.
+    ///
+    /// With `capture_output`, the output is still shown as it comes, but it goes through pipes so that
+    /// its last lines can be attached to the `FailedWithOutput` error if the command fails.
+    pub fn check_run_with(&mut self, timeout: Option<Duration>, capture_output: bool) -> Result<()> {
+        let command = match self {
+            Executor::Wet(c) => c,
+            Executor::Dry(c) => {
+                c.dry_run();
+                return Ok(());
+            }
+        };
+
+        // The output held back by `terminal::capture_output` is only known to this thread, so the output is
+        // collected and handed to it at the end instead
+        let held_back = terminal::capturing();
+        let live = !held_back && terminal::live();
+        if capture_output || held_back || live {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        if held_back || live {
+            command.stdin(Stdio::null());
+            in_own_group(command);
+        }
+        debug!("Running {:?}", command);
+        let mut child = command.spawn()?;
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let writer = move |stream: Box<dyn Write + Send>| -> Box<dyn Write + Send> {
+            if held_back {
+                Box::new(io::sink())
+            } else if live {
+                Box::new(LiveWriter)
+            } else {
+                stream
+            }
+        };
+        let readers: Vec<JoinHandle<()>> = child
+            .stdout
+            .take()
+            .map(|pipe| tee(pipe, writer(Box::new(io::stdout())), captured.clone()))
+            .into_iter()
+            .chain(
+                child
+                    .stderr
+                    .take()
+                    .map(|pipe| tee(pipe, writer(Box::new(io::stderr())), captured.clone())),
+            )
+            .collect();
+
+        let started = Instant::now();
+        let mut forwarded = false;
+        let mut step_timed_out = false;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            interrupt(&child, &mut forwarded);
+            // The step running out of time first also kills the command
+            step_timed_out = time_left().is_some_and(|left| left.is_zero());
+            if step_timed_out || timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                kill(&mut child)?;
+                child.wait()?;
+                if step_timed_out {
+                    record_timeout();
+                }
+                break None;
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+
+        // Processes started in the background by the command can keep the pipes open, so don't wait for
+        // the readers forever
+        let waiting = Instant::now();
+        while !readers.iter().all(|reader| reader.is_finished()) && waiting.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        // The readers still running are detached, they end with the last process holding the pipes
+        for reader in readers.into_iter().filter(|reader| reader.is_finished()) {
+            reader.join().ok();
+        }
+
+        if held_back {
+            terminal::print_output(String::from_utf8_lossy(&captured.lock().unwrap()));
+        }
+
+        let message = match status {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) if !capture_output => return Err(TopgradeError::ProcessFailed(status).into()),
+            Some(status) => TopgradeError::ProcessFailed(status).to_string(),
+            None => match timeout {
+                Some(timeout) if !step_timed_out => {
+                    format!("Timed out after {}", humantime::format_duration(timeout))
+                }
+                _ => String::from("The step ran out of time"),
+            },
+        };
+        if !capture_output {
+            return Err(anyhow::anyhow!(message));
+        }
+
+        let output = last_lines(&captured.lock().unwrap(), CAPTURED_LINES);
+        Err(FailedWithOutput {
+            message,
+            output,
+            exit_code: status.and_then(|status| status.code()),
+        }
+        .into())
     }
 
     /// An extension of `check_run` that allows you to set a sequence of codes
     /// that can indicate success of a script
     #[allow(dead_code)]
     pub fn check_run_with_codes(&mut self, codes: &[i32]) -> Result<()> {
-        self.spawn()?.wait()?.check_with_codes(codes)
+        match self.run_held_back()? {
+            Some(status) => status.check_with_codes(codes),
+            None => self.run()?.check_with_codes(codes),
+        }
     }
 }
 
@@ -191,23 +562,25 @@ pub struct DryCommand {
 
 impl DryCommand {
     fn dry_run(&self) {
-        print!(
-            "Dry running: {} {}",
+        let directory = match &self.directory {
+            Some(dir) => format!(" in {}", dir.to_string_lossy()),
+            None => String::new(),
+        };
+        terminal::print_output(format!(
+            "Dry running: {} {}{}\n",
             self.program.to_string_lossy(),
             self.args
                 .iter()
                 .map(|a| String::from(a.to_string_lossy()))
                 .collect::<Vec<String>>()
-                .join(" ")
-        );
-        match &self.directory {
-            Some(dir) => println!(" in {}", dir.to_string_lossy()),
-            None => println!(),
-        };
+                .join(" "),
+            directory
+        ));
     }
 }
 
 /// The Result of spawn. Contains an actual `std::process::Child` if executed by a wet command.
+#[allow(dead_code)]
 pub enum ExecutorChild {
     Wet(Child),
     Dry,
@@ -215,6 +588,7 @@ pub enum ExecutorChild {
 
 impl ExecutorChild {
     /// See `std::process::Child::wait`
+    #[allow(dead_code)]
     pub fn wait(&mut self) -> Result<ExecutorExitStatus> {
         let result = match self {
             ExecutorChild::Wet(c) => c.wait().map(ExecutorExitStatus::Wet)?,
@@ -249,7 +623,7 @@ pub trait CommandExt {
 
 impl CommandExt for Command {
     fn check_output(&mut self) -> Result<String> {
-        let output = self.output()?;
+        let output = output(self)?;
         trace!("Output of {:?}: {:?}", self, output);
         let status = output.status;
         if !status.success() {
@@ -260,7 +634,7 @@ impl CommandExt for Command {
     }
 
     fn string_output(&mut self) -> Result<String> {
-        let output = self.output()?;
+        let output = output(self)?;
         trace!("Output of {:?}: {:?}", self, output);
         Ok(String::from_utf8(output.stdout)?)
     }
@@ -288,3 +662,15 @@ impl CommandExt for Executor {
         Ok(String::from_utf8(output.stdout)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_lines() {
+        assert_eq!(last_lines(b"a\nb\nc\n", 2), "b\nc");
+        assert_eq!(last_lines(b"a\n", 5), "a");
+        assert_eq!(last_lines(b"", 5), "");
+    }
+}
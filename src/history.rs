@@ -0,0 +1,261 @@
+//! History of the step outcomes of previous Topgrade runs
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{Local, TimeZone, Utc};
+use directories::BaseDirs;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+
+use crate::report::{Report, StepResult};
+
+/// The outcome of one step
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct StepRecord {
+    pub step: String,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// The kind of skip reason, see `SkipReason::kind`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_kind: Option<String>,
+    /// Duration of the step in seconds
+    pub duration: f64,
+    /// Machine readable details about what the step changed, depending on the step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl StepRecord {
+    pub(crate) fn new(
+        step: &str,
+        result: &StepResult,
+        duration: Duration,
+        details: Option<&serde_json::Value>,
+    ) -> Self {
+        Self {
+            step: step.to_string(),
+            status: result.status().to_string(),
+            reason: match result {
+                StepResult::Skipped(reason) => Some(reason.message().to_string()),
+                _ => None,
+            },
+            skip_kind: match result {
+                StepResult::Skipped(reason) => Some(reason.kind().to_string()),
+                _ => None,
+            },
+            duration: duration.as_secs_f64(),
+            details: details.cloned(),
+        }
+    }
+}
+
+/// The outcomes of the steps of one run
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct RunRecord {
+    /// Unix timestamp of the end of the run
+    pub timestamp: i64,
+    pub steps: Vec<StepRecord>,
+}
+
+impl RunRecord {
+    pub(crate) fn from_report(report: &Report) -> Self {
+        let steps = report
+            .data()
+            .iter()
+            .map(|(key, result)| {
+                StepRecord::new(
+                    key,
+                    result,
+                    report.duration(key).unwrap_or_default(),
+                    report.details(key),
+                )
+            })
+            .collect();
+
+        Self {
+            timestamp: Utc::now().timestamp(),
+            steps,
+        }
+    }
+
+    /// Print the run like the summary of a run
+    pub fn print(&self) {
+        let time = Local
+            .timestamp_opt(self.timestamp, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| self.timestamp.to_string());
+        println!("―― Run of {} ――", time);
+
+        for step in &self.steps {
+            let duration = humantime::format_duration(Duration::from_secs(step.duration.round() as u64));
+            match &step.reason {
+                Some(reason) => println!("{}: {}: {} ({})", step.step, step.status, reason, duration),
+                None => println!("{}: {} ({})", step.step, step.status, duration),
+            }
+        }
+    }
+}
+
+fn history_path(base_dirs: &BaseDirs) -> PathBuf {
+    base_dirs.data_dir().join("topgrade").join("history.jsonl")
+}
+
+/// Parse the runs of the history file, ignoring lines which can't be parsed
+fn parse_history(contents: &str) -> Vec<RunRecord> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| error!("Failed to deserialize a history entry: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Append the run to the history file, keeping only the last `max_entries` runs
+pub fn append(base_dirs: &BaseDirs, run: RunRecord, max_entries: usize) -> Result<()> {
+    let path = history_path(base_dirs);
+
+    let mut runs = fs::read_to_string(&path)
+        .map(|contents| parse_history(&contents))
+        .unwrap_or_default();
+    runs.push(run);
+    let excess = runs.len().saturating_sub(max_entries);
+
+    let mut contents = String::new();
+    for run in runs.iter().skip(excess) {
+        contents.push_str(&serde_json::to_string(run)?);
+        contents.push('\n');
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, contents)?;
+    debug!("Saved the run to {}", path.display());
+
+    Ok(())
+}
+
+/// The last `count` runs, oldest first
+pub fn last(base_dirs: &BaseDirs, count: usize) -> Vec<RunRecord> {
+    let mut runs = fs::read_to_string(history_path(base_dirs))
+        .map(|contents| parse_history(&contents))
+        .unwrap_or_default();
+    let excess = runs.len().saturating_sub(count);
+    runs.split_off(excess)
+}
+
+/// Print the last `count` runs, and the steps which failed in all their runs since they last succeeded
+pub fn print(base_dirs: &BaseDirs, count: usize) {
+    let runs = last(base_dirs, usize::MAX);
+    if runs.is_empty() {
+        println!("No runs recorded yet");
+        return;
+    }
+
+    for run in &runs[runs.len().saturating_sub(count)..] {
+        run.print();
+    }
+
+    let streaks = failure_streaks(&runs);
+    if !streaks.is_empty() {
+        println!("―― Failing steps ――");
+        for (step, count) in streaks {
+            match count {
+                1 => println!("{}: failed in the last run", step),
+                _ => println!("{}: failed in the last {} runs", step, count),
+            }
+        }
+    }
+}
+
+/// The steps which failed in each of their last runs, with the number of runs they failed in a row, most
+/// failures first
+pub fn failure_streaks(runs: &[RunRecord]) -> Vec<(&str, usize)> {
+    let mut streaks: Vec<(&str, usize)> = Vec::new();
+    let mut settled: Vec<&str> = Vec::new();
+
+    for step in runs.iter().rev().flat_map(|run| run.steps.iter()) {
+        if settled.contains(&step.step.as_str()) {
+            continue;
+        }
+
+        // Skipped steps neither break nor extend a streak
+        match step.status.as_str() {
+            "FAILED" | "IGNORED" => match streaks.iter_mut().find(|(name, _)| *name == step.step) {
+                Some((_, count)) => *count += 1,
+                None => streaks.push((&step.step, 1)),
+            },
+            "SKIPPED" => (),
+            _ => settled.push(&step.step),
+        }
+    }
+
+    streaks.sort_by(|(_, a), (_, b)| b.cmp(a));
+    streaks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_history() {
+        let run = RunRecord {
+            timestamp: 1700000000,
+            steps: vec![
+                StepRecord {
+                    step: String::from("git"),
+                    status: String::from("OK"),
+                    reason: None,
+                    skip_kind: None,
+                    duration: 1.5,
+                    details: Some(serde_json::json!({"pulled": ["~/src/topgrade"]})),
+                },
+                StepRecord {
+                    step: String::from("pearl"),
+                    status: String::from("SKIPPED"),
+                    reason: Some(String::from("Cannot find \"pearl\" in PATH")),
+                    skip_kind: Some(String::from("not_installed")),
+                    duration: 0.0,
+                    details: None,
+                },
+            ],
+        };
+        let contents = format!("{}\nnot json\n\n", serde_json::to_string(&run).unwrap());
+
+        assert_eq!(parse_history(&contents), [run]);
+    }
+
+    #[test]
+    fn test_failure_streaks() {
+        let run = |steps: &[(&str, &str)]| RunRecord {
+            timestamp: 0,
+            steps: steps
+                .iter()
+                .map(|(step, status)| StepRecord {
+                    step: step.to_string(),
+                    status: status.to_string(),
+                    reason: None,
+                    skip_kind: None,
+                    duration: 0.0,
+                    details: None,
+                })
+                .collect(),
+        };
+        let runs = [
+            run(&[("apt", "FAILED"), ("git", "FAILED"), ("cargo", "FAILED")]),
+            run(&[("apt", "FAILED"), ("git", "OK"), ("cargo", "FAILED")]),
+            run(&[("apt", "FAILED"), ("git", "FAILED"), ("cargo", "SKIPPED")]),
+            run(&[("apt", "OK"), ("git", "FAILED")]),
+        ];
+
+        assert_eq!(failure_streaks(&runs), [("git", 2), ("cargo", 2)]);
+    }
+}
@@ -0,0 +1,757 @@
+//! Topgrade upgrades all the things. Besides the `topgrade` binary, the crate lets other tools run
+//! the steps and follow their progress with [`run_with_callback`].
+//!
+//! The public modules are:
+//! - [`config`]: the command line arguments and the configuration file, to build a [`Config`]
+//! - [`history`]: [`StepRecord`], the outcome of each step, and the history of previous runs
+//! - [`error`]: the errors returned by a run, e.g. [`error::StepFailed`] when a step failed
+//! - [`terminal`]: the output helpers shared by the steps
+//! - [`ctrlc`]: the handling of interruptions
+//! - [`schedule`]: when `topgrade daemon` runs the steps
+//!
+//! Everything else is internal.
+#![allow(clippy::cognitive_complexity)]
+
+use std::borrow::Cow;
+#[cfg(target_os = "linux")]
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::env;
+
+use anyhow::{anyhow, Result};
+use clap::{crate_version, ArgEnum};
+use console::Key;
+use directories::BaseDirs;
+use log::debug;
+use strum::IntoEnumIterator;
+
+use self::config::{Config, ConstraintPolicy, Step, SummarySkipDisplay};
+use self::error::{SkipStep, StepFailed};
+pub use self::history::StepRecord;
+use self::report::StepResult;
+use self::steps::{remote::*, *};
+use self::terminal::*;
+
+pub mod config;
+mod connectivity;
+pub mod ctrlc;
+mod disk_space;
+pub mod error;
+mod execution_context;
+mod executor;
+pub mod history;
+mod lock;
+mod metered;
+mod notifications;
+mod power;
+mod report;
+mod runner;
+pub mod schedule;
+#[cfg(windows)]
+mod self_renamer;
+#[cfg(feature = "self-update")]
+mod self_update;
+mod state;
+mod steps;
+pub mod terminal;
+#[cfg(feature = "tui")]
+mod tui;
+mod utils;
+
+/// Run the steps enabled by `config` once, calling `on_step` with the name, status and duration
+/// of each step as soon as it finishes
+pub fn run_with_callback(config: Config, mut on_step: impl FnMut(&StepRecord)) -> Result<()> {
+    let base_dirs = BaseDirs::new().ok_or_else(|| anyhow!("No base directories"))?;
+    run_once(&base_dirs, &config, &mut on_step)
+}
+
+/// Run all the steps enabled by the configuration once, calling `on_step` as each step finishes.
+///
+/// This is what the binary runs.
+pub fn run_once(base_dirs: &BaseDirs, config: &Config, on_step: &mut dyn FnMut(&StepRecord)) -> Result<()> {
+    prepare(config)?;
+    let result = run_steps(base_dirs, config, on_step);
+    // Like before, the prompt comes when the steps ran, even if some of them failed
+    if !matches!(&result, Err(e) if e.downcast_ref::<StepFailed>().is_none()) {
+        keep_at_end(config);
+    }
+    result
+}
+
+/// What comes once before the steps, even when they run repeatedly with `--watch` or `topgrade daemon`:
+/// moving to tmux and the self update
+pub fn prepare(config: &Config) -> Result<()> {
+    if config.run_in_tmux() && !config.print_steps() && env::var("TOPGRADE_INSIDE_TMUX").is_err() {
+        #[cfg(unix)]
+        {
+            tmux::run_in_tmux(config.tmux_arguments());
+        }
+    }
+
+    #[cfg(feature = "self-update")]
+    {
+        if !config.dry_run() && !config.print_steps() && env::var("TOPGRADE_NO_SELF_UPGRADE").is_err() {
+            let result = self_update::self_update();
+
+            if let Err(e) = &result {
+                #[cfg(windows)]
+                {
+                    if e.downcast_ref::<Upgraded>().is_some() {
+                        return result;
+                    }
+                }
+                print_warning(format!("Self update error: {}", e));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Offer to reboot or open a shell with `--keep`, once the steps ran
+pub fn keep_at_end(config: &Config) {
+    if !config.keep_at_end() || config.print_steps() {
+        return;
+    }
+
+    let sudo = if config.no_sudo() {
+        None
+    } else {
+        utils::sudo(config.sudo_command())
+    };
+    print_info("\n(R)eboot\n(S)hell\n(Q)uit");
+    loop {
+        match get_key() {
+            Ok(Key::Char('s')) | Ok(Key::Char('S')) => {
+                run_shell();
+            }
+            Ok(Key::Char('r')) | Ok(Key::Char('R')) => {
+                reboot(sudo.as_ref());
+            }
+            Ok(Key::Char('q')) | Ok(Key::Char('Q')) => (),
+            _ => {
+                continue;
+            }
+        }
+        break;
+    }
+}
+
+/// Run the steps enabled by the configuration, without what `prepare` and `keep_at_end` do
+pub fn run_steps(base_dirs: &BaseDirs, config: &Config, on_step: &mut dyn FnMut(&StepRecord)) -> Result<()> {
+    terminal::set_title(config.set_title());
+    terminal::display_time(config.display_time());
+    terminal::set_output_width(config.output_width());
+    terminal::set_desktop_notifications(config.notify_each_step());
+
+    debug!("Version: {}", crate_version!());
+    debug!("OS: {}", env!("TARGET"));
+    debug!("{:?}", std::env::args());
+    debug!("Binary path: {:?}", std::env::current_exe());
+    debug!("Self Update: {:?}", cfg!(feature = "self-update"));
+
+    let on_battery = !config.print_steps() && power::on_battery().unwrap_or(false);
+    if on_battery {
+        match config.battery_policy() {
+            ConstraintPolicy::Warn => print_warning("Running on battery power"),
+            ConstraintPolicy::Abort => return Err(anyhow!("Running on battery power, plug in the charger first")),
+            ConstraintPolicy::SkipHeavy => print_info("Running on battery power, skipping the heavy steps"),
+        }
+    }
+
+    // Runs started by another topgrade, in containers, remote hosts or WSL, are covered by its lock
+    let _lock = if config.print_steps() || config.dry_run() || env::var("TOPGRADE_PREFIX").is_ok() {
+        None
+    } else {
+        Some(lock::RunLock::acquire(base_dirs, config.wait_for_lock())?)
+    };
+
+    let git = git::Git::new();
+
+    let sudo = if config.no_sudo() {
+        None
+    } else {
+        utils::sudo(config.sudo_command())
+    };
+    let run_type = executor::RunType::new(config.dry_run());
+
+    let connectivity = if config.auto_offline() && !config.offline() && !config.print_steps() {
+        connectivity::check(config)
+    } else {
+        connectivity::Connectivity::default()
+    };
+    let offline = config.offline() || connectivity.offline;
+    if connectivity.offline {
+        print_info("No network connection, skipping the steps which need it");
+    }
+
+    let metered = !offline && !config.print_steps() && metered::metered().unwrap_or(false);
+    if metered {
+        match config.metered_policy() {
+            ConstraintPolicy::Warn => print_warning("The network connection is metered"),
+            ConstraintPolicy::Abort => return Err(anyhow!("The network connection is metered")),
+            ConstraintPolicy::SkipHeavy => print_info("The network connection is metered, skipping the heavy steps"),
+        }
+    }
+
+    if !run_type.dry() && !config.print_steps() {
+        disk_space::check(config)?;
+    }
+
+    let started = chrono::Local::now();
+    let constraints = execution_context::Constraints {
+        on_battery,
+        metered,
+        unreachable: connectivity.unreachable,
+    };
+    let ctx = execution_context::ExecutionContext::new(run_type, &sudo, &git, config, base_dirs, offline, constraints);
+
+    #[cfg(feature = "tui")]
+    let tui = if config.tui() && !config.print_steps() {
+        Some(tui::Tui::start(planned_steps(&ctx))?)
+    } else {
+        None
+    };
+    let mut runner = runner::Runner::new(&ctx, on_step);
+    if !run_type.dry() && !config.print_steps() {
+        runner.start_run();
+    }
+
+    #[cfg(windows)]
+    let _self_rename = if config.self_rename() {
+        Some(crate::self_renamer::SelfRenamer::create()?)
+    } else {
+        None
+    };
+
+    if !config.print_steps() {
+        ctx.start_sudo_keep_alive();
+    }
+
+    if let Some(commands) = config.pre_commands().as_ref().filter(|_| !config.print_steps()) {
+        for (name, command) in commands {
+            generic::run_custom_command(name, command, &ctx)?;
+        }
+    }
+
+    let executed = execute_steps(&mut runner, &ctx)?;
+    #[cfg(target_os = "linux")]
+    let distribution = executed.distribution;
+    #[cfg(target_os = "linux")]
+    let snapshot = executed.snapshot;
+    let container_keys = executed.container_keys;
+
+    if config.list_steps() {
+        print_step_list(config, runner.plan());
+        return Ok(());
+    }
+
+    if config.print_steps() {
+        for (_, key, status) in runner.plan() {
+            println!("{}: {}", key, status);
+        }
+        return Ok(());
+    }
+
+    if !config.dry_run() && !runner.report().data().is_empty() {
+        let run = history::RunRecord::from_report(runner.report());
+        if let Err(e) = history::append(base_dirs, run, config.history_max_entries()) {
+            print_warning(format!("Failed to save the run history: {}", e));
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    drop(tui);
+
+    if !runner.report().data().is_empty() {
+        print_separator("Summary");
+
+        let skip_display = config.summary_skip_display();
+        let visible: Vec<_> = runner
+            .report()
+            .data()
+            .iter()
+            .filter(|(_, result)| match result {
+                StepResult::Skipped(_) => match skip_display {
+                    SummarySkipDisplay::All => true,
+                    SummarySkipDisplay::Reasons => !result.skipped_not_installed(),
+                    SummarySkipDisplay::None => false,
+                },
+                _ => true,
+            })
+            .collect();
+        let key_width = visible.iter().map(|(key, _)| key.chars().count()).max().unwrap_or(0);
+
+        // The steps run in the containers of `[containers.exec]` come in a section of their own
+        let (in_containers, on_host): (Vec<_>, Vec<_>) = visible
+            .into_iter()
+            .partition(|(key, _)| container_keys.iter().any(|container_key| container_key == key));
+        let print_results = |results: Vec<&(Cow<str>, StepResult)>| {
+            for (key, result) in results {
+                print_result(key, result, key_width);
+
+                if config.show_changes() {
+                    if let Some(changes) = runner.report().changes(key) {
+                        print_changes(changes);
+                    }
+                }
+
+                if let Some(warnings) = runner.report().warnings(key) {
+                    print_step_warnings(warnings);
+                }
+            }
+        };
+        print_results(on_host);
+        if !in_containers.is_empty() {
+            print_separator("Containers");
+            print_results(in_containers);
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            if let Ok(distribution) = &distribution {
+                distribution.show_summary();
+            }
+
+            if let Some(snapshot) = &snapshot {
+                if runner.report().data().iter().any(|(_, result)| result.failed()) {
+                    print_warning(format!("Before the upgrade, Topgrade took the {}", snapshot));
+                }
+            }
+
+            if linux::reboot_required() {
+                print_warning(if config.keep_at_end() {
+                    "A reboot is required to finish applying the upgrades"
+                } else {
+                    "A reboot is required to finish applying the upgrades (run with --keep to be offered a reboot)"
+                });
+            }
+        }
+
+        #[cfg(target_os = "freebsd")]
+        freebsd::audit_packages(&sudo).ok();
+
+        #[cfg(target_os = "dragonfly")]
+        dragonfly::audit_packages(&sudo).ok();
+    }
+
+    if let Some(format) = config.report_format() {
+        if let Err(e) = runner.report().write(format, config.report_file().as_deref()) {
+            print_warning(format!("Failed to write the report: {}", e));
+        }
+    }
+
+    // A dry run would look like a run where nothing failed
+    if let Some(path) = config.metrics_file().filter(|_| !run_type.dry()) {
+        if let Err(e) = runner.report().write_metrics(&path, started.timestamp()) {
+            print_warning(format!("Failed to write the metrics: {}", e));
+        }
+    }
+
+    let mut post_command_failed = false;
+    if let Some(commands) = config.post_commands().as_ref().filter(|_| !runner.aborted()) {
+        for (name, command) in commands {
+            if generic::run_custom_command(name, command, &ctx).is_err() {
+                post_command_failed = true;
+            }
+        }
+    }
+
+    let failed = post_command_failed || runner.report().data().iter().any(|(_, result)| result.failed());
+    if !failed && !runner.aborted() && !run_type.dry() {
+        runner.finish_run();
+    }
+
+    notifications::run_finished(&ctx, runner.report(), !failed);
+
+    if failed {
+        Err(StepFailed.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// What the summary shows about the steps of `execute_steps`
+struct Executed {
+    container_keys: Vec<String>,
+    #[cfg(target_os = "linux")]
+    distribution: Result<linux::Distribution>,
+    #[cfg(target_os = "linux")]
+    snapshot: Option<snapshot::Snapshot>,
+}
+
+/// The steps of the registries which will run, in order, for the sidebar of the TUI to list them before the
+/// run. The other steps are added to it as they start
+#[cfg(feature = "tui")]
+fn planned_steps(ctx: &execution_context::ExecutionContext) -> Vec<String> {
+    let config = ctx.config();
+    let mut registries = Vec::new();
+    if let Some(topgrades) = config.remote_topgrades() {
+        registries.push(
+            topgrades
+                .iter()
+                .map(|t| remote::ssh::RemoteStep::boxed(t))
+                .collect::<Vec<_>>(),
+        );
+    }
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    registries.push(unix::brew_steps());
+    #[cfg(unix)]
+    {
+        registries.push(unix::package_manager_steps());
+        registries.push(unix::shell_steps());
+        registries.push(unix::tool_steps());
+    }
+    registries.push(generic::toolchain_steps());
+    registries.push(generic::language_steps());
+    registries.push(generic::package_steps());
+
+    registries
+        .iter()
+        .flatten()
+        .filter(|step| {
+            config.should_run(step.step())
+                && ctx.offline_reason(step.step()).is_none()
+                && ctx.constrained(step.step()).is_none()
+                && step.detect(ctx)
+        })
+        .map(|step| step.name().to_string())
+        .collect()
+}
+
+/// Run the steps in order, or only record them with a planning runner
+fn execute_steps<'a>(
+    runner: &mut runner::Runner<'a>,
+    ctx: &'a execution_context::ExecutionContext<'a>,
+) -> Result<Executed> {
+    let config = ctx.config();
+    let base_dirs = ctx.base_dirs();
+    let run_type = ctx.run_type();
+    let git = ctx.git();
+    let mut git_repos = git::Repositories::new(git);
+
+    if let Some(backup) = config.backup() {
+        runner.execute(Step::Backup, "Backup", || backup::run_backup(ctx, backup))?;
+    }
+
+    let powershell = powershell::Powershell::new();
+    let should_run_powershell = powershell.profile().is_some() && config.should_run(Step::Powershell);
+
+    #[cfg(windows)]
+    runner.execute(Step::Wsl, "WSL", || windows::run_wsl_topgrade(ctx))?;
+
+    if let Some(topgrades) = config.remote_topgrades() {
+        let remotes: Vec<_> = topgrades.iter().map(|t| remote::ssh::RemoteStep::boxed(t)).collect();
+        runner.execute_parallel(&remotes, config.remote_concurrency())?;
+    }
+
+    if let Some(kubernetes) = config.kubernetes() {
+        for (name, target) in kubernetes.targets() {
+            runner.execute(Step::Kubernetes, format!("Kubernetes ({})", name), || {
+                remote::kubernetes::run_target(ctx, kubernetes, name, target)
+            })?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    let distribution = linux::Distribution::detect();
+    #[cfg(target_os = "linux")]
+    let snapshot = RefCell::new(None);
+
+    #[cfg(target_os = "linux")]
+    {
+        match &distribution {
+            Ok(distribution) => {
+                if let Some(snapshot_config) = config.snapshot().filter(|_| config.should_run(Step::System)) {
+                    runner.execute(Step::Snapshot, "Snapshot", || {
+                        *snapshot.borrow_mut() = Some(snapshot::create(ctx, snapshot_config)?);
+                        Ok(())
+                    })?;
+                }
+
+                // Upgrading without the snapshot would leave nothing to roll back to
+                if runner.report().failed("Snapshot") {
+                    print_warning("The snapshot failed, skipping the system update");
+                } else {
+                    runner.execute(Step::System, "System update", || distribution.upgrade(ctx))?;
+                }
+            }
+            Err(e) => {
+                println!("Error detecting current distribution: {}", e);
+            }
+        }
+        runner.execute(Step::ConfigUpdate, "config-update", || linux::run_config_update(ctx))?;
+
+        runner.execute_steps(&unix::brew_steps())?;
+    }
+
+    #[cfg(windows)]
+    {
+        runner.execute(Step::Chocolatey, "Chocolatey", || windows::run_chocolatey(ctx))?;
+        runner.execute(Step::Scoop, "Scoop", || windows::run_scoop(config.cleanup(), run_type))?;
+        runner.execute(Step::Winget, "Winget", || windows::run_winget(ctx))?;
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        if config.brew_parallel_variants() {
+            let (parallel, sequential) = unix::parallel_brew_steps();
+            runner.execute_parallel(&parallel, 2)?;
+            runner.execute_steps(&sequential)?;
+        } else {
+            runner.execute_steps(&unix::brew_steps())?;
+        }
+        runner.execute(Step::Macports, "MacPorts", || macos::run_macports(ctx))?;
+    }
+
+    #[cfg(unix)]
+    {
+        runner.execute_parallel(&unix::package_manager_steps(), config.max_concurrency())?;
+        // Switches once nix updated the channels
+        runner.execute(Step::HomeManager, "home-manager", || unix::run_home_manager(run_type))?;
+    }
+
+    #[cfg(target_os = "dragonfly")]
+    runner.execute(Step::Pkg, "DragonFly BSD Packages", || dragonfly::upgrade_packages(ctx))?;
+
+    #[cfg(target_os = "freebsd")]
+    runner.execute(Step::FreebsdPkg, "FreeBSD Packages", || freebsd::upgrade_packages(ctx))?;
+
+    #[cfg(target_os = "android")]
+    runner.execute(Step::Pkg, "Termux Packages", || android::upgrade_packages(ctx))?;
+
+    let emacs = emacs::Emacs::new(base_dirs);
+    if config.use_predefined_git_repos() {
+        if config.should_run(Step::Emacs) {
+            if !emacs.is_doom() {
+                if let Some(directory) = emacs.directory() {
+                    git_repos.insert_if_repo(directory);
+                }
+            }
+            git_repos.insert_if_repo(base_dirs.home_dir().join(".doom.d"));
+        }
+
+        if config.should_run(Step::Vim) {
+            git_repos.insert_if_repo(base_dirs.home_dir().join(".vim"));
+            git_repos.insert_if_repo(base_dirs.home_dir().join(".config/nvim"));
+        }
+
+        git_repos.insert_if_repo(base_dirs.home_dir().join(".ideavimrc"));
+        git_repos.insert_if_repo(base_dirs.home_dir().join(".intellimacs"));
+
+        #[cfg(unix)]
+        {
+            git_repos.insert_if_repo(zsh::zshrc(base_dirs));
+            if config.should_run(Step::Tmux) {
+                git_repos.insert_if_repo(base_dirs.home_dir().join(".tmux"));
+            }
+            git_repos.insert_if_repo(base_dirs.home_dir().join(".config/fish"));
+            git_repos.insert_if_repo(base_dirs.config_dir().join("openbox"));
+            git_repos.insert_if_repo(base_dirs.config_dir().join("bspwm"));
+            git_repos.insert_if_repo(base_dirs.config_dir().join("i3"));
+            git_repos.insert_if_repo(base_dirs.config_dir().join("sway"));
+        }
+
+        #[cfg(windows)]
+        git_repos.insert_if_repo(
+            base_dirs
+                .data_local_dir()
+                .join("Packages/Microsoft.WindowsTerminal_8wekyb3d8bbwe/LocalState"),
+        );
+
+        #[cfg(windows)]
+        windows::insert_startup_scripts(ctx, &mut git_repos).ok();
+
+        if let Some(profile) = powershell.profile() {
+            git_repos.insert_if_repo(profile);
+        }
+    }
+
+    if config.should_run(Step::GitRepos) {
+        if let Some(custom_git_repos) = config.git_repos() {
+            for git_repo in custom_git_repos {
+                git_repos.glob_insert(git_repo);
+            }
+        }
+        runner.execute_with_report(Step::GitRepos, "Git repositories", || {
+            git.multi_pull_step(&git_repos, ctx)
+        })?;
+    }
+
+    if should_run_powershell {
+        runner.execute(Step::Powershell, "Powershell Modules Update", || {
+            powershell.update_modules(ctx)
+        })?;
+    }
+
+    #[cfg(unix)]
+    {
+        runner.execute(Step::Shell, "zr", || zsh::run_zr(base_dirs, run_type))?;
+        runner.execute(Step::Shell, "antibody", || zsh::run_antibody(run_type))?;
+        runner.execute(Step::Shell, "antigen", || zsh::run_antigen(base_dirs, run_type))?;
+        runner.execute(Step::Shell, "zgenom", || zsh::run_zgenom(base_dirs, run_type))?;
+        runner.execute(Step::Shell, "zplug", || zsh::run_zplug(base_dirs, run_type))?;
+        runner.execute(Step::Shell, "zinit", || zsh::run_zinit(base_dirs, run_type))?;
+        runner.execute(Step::Shell, "zi", || zsh::run_zi(base_dirs, run_type))?;
+        runner.execute(Step::Shell, "zim", || zsh::run_zim(base_dirs, run_type))?;
+        runner.execute(Step::Shell, "oh-my-zsh", || zsh::run_oh_my_zsh(ctx))?;
+        runner.execute_parallel(&unix::shell_steps(), config.max_concurrency())?;
+        runner.execute(Step::Tmux, "tmux", || tmux::run_tpm(base_dirs, run_type))?;
+        runner.execute_parallel(&unix::tool_steps(), config.max_concurrency())?;
+    }
+
+    runner.execute_parallel(&generic::toolchain_steps(), config.max_concurrency())?;
+    if let Some(projects) = config.cargo_projects() {
+        for project in projects {
+            runner.execute(Step::Cargo, format!("cargo ({})", project), || {
+                generic::run_cargo_project_update(ctx, project)
+            })?;
+        }
+    }
+    #[cfg(unix)]
+    if let Some(workspaces) = config.ros_workspaces() {
+        for (workspace, build_tool) in workspaces {
+            runner.execute(Step::Ros, format!("ROS ({})", workspace), || {
+                unix::run_ros_workspace(ctx, workspace, *build_tool)
+            })?;
+        }
+    }
+    runner.execute(Step::Emacs, "Emacs", || emacs.upgrade(ctx))?;
+    runner.execute_parallel(&generic::language_steps(), config.max_concurrency())?;
+    runner.execute(Step::Vim, "vim", || vim::upgrade_vim(base_dirs, ctx))?;
+    runner.execute(Step::Vim, "Neovim", || vim::upgrade_neovim(base_dirs, ctx))?;
+    runner.execute(Step::Vim, "The Ultimate vimrc", || vim::upgrade_ultimate_vimrc(ctx))?;
+    runner.execute(Step::Vim, "voom", || vim::run_voom(base_dirs, run_type))?;
+    runner.execute(Step::Kakoune, "Kakoune", || kakoune::upgrade_kak_plug(ctx))?;
+    runner.execute(Step::JetBrains, "JetBrains", || jetbrains::run_jetbrains(ctx))?;
+    runner.execute(Step::Node, "npm", || node::run_npm_upgrade(ctx))?;
+    runner.execute(Step::Node, "yarn", || node::run_yarn_upgrade(ctx))?;
+    runner.execute_with_report(Step::Fnm, "fnm", || node::run_fnm(ctx))?;
+    #[cfg(unix)]
+    runner.execute(Step::Nvm, "nvm", || node::run_nvm(ctx))?;
+    runner.execute(Step::Containers, "Containers", || containers::run_containers(ctx))?;
+    let mut container_keys = Vec::new();
+    for (name, container) in config.exec_containers().into_iter().flatten() {
+        for step in container.steps() {
+            let key = containers::exec_key(name, *step);
+            container_keys.push(key.clone());
+            runner.execute(Step::Containers, key, || {
+                containers::run_in_container(ctx, name, container, *step)
+            })?;
+        }
+    }
+    runner.execute(Step::Deno, "deno", || node::deno_upgrade(ctx))?;
+    runner.execute_parallel(&generic::package_steps(), config.max_concurrency())?;
+
+    #[cfg(target_os = "linux")]
+    {
+        runner.execute(Step::DebGet, "deb-get", || linux::run_deb_get(ctx))?;
+        runner.execute(Step::Toolbx, "toolbx", || toolbx::run_toolbx(ctx))?;
+        runner.execute_with_report(Step::Distrobox, "distrobox", || distrobox::run_distrobox(ctx))?;
+        runner.execute(Step::Flatpak, "Flatpak", || linux::flatpak_update(ctx))?;
+        runner.execute(Step::Snap, "snap", || linux::run_snap(ctx))?;
+        runner.execute(Step::Pacstall, "pacstall", || linux::run_pacstall(ctx))?;
+        runner.execute(Step::Pacdef, "pacdef", || linux::run_pacdef(ctx))?;
+        runner.execute(Step::PackageKit, "PackageKit", || linux::run_packagekit(ctx))?;
+        runner.execute(Step::Protonup, "protonup", || linux::run_protonup_update(ctx))?;
+        runner.execute_with_report(Step::AppImage, "AppImage", || linux::run_appimage(ctx))?;
+    }
+
+    if let Some(binaries) = config.self_update_binaries() {
+        for (binary, args) in binaries {
+            runner.execute(Step::SelfUpdate, binary, || {
+                generic::run_self_update_binary(ctx, binary, args)
+            })?;
+        }
+    }
+
+    if let Some(commands) = config.commands() {
+        let mut succeeded = HashSet::new();
+        for (name, command) in config::commands_in_dependency_order(commands) {
+            if config.should_run_custom_command(name) {
+                let dependency_succeeded = command.depends_on().is_none_or(|d| succeeded.contains(d));
+                runner.execute(Step::CustomCommands, name, || {
+                    if !dependency_succeeded {
+                        return Err(SkipStep::custom(String::from("dependency failed/skipped")).into());
+                    }
+                    generic::run_custom_command(name, command, ctx)
+                })?;
+                if runner.report().satisfied(name) {
+                    succeeded.insert(name.as_str());
+                }
+            }
+        }
+    }
+
+    for plugin in plugins::load(base_dirs) {
+        if config.should_run_custom_command(plugin.name()) {
+            runner.execute(Step::Plugins, plugin.name().to_string(), || {
+                plugins::run_plugin(&plugin, ctx)
+            })?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        runner.execute(Step::System, "pihole", || linux::run_pihole_update(ctx))?;
+        runner.execute(Step::Firmware, "Firmware upgrades", || linux::run_fwupdmgr(ctx))?;
+        runner.execute(Step::Dkms, "DKMS", || linux::run_dkms(ctx))?;
+        runner.execute(Step::Restarts, "Restarts", || linux::run_needrestart(ctx))?;
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        runner.execute(Step::Sparkle, "Sparkle", || macos::run_sparkle(ctx))?;
+        runner.execute(Step::Mas, "App Store", || macos::run_mas(ctx))?;
+        runner.execute(Step::XcodeCommandLineTools, "Xcode Command Line Tools", || {
+            macos::run_xcode_command_line_tools(ctx)
+        })?;
+        runner.execute(Step::System, "System upgrade", || macos::upgrade_macos(ctx))?;
+    }
+
+    #[cfg(target_os = "freebsd")]
+    runner.execute(Step::System, "FreeBSD Upgrade", || freebsd::upgrade_freebsd(ctx))?;
+
+    #[cfg(windows)]
+    runner.execute(Step::System, "Windows update", || windows::windows_update(ctx))?;
+
+    if config.should_run(Step::Vagrant) {
+        if let Ok(boxes) = vagrant::collect_boxes(ctx) {
+            for vagrant_box in boxes {
+                runner.execute(Step::Vagrant, format!("Vagrant ({})", vagrant_box.smart_name()), || {
+                    vagrant::topgrade_vagrant_box(ctx, &vagrant_box)
+                })?;
+            }
+        }
+    }
+    runner.execute(Step::Vagrant, "Vagrant boxes", || vagrant::upgrade_vagrant_boxes(ctx))?;
+
+    Ok(Executed {
+        container_keys,
+        #[cfg(target_os = "linux")]
+        distribution,
+        #[cfg(target_os = "linux")]
+        snapshot: snapshot.into_inner(),
+    })
+}
+
+/// Print every step for `--list-steps`, with the detection of the ones which `plan` reached on this system
+fn print_step_list(config: &Config, plan: &[(Step, Cow<str>, String)]) {
+    for step in Step::iter() {
+        let name = step
+            .to_possible_value()
+            .map(|value| value.get_name())
+            .unwrap_or_default();
+        let mut planned = plan.iter().filter(|(planned, _, _)| *planned == step).peekable();
+        if planned.peek().is_none() {
+            println!(
+                "{}: {}, not available on this system or not configured",
+                name,
+                config.disabled_reason(step).unwrap_or("enabled")
+            );
+        }
+        for (_, key, status) in planned {
+            println!("{} ({}): {}", name, key, status);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! Lock preventing several Topgrade runs from upgrading the same system at once
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use directories::BaseDirs;
+use log::debug;
+
+use crate::ctrlc;
+use crate::terminal::print_info;
+
+/// How often a waiting run checks whether the lock was released
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Held for the whole run. The lock file stays, the lock on it is released by the system when it's dropped, even
+/// when topgrade is killed
+pub struct RunLock {
+    _file: File,
+}
+
+/// Lock the whole file, failing with `WouldBlock` when another process holds it
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<()> {
+    use nix::fcntl::{flock, FlockArg};
+    use std::os::unix::io::AsRawFd;
+
+    flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).map_err(|e| io::Error::from_raw_os_error(e as i32))
+}
+
+/// Lock a byte out of the contents, which are locked for the other processes on Windows and would hide the pid
+#[cfg(windows)]
+fn try_lock(file: &File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::shared::winerror::ERROR_LOCK_VIOLATION;
+    use winapi::um::fileapi::LockFileEx;
+    use winapi::um::minwinbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED};
+
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    unsafe { overlapped.u.s_mut().OffsetHigh = 1 };
+    let locked = unsafe {
+        LockFileEx(
+            file.as_raw_handle() as _,
+            LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            1,
+            0,
+            &mut overlapped,
+        )
+    };
+    if locked != 0 {
+        return Ok(());
+    }
+
+    match io::Error::last_os_error() {
+        e if e.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) => Err(io::ErrorKind::WouldBlock.into()),
+        e => Err(e),
+    }
+}
+
+impl RunLock {
+    /// Acquire the lock, waiting for the running instance to finish when `wait` is set.
+    ///
+    /// The lock dies with the process holding it, so there are no stale locks to take over.
+    pub fn acquire(base_dirs: &BaseDirs, wait: bool) -> Result<Self> {
+        let path = base_dirs
+            .runtime_dir()
+            .unwrap_or_else(|| base_dirs.data_dir())
+            .join("topgrade")
+            .join("topgrade.lock");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut waiting = false;
+        loop {
+            match try_lock(&file) {
+                Ok(()) => {
+                    // The pid is only there to tell the other runs who holds the lock
+                    file.set_len(0)?;
+                    file.seek(SeekFrom::Start(0))?;
+                    write!(file, "{}", std::process::id())?;
+                    file.flush()?;
+                    debug!("Acquired {}", path.display());
+                    return Ok(Self { _file: file });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                Err(e) => return Err(e.into()),
+            }
+
+            let mut contents = String::new();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_string(&mut contents).ok();
+            let holder = match contents.trim() {
+                "" => String::from("Another topgrade is running"),
+                pid => format!("Another topgrade is running (pid {})", pid),
+            };
+
+            if !wait {
+                return Err(anyhow!("{}. Use --wait-for-lock to wait for it", holder));
+            }
+
+            if !waiting {
+                print_info(format!("{}, waiting for it to finish", holder));
+                waiting = true;
+            }
+            thread::sleep(POLL_INTERVAL);
+            if ctrlc::interrupted() {
+                return Err(io::Error::from(io::ErrorKind::Interrupted).into());
+            }
+        }
+    }
+}
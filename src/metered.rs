@@ -0,0 +1,70 @@
+//! Whether the network connection is metered, checked before the steps by `[network]`
+#[cfg(any(target_os = "linux", windows))]
+use std::process::Command;
+
+#[cfg(any(target_os = "linux", windows))]
+use crate::executor::CommandExt;
+#[cfg(target_os = "linux")]
+use crate::utils::which;
+
+/// Whether the connection is metered, `None` when it can't be told
+#[cfg(target_os = "linux")]
+pub fn metered() -> Option<bool> {
+    let nmcli = which("nmcli")?;
+    let output = Command::new(nmcli)
+        .args(["--terse", "--fields", "GENERAL.STATE,GENERAL.METERED", "device", "show"])
+        .check_output()
+        .ok()?;
+    Some(nmcli_metered(&output))
+}
+
+/// Whether one of the connected devices of `nmcli device show` is metered, set by `connection.metered` or
+/// guessed by NetworkManager like for tethered phones
+#[cfg(any(target_os = "linux", test))]
+fn nmcli_metered(output: &str) -> bool {
+    let mut connected = false;
+    for line in output.lines() {
+        match line.split_once(':') {
+            Some(("GENERAL.STATE", state)) => connected = state.starts_with("100"),
+            Some(("GENERAL.METERED", metered)) if connected && metered.starts_with("yes") => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// The cost of the internet connection from the Windows Runtime: "Fixed" and "Variable" are metered
+#[cfg(windows)]
+const CONNECTION_COST: &str = "[Windows.Networking.Connectivity.NetworkInformation, Windows.Networking.Connectivity, \
+    ContentType = WindowsRuntime]::GetInternetConnectionProfile().GetConnectionCost().NetworkCostType";
+
+#[cfg(windows)]
+pub fn metered() -> Option<bool> {
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-Command", CONNECTION_COST])
+        .check_output()
+        .ok()?;
+    match output.trim() {
+        "Fixed" | "Variable" => Some(true),
+        "Unrestricted" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn metered() -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nmcli_metered() {
+        let output = "GENERAL.STATE:100 (connected)\nGENERAL.METERED:no (guessed)\n\nGENERAL.STATE:100 (connected)\nGENERAL.METERED:yes (guessed)\n";
+        assert!(nmcli_metered(output));
+        let output = "GENERAL.STATE:30 (disconnected)\nGENERAL.METERED:yes\n\nGENERAL.STATE:100 (connected)\nGENERAL.METERED:no\n";
+        assert!(!nmcli_metered(output));
+    }
+}
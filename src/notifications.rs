@@ -0,0 +1,395 @@
+//! What's sent when a run finishes: a desktop notification, a summary posted to a webhook, a message sent
+//! to ntfy, Gotify or Telegram and an email
+use std::fmt::Write as _;
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use serde::Serialize;
+use serde_json::json;
+use sys_info::hostname;
+use tempfile::NamedTempFile;
+
+use crate::config::{Email, EmailTls, Gotify, Ntfy, Telegram};
+use crate::execution_context::ExecutionContext;
+use crate::executor::ExecutorOutput;
+use crate::report::{Report, StepResult};
+use crate::terminal::{self, print_warning};
+use crate::utils;
+
+/// How long to wait for the webhook and the notification services to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The JSON posted to the webhook
+#[derive(Serialize, Debug, PartialEq)]
+struct Payload<'r> {
+    hostname: Option<String>,
+    success: bool,
+    succeeded: Vec<&'r str>,
+    failed: Vec<&'r str>,
+    ignored: Vec<&'r str>,
+}
+
+impl<'r> Payload<'r> {
+    fn new(report: &'r Report, hostname: Option<String>, success: bool) -> Self {
+        let steps = |wanted: fn(&StepResult) -> bool| {
+            report
+                .data()
+                .iter()
+                .filter(|(_, result)| wanted(result))
+                .map(|(key, _)| key.as_ref())
+                .collect()
+        };
+
+        Self {
+            hostname,
+            success,
+            succeeded: steps(|result| matches!(result, StepResult::Success)),
+            failed: steps(StepResult::failed),
+            ignored: steps(|result| matches!(result, StepResult::Ignored)),
+        }
+    }
+
+    /// The text of the desktop notification
+    fn message(&self) -> String {
+        if self.success {
+            return String::from("Topgrade finished successfully");
+        }
+
+        if self.failed.is_empty() {
+            // Only the post commands failed
+            String::from("Topgrade finished with errors")
+        } else {
+            format!("Topgrade finished with errors: {} failed", self.failed.join(", "))
+        }
+    }
+
+    /// The message sent to ntfy, Gotify and Telegram.
+    ///
+    /// In the template, `{hostname}`, `{status}`, `{succeeded}`, `{failed}` and `{ignored}` are replaced by
+    /// their values. Without a template, it's the text of the desktop notification with the hostname.
+    fn render(&self, template: Option<&str>) -> String {
+        let template = match template {
+            Some(template) => template,
+            None => {
+                return match &self.hostname {
+                    Some(hostname) => format!("{}: {}", hostname, self.message()),
+                    None => self.message(),
+                }
+            }
+        };
+
+        let steps = |steps: &[&str]| {
+            if steps.is_empty() {
+                String::from("none")
+            } else {
+                steps.join(", ")
+            }
+        };
+        template
+            .replace("{hostname}", self.hostname.as_deref().unwrap_or("unknown"))
+            .replace("{status}", if self.success { "successfully" } else { "with errors" })
+            .replace("{succeeded}", &steps(&self.succeeded))
+            .replace("{failed}", &steps(&self.failed))
+            .replace("{ignored}", &steps(&self.ignored))
+    }
+}
+
+/// Send a request with curl, failing when the server answers with an error
+fn curl(ctx: &ExecutionContext, args: &[&str]) -> Result<()> {
+    let curl = utils::require("curl")?;
+
+    let output = ctx
+        .run_type()
+        .execute(curl)
+        .args(["--silent", "--show-error", "--fail", "--max-time"])
+        .arg(WEBHOOK_TIMEOUT.as_secs().to_string())
+        .args(args)
+        .output()?;
+
+    match output {
+        ExecutorOutput::Wet(output) if !output.status.success() => {
+            Err(anyhow!("{}", String::from_utf8_lossy(&output.stderr).trim()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Send a request with curl, its options being in a config file so that the tokens and the passwords don't show
+/// up in the command line
+fn curl_config(ctx: &ExecutionContext, options: &[String]) -> Result<()> {
+    let mut config = NamedTempFile::new()?;
+    writeln!(config, "{}", options.join("\n"))?;
+    config.flush()?;
+
+    curl(ctx, &["--config", &config.path().to_string_lossy()])
+}
+
+fn post_json(ctx: &ExecutionContext, url: &str, headers: &[String], body: &impl Serialize) -> Result<()> {
+    let mut options = vec![
+        format!("header = {}", curl_config_value("Content-Type: application/json")),
+        format!("data = {}", curl_config_value(&serde_json::to_string(body)?)),
+    ];
+    options.extend(
+        headers
+            .iter()
+            .map(|header| format!("header = {}", curl_config_value(header))),
+    );
+    options.push(format!("url = {}", curl_config_value(url)));
+
+    curl_config(ctx, &options)
+}
+
+fn send_ntfy(ctx: &ExecutionContext, ntfy: &Ntfy, message: &str, success: bool) -> Result<()> {
+    let (priority, tags) = if success {
+        ("Priority: default", "Tags: heavy_check_mark")
+    } else {
+        ("Priority: high", "Tags: warning")
+    };
+
+    let mut options = vec![
+        format!("header = {}", curl_config_value("Title: Topgrade")),
+        format!("header = {}", curl_config_value(priority)),
+        format!("header = {}", curl_config_value(tags)),
+    ];
+    if let Some(token) = ntfy.token() {
+        options.push(format!(
+            "header = {}",
+            curl_config_value(&format!("Authorization: Bearer {}", token))
+        ));
+    }
+    options.push(format!("data = {}", curl_config_value(message)));
+    options.push(format!(
+        "url = {}",
+        curl_config_value(&format!("{}/{}", ntfy.server().trim_end_matches('/'), ntfy.topic()))
+    ));
+
+    curl_config(ctx, &options)
+}
+
+fn send_gotify(ctx: &ExecutionContext, gotify: &Gotify, message: &str, success: bool) -> Result<()> {
+    post_json(
+        ctx,
+        &format!("{}/message", gotify.url().trim_end_matches('/')),
+        &[format!("X-Gotify-Key: {}", gotify.token())],
+        &json!({
+            "title": "Topgrade",
+            "message": message,
+            // Gotify clients alert from priority 8
+            "priority": if success { 4 } else { 8 },
+        }),
+    )
+}
+
+fn send_telegram(ctx: &ExecutionContext, telegram: &Telegram, message: &str) -> Result<()> {
+    post_json(
+        ctx,
+        &format!("https://api.telegram.org/bot{}/sendMessage", telegram.bot_token()),
+        &[],
+        &json!({
+            "chat_id": telegram.chat_id(),
+            "text": message,
+        }),
+    )
+}
+
+/// The text of the email: the summary, with the warnings of the steps and their output if `with_output`
+fn email_body(report: &Report, with_output: bool) -> String {
+    let mut body = String::new();
+    for (key, result) in report.data() {
+        match result {
+            StepResult::Skipped(reason) => writeln!(body, "{}: {}: {}", key, result.status(), reason.message()),
+            _ => writeln!(body, "{}: {}", key, result.status()),
+        }
+        .ok();
+
+        if let Some(warnings) = report.warnings(key) {
+            for line in warnings.lines() {
+                writeln!(body, "  {}", line).ok();
+            }
+        }
+    }
+
+    let outputs = report
+        .data()
+        .iter()
+        .filter(|(_, result)| result.failed())
+        .filter_map(|(key, _)| report.output(key).map(|output| (key, output)));
+    for (key, output) in outputs.filter(|_| with_output) {
+        write!(body, "\n―― Output of {} ――\n{}", key, output).ok();
+        if !output.ends_with('\n') {
+            body.push('\n');
+        }
+    }
+
+    body
+}
+
+/// Quote a value of a curl config file
+fn curl_config_value(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+    )
+}
+
+/// Send the email with curl
+fn send_email(ctx: &ExecutionContext, email: &Email, domain: &str, subject: &str, body: &str) -> Result<()> {
+    let from = email
+        .from()
+        .ok_or_else(|| anyhow!("Set either from or username in [report.email]"))?;
+
+    let mut message = NamedTempFile::new()?;
+    write!(message, "From: {}\r\nTo: {}\r\n", from, email.to().join(", "))?;
+    write!(
+        message,
+        "Subject: {}\r\nDate: {}\r\n",
+        subject,
+        Local::now().to_rfc2822()
+    )?;
+    write!(
+        message,
+        "Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n"
+    )?;
+    for line in body.lines() {
+        write!(message, "{}\r\n", line)?;
+    }
+    message.flush()?;
+
+    let scheme = if email.tls() == EmailTls::Tls { "smtps" } else { "smtp" };
+    let mut options = vec![
+        // The path is the name announced to the server, and keeps curl from appending the name of the message file
+        format!(
+            "url = {}",
+            curl_config_value(&format!("{}://{}:{}/{}", scheme, email.host(), email.port(), domain))
+        ),
+        format!("mail-from = {}", curl_config_value(from)),
+        format!("upload-file = {}", curl_config_value(&message.path().to_string_lossy())),
+    ];
+    options.extend(
+        email
+            .to()
+            .iter()
+            .map(|to| format!("mail-rcpt = {}", curl_config_value(to))),
+    );
+    if email.tls() == EmailTls::Starttls {
+        options.push(String::from("ssl-reqd"));
+    }
+    if let Some((username, password)) = email.credentials() {
+        options.push(format!(
+            "user = {}",
+            curl_config_value(&format!("{}:{}", username, password))
+        ));
+    }
+
+    curl_config(ctx, &options)
+}
+
+/// Tell that the run finished, with the steps which failed
+pub fn run_finished(ctx: &ExecutionContext, report: &Report, success: bool) {
+    let config = ctx.config();
+    let payload = Payload::new(report, hostname().ok(), success);
+
+    if !config.skip_notify() {
+        terminal::notify_desktop(payload.message(), None);
+    }
+
+    let warn = |destination: &str, result: Result<()>| {
+        if let Err(e) = result {
+            print_warning(format!("Failed to send the notification to {}: {}", destination, e));
+        }
+    };
+
+    if let Some(url) = config.notification_webhook() {
+        warn("the webhook", post_json(ctx, url, &[], &payload));
+    }
+
+    if let Some(email) = config.report_email() {
+        let hostname = payload.hostname.as_deref().unwrap_or("localhost");
+        let subject = format!(
+            "Topgrade on {} finished {}",
+            hostname,
+            if success { "successfully" } else { "with errors" }
+        );
+        let body = email_body(report, email.output());
+        warn("email", send_email(ctx, email, hostname, &subject, &body));
+    }
+
+    if success && config.notify_only_failures() {
+        return;
+    }
+
+    let message = payload.render(config.notification_template());
+    if let Some(ntfy) = config.ntfy() {
+        warn("ntfy", send_ntfy(ctx, ntfy, &message, success));
+    }
+    if let Some(gotify) = config.gotify() {
+        warn("Gotify", send_gotify(ctx, gotify, &message, success));
+    }
+    if let Some(telegram) = config.telegram() {
+        warn("Telegram", send_telegram(ctx, telegram, &message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::StepReport;
+
+    #[test]
+    fn test_payload() {
+        let mut report = Report::new();
+        report.push_result(Some(("cargo", StepResult::Success)));
+        report.push_result(Some(("npm", StepResult::Failure)));
+        report.push_result(Some(("pip3", StepResult::Ignored)));
+        report.push_result(Some(("flatpak", StepResult::Failure)));
+
+        let payload = Payload::new(&report, Some(String::from("host")), false);
+        assert_eq!(payload.succeeded, ["cargo"]);
+        assert_eq!(payload.failed, ["npm", "flatpak"]);
+        assert_eq!(payload.ignored, ["pip3"]);
+        assert_eq!(payload.message(), "Topgrade finished with errors: npm, flatpak failed");
+        assert_eq!(
+            serde_json::to_string(&payload).unwrap(),
+            r#"{"hostname":"host","success":false,"succeeded":["cargo"],"failed":["npm","flatpak"],"ignored":["pip3"]}"#
+        );
+
+        assert_eq!(
+            payload.render(None),
+            "host: Topgrade finished with errors: npm, flatpak failed"
+        );
+        assert_eq!(
+            payload.render(Some(
+                "{hostname} finished {status}. Failed: {failed}, ignored: {ignored}"
+            )),
+            "host finished with errors. Failed: npm, flatpak, ignored: pip3"
+        );
+        let payload = Payload::new(&report, None, true);
+        assert_eq!(payload.render(Some("{hostname} {status}")), "unknown successfully");
+    }
+
+    #[test]
+    fn test_email_body() {
+        let mut report = Report::new();
+        report.push_result(Some(("cargo", StepResult::Success)));
+        report.push_step_report("apt", StepReport::with_warnings(String::from("E: Broken packages")));
+        report.push_output("apt", String::from("Reading package lists...\nE: Broken packages"));
+        report.push_result(Some(("apt", StepResult::Failure)));
+
+        assert_eq!(
+            email_body(&report, false),
+            "cargo: OK\napt: FAILED\n  E: Broken packages\n"
+        );
+        assert_eq!(
+            email_body(&report, true),
+            "cargo: OK\napt: FAILED\n  E: Broken packages\n\n―― Output of apt ――\nReading package lists...\nE: Broken packages\n"
+        );
+        assert_eq!(curl_config_value(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(curl_config_value("a\nb"), r#""a\nb""#);
+    }
+}
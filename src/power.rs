@@ -0,0 +1,100 @@
+//! Whether the machine runs on battery power, checked before the steps by `[power]`
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "macos")]
+use std::process::Command;
+
+#[cfg(target_os = "macos")]
+use crate::executor::CommandExt;
+
+/// Whether the machine runs on battery power, `None` when it can't be told
+#[cfg(target_os = "linux")]
+pub fn on_battery() -> Option<bool> {
+    let mut supplies = Vec::new();
+    for entry in fs::read_dir("/sys/class/power_supply").ok()?.flatten() {
+        let read = |name: &str| {
+            fs::read_to_string(entry.path().join(name))
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        supplies.push((read("type"), read("online"), read("status")));
+    }
+
+    Some(linux_on_battery(supplies.iter().map(|(kind, online, status)| {
+        (kind.as_str(), online.as_str(), status.as_str())
+    })))
+}
+
+/// Whether the power supplies of sysfs, by type, online and status, leave the machine on battery: one of the
+/// batteries discharges and no charger is online. Desktops have no battery
+#[cfg(any(target_os = "linux", test))]
+fn linux_on_battery<'a>(supplies: impl Iterator<Item = (&'a str, &'a str, &'a str)>) -> bool {
+    let mut discharging = false;
+    for (kind, online, status) in supplies {
+        match kind {
+            "Battery" => discharging |= status == "Discharging",
+            _ if online == "1" => return false,
+            _ => (),
+        }
+    }
+    discharging
+}
+
+#[cfg(target_os = "macos")]
+pub fn on_battery() -> Option<bool> {
+    let output = Command::new("pmset").args(["-g", "batt"]).check_output().ok()?;
+    pmset_on_battery(&output)
+}
+
+/// Whether `pmset -g batt` draws from the battery, from its first line like "Now drawing from 'AC Power'"
+#[cfg(any(target_os = "macos", test))]
+fn pmset_on_battery(output: &str) -> Option<bool> {
+    let source = output.lines().next()?.strip_prefix("Now drawing from ")?;
+    Some(source.trim().trim_matches('\'') == "Battery Power")
+}
+
+#[cfg(windows)]
+pub fn on_battery() -> Option<bool> {
+    use winapi::um::winbase::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    let mut status: SYSTEM_POWER_STATUS = unsafe { std::mem::zeroed() };
+    if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+        return None;
+    }
+
+    // 0 is offline, 1 online and 255 unknown
+    match status.ACLineStatus {
+        0 => Some(true),
+        1 => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn on_battery() -> Option<bool> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linux_on_battery() {
+        let laptop = |online, status| vec![("Mains", online, ""), ("Battery", "", status)].into_iter();
+        assert!(linux_on_battery(laptop("0", "Discharging")));
+        assert!(!linux_on_battery(laptop("1", "Charging")));
+        assert!(!linux_on_battery(laptop("1", "Discharging")));
+        assert!(!linux_on_battery(vec![("USB", "0", "")].into_iter()));
+    }
+
+    #[test]
+    fn test_pmset_on_battery() {
+        assert_eq!(
+            pmset_on_battery("Now drawing from 'Battery Power'\n -InternalBattery-0 (id=1234)\t85%; discharging\n"),
+            Some(true)
+        );
+        assert_eq!(pmset_on_battery("Now drawing from 'AC Power'\n"), Some(false));
+        assert_eq!(pmset_on_battery(""), None);
+    }
+}
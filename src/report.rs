@@ -1,10 +1,20 @@
 use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::debug;
+use serde::Serialize;
+
+use crate::config::ReportFormat;
+use crate::error::SkipReason;
 
 pub enum StepResult {
     Success,
     Failure,
     Ignored,
-    Skipped(String),
+    Skipped(SkipReason),
 }
 
 impl StepResult {
@@ -14,17 +24,106 @@ impl StepResult {
             StepResult::Failure => true,
         }
     }
+
+    /// The status shown in the summary
+    pub fn status(&self) -> &'static str {
+        match self {
+            StepResult::Success => "OK",
+            StepResult::Failure => "FAILED",
+            StepResult::Ignored => "IGNORED",
+            StepResult::Skipped(_) => "SKIPPED",
+        }
+    }
+
+    /// The status in the machine readable report
+    fn report_status(&self) -> &'static str {
+        match self {
+            StepResult::Success => "success",
+            StepResult::Failure => "failure",
+            StepResult::Ignored => "ignored",
+            StepResult::Skipped(_) => "skipped",
+        }
+    }
+
+    /// Whether the step was skipped because its tool isn't installed
+    pub fn skipped_not_installed(&self) -> bool {
+        matches!(self, StepResult::Skipped(SkipReason::NotInstalled(_)))
+    }
+}
+
+/// Additional information a step can hand over to the summary
+#[derive(Default)]
+pub struct StepReport {
+    changes: Option<String>,
+    warnings: Option<String>,
+    details: Option<serde_json::Value>,
+}
+
+impl StepReport {
+    pub fn new(changes: Option<String>, warnings: Option<String>) -> Self {
+        Self {
+            changes,
+            warnings,
+            details: None,
+        }
+    }
+
+    /// Add machine readable details, which are kept in the step records of the run
+    pub fn details(self, details: Option<serde_json::Value>) -> Self {
+        Self { details, ..self }
+    }
+
+    /// A report with a short description of what the step changed
+    pub fn with_changes(changes: String) -> Self {
+        Self::new(Some(changes), None)
+    }
+
+    /// A report with advisory warnings which don't make the step fail
+    pub fn with_warnings(warnings: String) -> Self {
+        Self::new(None, Some(warnings))
+    }
 }
 
 type CowString<'a> = Cow<'a, str>;
 type ReportData<'a> = Vec<(CowString<'a>, StepResult)>;
 pub struct Report<'a> {
     data: ReportData<'a>,
+    changes: Vec<(CowString<'a>, String)>,
+    warnings: Vec<(CowString<'a>, String)>,
+    details: Vec<(CowString<'a>, serde_json::Value)>,
+    durations: Vec<(CowString<'a>, Duration)>,
+    exit_codes: Vec<(CowString<'a>, i32)>,
+    outputs: Vec<(CowString<'a>, String)>,
+}
+
+/// The report written with `--report json`
+#[derive(Serialize)]
+struct ReportedRun<'r> {
+    steps: Vec<ReportedStep<'r>>,
+}
+
+/// One step in the report written with `--report json`
+#[derive(Serialize, Debug, PartialEq)]
+struct ReportedStep<'r> {
+    name: &'r str,
+    status: &'static str,
+    skip_reason: Option<&'r str>,
+    /// Duration of the step in seconds
+    duration: f64,
+    exit_code: Option<i32>,
 }
 
 impl<'a> Report<'a> {
     pub fn new() -> Self {
-        Self { data: Vec::new() }
+        Self {
+            data: Vec::new(),
+            changes: Vec::new(),
+            warnings: Vec::new(),
+            details: Vec::new(),
+            durations: Vec::new(),
+            exit_codes: Vec::new(),
+            outputs: Vec::new(),
+        }
     }
 
     pub fn push_result<M>(&mut self, result: Option<(M, StepResult)>)
@@ -39,7 +138,313 @@ impl<'a> Report<'a> {
         }
     }
 
+    pub fn push_step_report<M>(&mut self, key: M, step_report: StepReport)
+    where
+        M: Into<CowString<'a>>,
+    {
+        let key = key.into();
+
+        if let Some(changes) = step_report.changes {
+            self.changes.push((key.clone(), changes));
+        }
+
+        if let Some(details) = step_report.details {
+            self.details.push((key.clone(), details));
+        }
+
+        if let Some(warnings) = step_report.warnings {
+            self.warnings.push((key, warnings));
+        }
+    }
+
+    pub fn push_duration<M>(&mut self, key: M, duration: Duration)
+    where
+        M: Into<CowString<'a>>,
+    {
+        self.durations.push((key.into(), duration));
+    }
+
+    /// Record the exit code of the command which made the step fail
+    pub fn push_exit_code<M>(&mut self, key: M, exit_code: i32)
+    where
+        M: Into<CowString<'a>>,
+    {
+        self.exit_codes.push((key.into(), exit_code));
+    }
+
     pub fn data(&self) -> &ReportData<'a> {
         &self.data
     }
+
+    /// The changes reported by the given step
+    pub fn changes(&self, key: &str) -> Option<&str> {
+        self.changes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, changes)| changes.as_str())
+    }
+
+    /// The warnings reported by the given step
+    pub fn warnings(&self, key: &str) -> Option<&str> {
+        self.warnings
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, warnings)| warnings.as_str())
+    }
+
+    /// The machine readable details reported by the given step
+    pub fn details(&self, key: &str) -> Option<&serde_json::Value> {
+        self.details.iter().find(|(k, _)| k == key).map(|(_, details)| details)
+    }
+
+    /// How long the given step took
+    pub fn duration(&self, key: &str) -> Option<Duration> {
+        self.durations
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, duration)| *duration)
+    }
+
+    /// Whether the given step succeeded, in this run or in one of the runs which made it skip as already
+    /// succeeded
+    pub fn satisfied(&self, key: &str) -> bool {
+        self.data
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .is_some_and(|(_, result)| {
+                matches!(
+                    result,
+                    StepResult::Success | StepResult::Skipped(SkipReason::AlreadySucceeded(_))
+                )
+            })
+    }
+
+    /// Whether the given step failed
+    pub fn failed(&self, key: &str) -> bool {
+        self.data
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .is_some_and(|(_, result)| result.failed())
+    }
+
+    /// The exit code of the command which made the given step fail
+    pub fn exit_code(&self, key: &str) -> Option<i32> {
+        self.exit_codes
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, exit_code)| *exit_code)
+    }
+
+    /// Keep the output of a failed step, for the email of the summary
+    pub fn push_output<M>(&mut self, key: M, output: String)
+    where
+        M: Into<CowString<'a>>,
+    {
+        self.outputs.push((key.into(), output));
+    }
+
+    /// The output kept for the given step
+    pub fn output(&self, key: &str) -> Option<&str> {
+        self.outputs
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, output)| output.as_str())
+    }
+
+    fn reported_steps(&self) -> Vec<ReportedStep<'_>> {
+        self.data
+            .iter()
+            .map(|(key, result)| ReportedStep {
+                name: key,
+                status: result.report_status(),
+                skip_reason: match result {
+                    StepResult::Skipped(reason) => Some(reason.message()),
+                    _ => None,
+                },
+                duration: self.duration(key).unwrap_or_default().as_secs_f64(),
+                exit_code: self.exit_code(key),
+            })
+            .collect()
+    }
+
+    /// The results of the steps which ran in the Prometheus text format, for a run started at `timestamp`
+    fn metrics(&self, timestamp: i64) -> String {
+        // Skipped steps didn't run, so they have no results
+        let ran: Vec<_> = self
+            .data
+            .iter()
+            .filter(|(_, result)| !matches!(result, StepResult::Skipped(_)))
+            .map(|(key, result)| {
+                let label = key.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+                (key, label, result)
+            })
+            .collect();
+
+        let mut metrics = String::from(
+            "# HELP topgrade_step_duration_seconds How long the step took in the last run\n\
+             # TYPE topgrade_step_duration_seconds gauge\n",
+        );
+        for (key, label, _) in &ran {
+            let duration = self.duration(key).unwrap_or_default();
+            metrics.push_str(&format!(
+                "topgrade_step_duration_seconds{{step=\"{}\"}} {}\n",
+                label,
+                duration.as_secs_f64()
+            ));
+        }
+
+        metrics.push_str(
+            "# HELP topgrade_step_success Whether the step succeeded in the last run\n\
+             # TYPE topgrade_step_success gauge\n",
+        );
+        for (_, label, result) in &ran {
+            let success = matches!(result, StepResult::Success) as u8;
+            metrics.push_str(&format!("topgrade_step_success{{step=\"{}\"}} {}\n", label, success));
+        }
+
+        metrics.push_str(&format!(
+            "# HELP topgrade_last_run_timestamp When the last run started, in seconds since the epoch\n\
+             # TYPE topgrade_last_run_timestamp gauge\n\
+             topgrade_last_run_timestamp {}\n",
+            timestamp
+        ));
+
+        metrics
+    }
+
+    /// Write the metrics of a run started at `timestamp` to `path`. The file is replaced at once, so that the
+    /// textfile collector never reads it half written
+    pub fn write_metrics(&self, path: &Path, timestamp: i64) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+
+        // The collector only reads the files ending with .prom
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.metrics(timestamp))?;
+        fs::rename(&temporary, path)?;
+        debug!("Wrote the metrics to {}", path.display());
+
+        Ok(())
+    }
+
+    /// Write the report of the run in the given format, to `path` or to stdout
+    pub fn write(&self, format: ReportFormat, path: Option<&Path>) -> Result<()> {
+        let contents = match format {
+            ReportFormat::Json => serde_json::to_string_pretty(&ReportedRun {
+                steps: self.reported_steps(),
+            })?,
+        };
+
+        match path {
+            Some(path) => {
+                if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(path, contents + "\n")?;
+                debug!("Wrote the report to {}", path.display());
+            }
+            None => println!("{}", contents),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let mut report = Report::new();
+        report.push_result(Some(("git", StepResult::Success)));
+        report.push_duration("git", Duration::from_millis(1500));
+        report.push_result(Some(("Custom \"cmd\"", StepResult::Failure)));
+        report.push_result(Some((
+            "pearl",
+            StepResult::Skipped(SkipReason::Offline(String::from("offline"))),
+        )));
+
+        assert_eq!(
+            report.metrics(1700000000),
+            "# HELP topgrade_step_duration_seconds How long the step took in the last run\n\
+             # TYPE topgrade_step_duration_seconds gauge\n\
+             topgrade_step_duration_seconds{step=\"git\"} 1.5\n\
+             topgrade_step_duration_seconds{step=\"Custom \\\"cmd\\\"\"} 0\n\
+             # HELP topgrade_step_success Whether the step succeeded in the last run\n\
+             # TYPE topgrade_step_success gauge\n\
+             topgrade_step_success{step=\"git\"} 1\n\
+             topgrade_step_success{step=\"Custom \\\"cmd\\\"\"} 0\n\
+             # HELP topgrade_last_run_timestamp When the last run started, in seconds since the epoch\n\
+             # TYPE topgrade_last_run_timestamp gauge\n\
+             topgrade_last_run_timestamp 1700000000\n"
+        );
+    }
+
+    #[test]
+    fn test_satisfied() {
+        let mut report = Report::new();
+        report.push_result(Some(("sync", StepResult::Success)));
+        report.push_result(Some((
+            "fetch",
+            StepResult::Skipped(SkipReason::AlreadySucceeded(String::from("recently updated"))),
+        )));
+        report.push_result(Some((
+            "build",
+            StepResult::Skipped(SkipReason::Custom(String::from("dependency failed/skipped"))),
+        )));
+        report.push_result(Some(("check", StepResult::Failure)));
+
+        assert!(report.satisfied("sync"));
+        assert!(report.satisfied("fetch"));
+        assert!(!report.satisfied("build"));
+        assert!(!report.satisfied("check"));
+        assert!(!report.satisfied("missing"));
+    }
+
+    #[test]
+    fn test_reported_steps() {
+        let mut report = Report::new();
+        report.push_result(Some(("git", StepResult::Success)));
+        report.push_duration("git", Duration::from_millis(1500));
+        report.push_result(Some(("apt", StepResult::Failure)));
+        report.push_exit_code("apt", 100);
+        report.push_result(Some((
+            "pearl",
+            StepResult::Skipped(SkipReason::NotInstalled(String::from("Cannot find \"pearl\" in PATH"))),
+        )));
+
+        assert_eq!(
+            report.reported_steps(),
+            [
+                ReportedStep {
+                    name: "git",
+                    status: "success",
+                    skip_reason: None,
+                    duration: 1.5,
+                    exit_code: None,
+                },
+                ReportedStep {
+                    name: "apt",
+                    status: "failure",
+                    skip_reason: None,
+                    duration: 0.0,
+                    exit_code: Some(100),
+                },
+                ReportedStep {
+                    name: "pearl",
+                    status: "skipped",
+                    skip_reason: Some("Cannot find \"pearl\" in PATH"),
+                    duration: 0.0,
+                    exit_code: None,
+                },
+            ]
+        );
+    }
 }
@@ -1,23 +1,116 @@
+use crate::config::{FailurePolicy, Step, SummarySkipDisplay};
 use crate::ctrlc;
-use crate::error::{DryRun, SkipStep};
+use crate::error::{DryRun, FailedWithOutput, SkipReason, SkipStep, TimedOut, TopgradeError};
 use crate::execution_context::ExecutionContext;
-use crate::report::{Report, StepResult};
-use crate::{config::Step, terminal::should_retry};
+use crate::executor;
+use crate::history::StepRecord;
+use crate::report::{Report, StepReport, StepResult};
+use crate::state::State;
+use crate::steps::generic;
+use crate::steps::registry::{Privileges, UpdateStep};
+use crate::terminal::CapturedOutput;
+use crate::terminal::{capture_output, print_info, print_warning, send_live, should_retry, LiveEvent};
+use crate::utils::which;
 use anyhow::Result;
-use log::debug;
+use log::{debug, error};
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::fmt::Debug;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The result of a step run by `Runner::execute_parallel`, with its held back output
+type Outcome = (Result<StepReport>, CapturedOutput);
+
+/// The exit code of the command which made a step fail
+fn exit_code(error: &anyhow::Error) -> Option<i32> {
+    error.chain().find_map(|cause| {
+        if let Some(TopgradeError::ProcessFailed(status) | TopgradeError::ProcessFailedWithOutput(status, _)) =
+            cause.downcast_ref()
+        {
+            status.code()
+        } else {
+            cause
+                .downcast_ref::<FailedWithOutput>()
+                .and_then(|failed| failed.exit_code)
+        }
+    })
+}
+
+/// The tools of `step` that `key` is about: the one it's named after, like npm for the npm step of Node, or the
+/// ones in its name, like zsh for oh-my-zsh. All of them otherwise
+fn candidate_tools(step: Step, key: &str) -> Vec<&'static str> {
+    let key = key.to_lowercase();
+    let tools = step.tools();
+    if let Some(tool) = tools.iter().find(|tool| key == **tool) {
+        return vec![tool];
+    }
+
+    let named: Vec<&str> = tools.iter().copied().filter(|tool| key.contains(tool)).collect();
+    if named.is_empty() {
+        tools.to_vec()
+    } else {
+        named
+    }
+}
+
+/// Whether the tools of the step are installed, for `--list-steps`, without running it
+fn detect(step: Step, key: &str) -> Option<String> {
+    let candidates = candidate_tools(step, key);
+    if candidates.is_empty() {
+        return None;
+    }
+
+    Some(match candidates.iter().find(|tool| which(tool).is_some()) {
+        Some(tool) => format!("detected ({})", tool),
+        None => format!("not detected, {} isn't installed", candidates.join(" or ")),
+    })
+}
+
+/// Run `step`, then its cleanup when enabled
+fn update(step: &dyn UpdateStep, ctx: &ExecutionContext) -> Result<StepReport> {
+    let report = step.run_with_report(ctx)?;
+    if ctx.config().cleanup() {
+        step.cleanup(ctx)?;
+    }
+    Ok(report)
+}
+
+/// Wait before retrying a failed step automatically. Returns false when interrupted in the meantime
+fn wait_for_retry(delay: Duration) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < delay {
+        if ctrlc::interrupted() {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(100).min(delay.saturating_sub(started.elapsed())));
+    }
+
+    !ctrlc::interrupted()
+}
 
 pub struct Runner<'a> {
     ctx: &'a ExecutionContext<'a>,
     report: Report<'a>,
+    state: State,
+    aborted: bool,
+    /// Whether the steps are only recorded in the plan, like with `--print-steps`
+    planning: bool,
+    plan: Vec<(Step, Cow<'a, str>, String)>,
+    on_step: &'a mut dyn FnMut(&StepRecord),
 }
 
 impl<'a> Runner<'a> {
-    pub fn new(ctx: &'a ExecutionContext) -> Runner<'a> {
+    pub fn new(ctx: &'a ExecutionContext, on_step: &'a mut dyn FnMut(&StepRecord)) -> Runner<'a> {
         Runner {
             ctx,
             report: Report::new(),
+            state: State::load(ctx.base_dirs()),
+            aborted: false,
+            planning: ctx.config().print_steps(),
+            plan: Vec::new(),
+            on_step,
         }
     }
 
@@ -26,28 +119,238 @@ impl<'a> Runner<'a> {
         F: Fn() -> Result<()>,
         M: Into<Cow<'a, str>> + Debug,
     {
-        if !self.ctx.config().should_run(step) {
+        self.execute_with_report(step, key, || func().map(|()| StepReport::default()))
+    }
+
+    /// Runs each detected step of a registry, in order
+    pub fn execute_steps(&mut self, steps: &[Box<dyn UpdateStep>]) -> Result<()> {
+        for step in steps {
+            if step.detect(self.ctx) {
+                let ctx = self.ctx;
+                self.execute_with_report(step.step(), String::from(step.name()), || update(step.as_ref(), ctx))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like `execute_steps`, but runs up to `max_concurrency` steps at the same time.
+    ///
+    /// Steps using the same resource run one after the other, and the ones using sudo run on their own after
+    /// the others. The output of each step is held back and printed once they all finished, in order.
+    /// Retrying a failed step runs it again on its own
+    pub fn execute_parallel(&mut self, steps: &[Box<dyn UpdateStep>], max_concurrency: usize) -> Result<()> {
+        if max_concurrency <= 1 {
+            return self.execute_steps(steps);
+        }
+
+        let ctx = self.ctx;
+        let (sudo_steps, steps): (Vec<&dyn UpdateStep>, Vec<&dyn UpdateStep>) = steps
+            .iter()
+            .map(|step| step.as_ref())
+            .filter(|step| step.detect(ctx))
+            .partition(|step| step.privileges() == Privileges::Sudo);
+
+        // Only start the steps that `execute_with_report` would run
+        let config = ctx.config();
+        let should_start = |step: &dyn UpdateStep| {
+            let offline = ctx.offline_reason(step.step()).is_some();
+            let constrained = ctx.constrained(step.step()).is_some();
+            let already_succeeded = self.already_succeeded(step.name()).is_some();
+            !(self.aborted
+                || self.planning
+                || !config.should_run(step.step())
+                || offline
+                || constrained
+                || already_succeeded)
+        };
+
+        // Each group of steps runs in order on one thread
+        let mut groups: Vec<(Option<&'static str>, Vec<usize>)> = Vec::new();
+        for (index, step) in steps.iter().enumerate().filter(|(_, step)| should_start(**step)) {
+            match groups
+                .iter_mut()
+                .find(|(resource, _)| resource.is_some() && *resource == step.resource())
+            {
+                Some((_, group)) => group.push(index),
+                None => groups.push((step.resource(), vec![index])),
+            }
+        }
+
+        let outcomes: Vec<Mutex<Option<Outcome>>> = steps.iter().map(|_| Mutex::new(None)).collect();
+        let queue = Mutex::new(groups.into_iter().map(|(_, group)| group).collect::<VecDeque<_>>());
+        thread::scope(|scope| {
+            for _ in 0..max_concurrency.min(queue.lock().unwrap().len()) {
+                scope.spawn(|| loop {
+                    let group = match queue.lock().unwrap().pop_front() {
+                        Some(group) => group,
+                        None => break,
+                    };
+                    for index in group {
+                        send_live(|| LiveEvent::Started(steps[index].name().to_string()));
+                        let timeout = config.step_timeout(steps[index].step());
+                        let outcome = capture_output(|| executor::with_timeout(timeout, || update(steps[index], ctx)));
+                        *outcomes[index].lock().unwrap() = Some(outcome);
+                    }
+                });
+            }
+        });
+
+        for (step, outcome) in steps.into_iter().zip(outcomes) {
+            self.execute_with_report(step.step(), String::from(step.name()), || {
+                match outcome.lock().unwrap().take() {
+                    Some((result, output)) => {
+                        output.print();
+                        result
+                    }
+                    None => update(step, ctx),
+                }
+            })?;
+        }
+
+        for step in sudo_steps {
+            self.execute_with_report(step.step(), String::from(step.name()), || update(step, ctx))?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `execute`, but for steps that can report what they changed
+    pub fn execute_with_report<F, M>(&mut self, step: Step, key: M, func: F) -> Result<()>
+    where
+        F: Fn() -> Result<StepReport>,
+        M: Into<Cow<'a, str>> + Debug,
+    {
+        if self.planning {
+            let key = key.into();
+            let detection = if self.ctx.config().list_steps() {
+                detect(step, &key)
+            } else {
+                None
+            };
+            self.plan_step(step, key, detection);
+            return Ok(());
+        }
+
+        if self.aborted || !self.ctx.config().should_run(step) {
             return Ok(());
         }
 
         let key = key.into();
+        let succeeded = self.run_step(step, key.clone(), func)?;
+
+        if let Some(command) = self.ctx.config().post_app_update(step).filter(|_| succeeded) {
+            let ctx = self.ctx;
+            let name = format!("After {}", key);
+            self.run_step(step, Cow::Owned(name.clone()), || {
+                generic::run_custom_command(&name, command, ctx).map(|()| StepReport::default())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Run an enabled step, retrying it as the user asks. Returns whether it succeeded
+    fn run_step<F>(&mut self, step: Step, key: Cow<'a, str>, func: F) -> Result<bool>
+    where
+        F: Fn() -> Result<StepReport>,
+    {
         debug!("Step {:?}", key);
+        send_live(|| LiveEvent::Started(key.to_string()));
+
+        if let Some(reason) = self.ctx.offline_reason(step) {
+            self.report.push_result(Some((
+                key.clone(),
+                StepResult::Skipped(SkipReason::Offline(String::from(reason))),
+            )));
+            self.finish_step(key, Duration::ZERO);
+            return Ok(false);
+        }
+
+        if let Some(reason) = self.ctx.constrained(step) {
+            self.report.push_result(Some((
+                key.clone(),
+                StepResult::Skipped(SkipReason::Disabled(String::from(reason))),
+            )));
+            self.finish_step(key, Duration::ZERO);
+            return Ok(false);
+        }
 
+        if let Some(reason) = self.already_succeeded(&key) {
+            debug!("Step {:?} already succeeded: {}", key, reason);
+            self.report.push_result(Some((
+                key.clone(),
+                StepResult::Skipped(SkipReason::AlreadySucceeded(String::from(reason))),
+            )));
+            self.finish_step(key, Duration::ZERO);
+            return Ok(false);
+        }
+
+        let started = Instant::now();
+        let duration_key = key.clone();
+        let mut succeeded = false;
+        let timeout = self.ctx.config().step_timeout(step);
+        let func = || executor::with_timeout(timeout, &func);
+        let retries = self.ctx.config().step_retries(step);
+        let mut retries_left = retries;
+        let keep_output = self.ctx.config().report_email().is_some_and(|email| email.output());
+        let mut failed_output = None;
         loop {
-            match func() {
-                Ok(()) => {
+            let result = if self.ctx.config().errors_only() {
+                let (result, output) = capture_output(func);
+                if let Err(e) = &result {
+                    if e.downcast_ref::<SkipStep>().is_none() && e.downcast_ref::<DryRun>().is_none() {
+                        if keep_output {
+                            failed_output = Some(output.text());
+                        }
+                        output.print();
+                    }
+                }
+                result
+            } else {
+                func()
+            };
+
+            match result {
+                Ok(step_report) => {
+                    if !self.ctx.run_type().dry() {
+                        if let Err(e) = self.state.record_success(&key) {
+                            error!("Failed to save the state file: {}", e);
+                        }
+                    }
+                    self.report.push_step_report(key.clone(), step_report);
                     self.report.push_result(Some((key, StepResult::Success)));
+                    succeeded = true;
                     break;
                 }
                 Err(e) if e.downcast_ref::<DryRun>().is_some() => break,
                 Err(e) if e.downcast_ref::<SkipStep>().is_some() => {
-                    if self.ctx.config().verbose() || self.ctx.config().show_skipped() {
-                        self.report.push_result(Some((key, StepResult::Skipped(e.to_string()))));
+                    // Skipped steps are only kept when they're shown somewhere
+                    if self.ctx.config().summary_skip_display() != SummarySkipDisplay::None
+                        || self.ctx.config().report_format().is_some()
+                    {
+                        let SkipStep(reason) = e.downcast().unwrap();
+                        self.report.push_result(Some((key, StepResult::Skipped(reason))));
                     }
                     break;
                 }
                 Err(e) => {
                     debug!("Step {:?} failed: {:?}", key, e);
+                    if retries_left > 0 && !ctrlc::interrupted() {
+                        retries_left -= 1;
+                        let delay = self.ctx.config().step_retry_delay(step);
+                        print_warning(format!(
+                            "{} failed, retrying in {} (retry {} of {})",
+                            key,
+                            humantime::format_duration(delay),
+                            retries - retries_left,
+                            retries
+                        ));
+                        if wait_for_retry(delay) {
+                            continue;
+                        }
+                    }
+
                     let interrupted = ctrlc::interrupted();
                     if interrupted {
                         ctrlc::unset_interrupted();
@@ -58,6 +361,31 @@ impl<'a> Runner<'a> {
                     let should_retry = should_ask && should_retry(interrupted, key.as_ref())?;
 
                     if !should_retry {
+                        if let Some(exit_code) = exit_code(&e) {
+                            self.report.push_exit_code(key.clone(), exit_code);
+                        }
+                        if let Some(output) = failed_output.take() {
+                            self.report.push_output(key.clone(), output);
+                        }
+
+                        if let Some(timed_out) = e.downcast_ref::<TimedOut>() {
+                            self.report
+                                .push_step_report(key.clone(), StepReport::with_warnings(timed_out.to_string()));
+                        } else if let Some(failed) = e.downcast_ref::<FailedWithOutput>() {
+                            let warnings = if failed.output.is_empty() {
+                                failed.message.clone()
+                            } else {
+                                format!("{}\n{}", failed.message, failed.output)
+                            };
+                            self.report
+                                .push_step_report(key.clone(), StepReport::with_warnings(warnings));
+                        }
+
+                        if !ignore_failure && self.ctx.config().failure_policy(step) == FailurePolicy::Abort {
+                            print_warning(format!("{} failed. Aborting the rest of the run", key));
+                            self.aborted = true;
+                        }
+
                         self.report.push_result(Some((
                             key,
                             if ignore_failure {
@@ -71,11 +399,92 @@ impl<'a> Runner<'a> {
                 }
             }
         }
+        self.finish_step(duration_key, started.elapsed());
 
-        Ok(())
+        Ok(succeeded)
+    }
+
+    /// Why the step doesn't need to run again, if it succeeded recently or in the run being resumed
+    fn already_succeeded(&self, key: &str) -> Option<&'static str> {
+        if self.state.succeeded_in_resumed_run(key) {
+            return Some("succeeded in the resumed run");
+        }
+
+        self.ctx
+            .config()
+            .min_step_interval()
+            .filter(|interval| self.state.succeeded_within(key, *interval))
+            .map(|_| "recently updated")
+    }
+
+    /// Record the start of the run, resuming the last unfinished one with `--resume`
+    pub fn start_run(&mut self) {
+        match self.state.start_run(self.ctx.config().resume()) {
+            Ok(false) if self.ctx.config().resume() => print_info("No unfinished run to resume, running all the steps"),
+            Ok(_) => (),
+            Err(e) => error!("Failed to save the state file: {}", e),
+        }
+    }
+
+    /// Record that the run completed, so that there's nothing left to resume
+    pub fn finish_run(&mut self) {
+        if let Err(e) = self.state.finish_run() {
+            error!("Failed to save the state file: {}", e);
+        }
+    }
+
+    /// Record how long the step took and hand its outcome over to the callback
+    fn finish_step(&mut self, key: Cow<'a, str>, duration: Duration) {
+        let mut status = None;
+        if let Some((_, result)) = self.report.data().iter().rev().find(|(k, _)| *k == key) {
+            (self.on_step)(&StepRecord::new(&key, result, duration, self.report.details(&key)));
+            status = Some(result.status());
+        }
+        send_live(|| LiveEvent::Finished(key.to_string(), status));
+        self.report.push_duration(key, duration);
+    }
+
+    /// Tell whether a failing step aborted the run
+    pub fn aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Record the step with the reason it would not run instead of running it
+    fn plan_step(&mut self, step: Step, key: Cow<'a, str>, detection: Option<String>) {
+        let mut status = if let Some(reason) = self.ctx.config().disabled_reason(step) {
+            String::from(reason)
+        } else if let Some(reason) = self.already_succeeded(&key) {
+            String::from(reason)
+        } else {
+            String::from("enabled")
+        };
+        if let Some(detection) = detection {
+            status = format!("{}, {}", status, detection);
+        }
+
+        self.plan.push((step, key, status));
+    }
+
+    /// Steps collected while planning, in execution order
+    pub fn plan(&self) -> &[(Step, Cow<'a, str>, String)] {
+        &self.plan
     }
 
     pub fn report(&self) -> &Report {
         &self.report
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_tools() {
+        assert_eq!(candidate_tools(Step::Node, "npm"), ["npm"]);
+        assert_eq!(candidate_tools(Step::Shell, "oh-my-zsh"), ["zsh"]);
+        assert_eq!(candidate_tools(Step::Shell, "fisher"), ["fish"]);
+        assert_eq!(candidate_tools(Step::Containers, "Containers"), ["docker", "podman"]);
+        assert!(candidate_tools(Step::System, "System update").is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+//! When `topgrade daemon` runs the steps, from the `[schedule]` section of the configuration
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, Timelike};
+
+/// How far ahead to look for the next time matching a cron expression
+const CRON_HORIZON: i64 = 366 * 24 * 60;
+
+/// The values of each field of a cron expression it matches
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cron {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    // Like cron, a day matches either field when both the day of the month and the day of the week are set
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Parse one field of a cron expression: `*`, numbers, ranges and steps, separated by commas
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>> {
+    let mut values = Vec::new();
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>().map_err(|_| anyhow!("Invalid step {}", step))?,
+            ),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(anyhow!("Invalid step 0 in {}", part));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, min, max)?, parse_value(end, min, max)?)
+        } else {
+            let start = parse_value(range, min, max)?;
+            // `5/10` means every 10 from 5
+            (start, if part.contains('/') { max } else { start })
+        };
+        if start > end {
+            return Err(anyhow!("Invalid range {}", range));
+        }
+
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+fn parse_value(value: &str, min: u32, max: u32) -> Result<u32> {
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|value| (min..=max).contains(value))
+        .ok_or_else(|| anyhow!("Invalid value {}, expected {} to {}", value, min, max))
+}
+
+impl Cron {
+    /// Parse a cron expression with five fields: minute, hour, day of the month, month and day of the week
+    pub fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!(
+                "Invalid cron expression {:?}: expected minute, hour, day of month, month and day of week",
+                expression
+            ));
+        }
+
+        let field = |index: usize, min: u32, max: u32| {
+            parse_field(fields[index], min, max).map_err(|e| anyhow!("Invalid cron expression {:?}: {}", expression, e))
+        };
+        let mut weekdays = field(4, 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if weekdays.contains(&7) {
+            weekdays.retain(|weekday| *weekday != 7);
+            if !weekdays.contains(&0) {
+                weekdays.insert(0, 0);
+            }
+        }
+
+        Ok(Self {
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            weekdays,
+            // Like in cron, a field starting with `*` doesn't restrict the days, even with a step
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches(&self, time: &DateTime<Local>) -> bool {
+        let day = self.days.contains(&time.day());
+        let weekday = self.weekdays.contains(&time.weekday().num_days_from_sunday());
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        day_matches
+            && self.minutes.contains(&time.minute())
+            && self.hours.contains(&time.hour())
+            && self.months.contains(&time.month())
+    }
+
+    /// The first minute after `after` matching the expression
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+
+        (0..CRON_HORIZON)
+            .map(|minutes| start + ChronoDuration::minutes(minutes))
+            .find(|time| self.matches(time))
+    }
+}
+
+/// When to run the steps
+#[derive(Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// Right away, then every given duration
+    Interval(Duration),
+    /// At the times matching a cron expression
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// When to run next, given the time the previous run started
+    pub fn next_run(&self, previous: Option<DateTime<Local>>) -> Result<DateTime<Local>> {
+        match self {
+            Schedule::Interval(interval) => Ok(match previous {
+                Some(previous) => previous + ChronoDuration::from_std(*interval)?,
+                None => Local::now(),
+            }),
+            Schedule::Cron(cron) => cron
+                .next_after(Local::now())
+                .ok_or_else(|| anyhow!("The cron expression of the schedule never matches")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_cron() {
+        let cron = Cron::parse("*/15 3,4 1-5 * 7").unwrap();
+        assert_eq!(cron.minutes, [0, 15, 30, 45]);
+        assert_eq!(cron.hours, [3, 4]);
+        assert_eq!(cron.days, [1, 2, 3, 4, 5]);
+        assert_eq!(cron.months.len(), 12);
+        assert_eq!(cron.weekdays, [0]);
+
+        assert!(Cron::parse("0 3 * *").is_err());
+        assert!(Cron::parse("60 3 * * *").is_err());
+        assert!(Cron::parse("0 5-3 * * *").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let time = |day, hour, minute| Local.ymd(2024, 1, day).and_hms(hour, minute, 30);
+        let next = |expression: &str, after| Cron::parse(expression).unwrap().next_after(after).unwrap();
+
+        assert_eq!(
+            next("0 3 * * *", time(10, 2, 59)),
+            time(10, 3, 0).with_second(0).unwrap()
+        );
+        assert_eq!(
+            next("0 3 * * *", time(10, 3, 0)),
+            time(11, 3, 0).with_second(0).unwrap()
+        );
+        // 2024-01-13 is a Saturday
+        assert_eq!(
+            next("30 4 * * 6", time(10, 0, 0)),
+            time(13, 4, 30).with_second(0).unwrap()
+        );
+        // The day of the month or the day of the week
+        assert_eq!(
+            next("0 0 12 * 6", time(10, 0, 0)),
+            time(12, 0, 0).with_second(0).unwrap()
+        );
+        // The odd days of the month which are a Monday, 2024-01-15 being the first one after the 10th
+        assert_eq!(
+            next("0 3 */2 * 1", time(10, 0, 0)),
+            time(15, 3, 0).with_second(0).unwrap()
+        );
+    }
+}
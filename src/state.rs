@@ -0,0 +1,122 @@
+//! Persistent state kept between Topgrade runs
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use directories::BaseDirs;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq)]
+struct StateFile {
+    // Before the tables, which TOML can't have values after
+    /// Unix timestamp of the start of the last run which didn't complete, the one `--resume` continues
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unfinished_run: Option<i64>,
+    /// Unix timestamp of the last successful run of each step, keyed by the step's name
+    #[serde(default)]
+    last_success: BTreeMap<String, i64>,
+}
+
+pub struct State {
+    path: PathBuf,
+    state_file: StateFile,
+    /// Start of the run being resumed
+    resumed_run: Option<i64>,
+}
+
+impl State {
+    /// Load the state file from the data directory.
+    ///
+    /// A missing or unreadable state file results in an empty state.
+    pub fn load(base_dirs: &BaseDirs) -> Self {
+        let path = base_dirs.data_dir().join("topgrade").join("state.toml");
+
+        let state_file = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| {
+                toml::from_str(&contents)
+                    .map_err(|e| error!("Failed to deserialize {}: {}", path.display(), e))
+                    .ok()
+            })
+            .unwrap_or_default();
+        debug!("Loaded state from {}: {:?}", path.display(), state_file);
+
+        Self {
+            path,
+            state_file,
+            resumed_run: None,
+        }
+    }
+
+    /// Tell whether the step succeeded in the last `interval`
+    pub fn succeeded_within(&self, key: &str, interval: Duration) -> bool {
+        self.state_file
+            .last_success
+            .get(key)
+            .map(|timestamp| Utc::now().timestamp() - timestamp < interval.as_secs() as i64)
+            .unwrap_or(false)
+    }
+
+    /// Record a successful run of the step and save the state file
+    pub fn record_success(&mut self, key: &str) -> Result<()> {
+        self.state_file
+            .last_success
+            .insert(key.to_string(), Utc::now().timestamp());
+        self.save()
+    }
+
+    /// Record the start of a run, which continues the last unfinished run with `resume`.
+    ///
+    /// Returns whether there was an unfinished run to resume
+    pub fn start_run(&mut self, resume: bool) -> Result<bool> {
+        self.resumed_run = self.state_file.unfinished_run.filter(|_| resume);
+        // A resumed run which doesn't complete either is resumed from the same point the next time
+        self.state_file.unfinished_run = Some(self.resumed_run.unwrap_or_else(|| Utc::now().timestamp()));
+        self.save()?;
+        Ok(self.resumed_run.is_some())
+    }
+
+    /// Record that the run completed without failures, so that there's nothing to resume
+    pub fn finish_run(&mut self) -> Result<()> {
+        self.state_file.unfinished_run = None;
+        self.save()
+    }
+
+    /// Tell whether the step already succeeded in the run being resumed
+    pub fn succeeded_in_resumed_run(&self, key: &str) -> bool {
+        match (self.resumed_run, self.state_file.last_success.get(key)) {
+            (Some(started), Some(timestamp)) => *timestamp >= started,
+            _ => false,
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, toml::to_string(&self.state_file)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file() {
+        let state_file = StateFile {
+            unfinished_run: Some(1700000000),
+            last_success: vec![(String::from("Git repositories"), 1700000060)]
+                .into_iter()
+                .collect(),
+        };
+        let serialized = toml::to_string(&state_file).unwrap();
+
+        assert_eq!(toml::from_str::<StateFile>(&serialized).unwrap(), state_file);
+    }
+}
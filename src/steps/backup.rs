@@ -0,0 +1,65 @@
+use anyhow::{anyhow, Result};
+
+use crate::config::{Backup, BackupTool};
+use crate::execution_context::ExecutionContext;
+use crate::executor::Executor;
+use crate::terminal::print_separator;
+use crate::utils::which;
+
+/// The backup command, through sudo if the configuration asks for it
+fn execute(ctx: &ExecutionContext, backup: &Backup, tool: &str) -> Result<Executor> {
+    // The run aborts when the backup fails, so a missing tool is a failure rather than a reason to skip
+    let tool = which(tool).ok_or_else(|| anyhow!("{} is not installed", tool))?;
+
+    if backup.sudo() {
+        let sudo = ctx.require_sudo()?;
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg(tool);
+        Ok(command)
+    } else {
+        Ok(ctx.run_type().execute(tool))
+    }
+}
+
+fn restic(ctx: &ExecutionContext, backup: &Backup) -> Result<()> {
+    if backup.paths().is_empty() {
+        return Err(anyhow!("Set the paths to back up in [backup]"));
+    }
+
+    let mut command = execute(ctx, backup, "restic")?;
+    if let Some(repository) = backup.repository() {
+        command.args(["--repo", repository]);
+    }
+    if let Some(password_file) = backup.password_file() {
+        command.args(["--password-file", password_file]);
+    }
+    command.args(["backup", "--tag", "topgrade"]);
+    if let Some(arguments) = backup.arguments() {
+        command.args(arguments.split_whitespace());
+    }
+
+    command.args(backup.paths()).check_run()
+}
+
+fn borgmatic(ctx: &ExecutionContext, backup: &Backup) -> Result<()> {
+    let mut command = execute(ctx, backup, "borgmatic")?;
+    if let Some(config) = backup.config() {
+        command.args(["--config", config]);
+    }
+    command.args(["create", "--stats"]);
+    if let Some(arguments) = backup.arguments() {
+        command.args(arguments.split_whitespace());
+    }
+
+    command.check_run()
+}
+
+/// Make the backup of the `[backup]` configuration
+pub fn run_backup(ctx: &ExecutionContext, backup: &Backup) -> Result<()> {
+    print_separator("Backup");
+
+    match backup.tool() {
+        BackupTool::Restic => restic(ctx, backup),
+        BackupTool::Borgmatic => borgmatic(ctx, backup),
+    }
+}
@@ -1,6 +1,7 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
-use crate::error::{self, TopgradeError};
+use crate::config::{ContainerStep, ExecContainer, Step};
+use crate::error::{self, SkipStep, TopgradeError};
 use crate::executor::CommandExt;
 use crate::terminal::print_separator;
 use crate::{execution_context::ExecutionContext, utils::require};
@@ -107,3 +108,103 @@ pub fn run_containers(ctx: &ExecutionContext) -> Result<()> {
         Err(anyhow::anyhow!(error::StepFailed))
     }
 }
+
+/// The key of a step run in one of the `[containers.exec]` containers
+pub fn exec_key(name: &str, step: ContainerStep) -> String {
+    format!("{} in {}", step, name)
+}
+
+/// The first of the supported package managers installed in the container
+fn package_manager(crt: &Path, name: &str) -> Result<String> {
+    let found = Command::new(crt)
+        .args(["exec", name, "sh", "-c"])
+        .arg("for pm in apt-get dnf apk pacman zypper; do command -v $pm && break; done")
+        .string_output()?;
+
+    found
+        .lines()
+        .next()
+        .and_then(|path| Path::new(path.trim()).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!("No supported package manager in the container {}", name))
+}
+
+/// The commands upgrading the packages of `package_manager`
+fn upgrade_commands(package_manager: &str, yes: bool) -> Vec<Vec<&'static str>> {
+    let yes_flag = |flag| if yes { vec![flag] } else { vec![] };
+    match package_manager {
+        "apt-get" => vec![
+            vec!["apt-get", "update"],
+            [vec!["apt-get", "dist-upgrade"], yes_flag("-y")].concat(),
+        ],
+        "dnf" => vec![[vec!["dnf", "upgrade"], yes_flag("-y")].concat()],
+        "apk" => vec![vec!["apk", "upgrade", "--update-cache"]],
+        "pacman" => vec![[vec!["pacman", "-Syu"], yes_flag("--noconfirm")].concat()],
+        "zypper" => vec![[vec!["zypper"], yes_flag("--non-interactive"), vec!["update"]].concat()],
+        _ => vec![],
+    }
+}
+
+/// Run `step` in the container `name` of `[containers.exec]`
+pub fn run_in_container(
+    ctx: &ExecutionContext,
+    name: &str,
+    container: &ExecContainer,
+    step: ContainerStep,
+) -> Result<()> {
+    let crt = match container.runtime() {
+        Some(runtime) => require(runtime)?,
+        None => require("podman").or_else(|_| require("docker"))?,
+    };
+
+    let running = Command::new(&crt)
+        .args(["inspect", "--format", "{{.State.Running}}", name])
+        .output()?;
+    if !running.status.success() {
+        return Err(SkipStep::not_applicable(format!("The container {} doesn't exist", name)).into());
+    }
+    if String::from_utf8_lossy(&running.stdout).trim() != "true" {
+        return Err(SkipStep::not_applicable(format!("The container {} isn't running", name)).into());
+    }
+
+    print_separator(exec_key(name, step));
+
+    let exec = |command: &[&str]| {
+        ctx.run_type()
+            .execute(&crt)
+            .args(["exec", "--interactive", "--user", container.user(), name])
+            .args(command)
+            .check_run()
+    };
+
+    match step {
+        ContainerStep::System => {
+            let package_manager = package_manager(&crt, name)?;
+            debug!("Package manager of {}: {}", name, package_manager);
+            for command in upgrade_commands(&package_manager, ctx.config().yes(Step::Containers)) {
+                exec(&command)?;
+            }
+            Ok(())
+        }
+        ContainerStep::Npm => exec(&["npm", "update", "--global"]),
+        ContainerStep::Pipx => exec(&["pipx", "upgrade-all"]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_commands() {
+        assert_eq!(
+            upgrade_commands("apt-get", true),
+            [vec!["apt-get", "update"], vec!["apt-get", "dist-upgrade", "-y"]]
+        );
+        assert_eq!(
+            upgrade_commands("zypper", true),
+            [vec!["zypper", "--non-interactive", "update"]]
+        );
+        assert_eq!(upgrade_commands("pacman", false), [vec!["pacman", "-Syu"]]);
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+
+use crate::error::SkipStep;
+use crate::report::StepReport;
+use crate::terminal::{print_separator, print_warning};
+use crate::{execution_context::ExecutionContext, utils::require};
+use log::debug;
+use std::path::Path;
+use std::process::Command;
+
+fn list_containers(distrobox: &Path) -> Result<Vec<String>> {
+    let output = Command::new(distrobox).args(["list", "--no-color"]).output()?;
+    let output_str = String::from_utf8(output.stdout)?;
+
+    Ok(parse_containers(&output_str))
+}
+
+/// Container names from the output of `distrobox list`
+fn parse_containers(output: &str) -> Vec<String> {
+    output
+        .lines()
+        // Skip the first line since that contains only the column headers
+        .skip(1)
+        .filter_map(|line| line.split('|').nth(1))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+pub fn run_distrobox(ctx: &ExecutionContext) -> Result<StepReport> {
+    let distrobox = require("distrobox")?;
+
+    let containers = list_containers(&distrobox)?;
+    debug!("Distrobox containers: {:?}", containers);
+    if containers.is_empty() {
+        return Err(SkipStep::nothing_to_do(String::from("No distrobox containers")).into());
+    }
+
+    print_separator("Distrobox");
+
+    let allowlist = match ctx.config().distrobox_containers() {
+        Some(allowlist) => allowlist,
+        None => {
+            ctx.run_type()
+                .execute(&distrobox)
+                .args(["upgrade", "--all"])
+                .check_run()?;
+            return Ok(StepReport::with_changes(format!("Upgraded {}", containers.join(", "))));
+        }
+    };
+
+    let mut upgraded = Vec::new();
+    let mut failed = Vec::new();
+    for container in containers.iter().filter(|container| allowlist.contains(container)) {
+        match ctx
+            .run_type()
+            .execute(&distrobox)
+            .args(["upgrade", container])
+            .check_run()
+        {
+            Ok(()) => upgraded.push(container.as_str()),
+            Err(e) => {
+                print_warning(format!("Failed to upgrade {}: {}", container, e));
+                failed.push(container.as_str());
+            }
+        }
+    }
+
+    if upgraded.is_empty() && failed.is_empty() {
+        return Err(SkipStep::nothing_to_do(String::from("None of the allowed distrobox containers exist")).into());
+    }
+
+    if upgraded.is_empty() {
+        return Err(anyhow!("Failed to upgrade {}", failed.join(", ")));
+    }
+
+    Ok(StepReport::new(
+        Some(format!("Upgraded {}", upgraded.join(", "))),
+        (!failed.is_empty()).then(|| format!("Failed to upgrade {}", failed.join(", "))),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_containers() {
+        let output = "ID           | NAME                 | STATUS             | IMAGE
+a1b2c3d4e5f6 | fedora               | Up 2 hours         | registry.fedoraproject.org/fedora-toolbox:38
+0f9e8d7c6b5a | ubuntu               | Exited (0) 1 day   | quay.io/toolbx/ubuntu-toolbox:22.04
+";
+        assert_eq!(parse_containers(output), ["fedora", "ubuntu"]);
+        assert!(parse_containers("ID | NAME | STATUS | IMAGE\n").is_empty());
+    }
+}
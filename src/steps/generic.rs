@@ -10,16 +10,20 @@ use directories::BaseDirs;
 use log::debug;
 use tempfile::tempfile_in;
 
+use crate::config::CustomCommand;
 use crate::execution_context::ExecutionContext;
 use crate::executor::{CommandExt, ExecutorOutput, RunType};
-use crate::terminal::{print_separator, shell};
+use crate::report::StepReport;
+use crate::steps::registry::{elevated, sharing, FnStep, UpdateStep};
+use crate::terminal::{print_output, print_separator, shell};
 use crate::utils::{self, require_option, PathExt};
 use crate::{
     error::{SkipStep, TopgradeError},
     terminal::print_warning,
+    Step,
 };
 
-pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
+pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<StepReport> {
     let cargo_dir = env::var_os("CARGO_HOME")
         .map(PathBuf::from)
         .unwrap_or_else(|| ctx.base_dirs().home_dir().join(".cargo"))
@@ -34,7 +38,7 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
     let toml_file = cargo_dir.join(".crates.toml").require()?;
 
     if fs::metadata(&toml_file)?.len() == 0 {
-        return Err(SkipStep(format!("{} exists but empty", &toml_file.display())).into());
+        return Err(SkipStep::nothing_to_do(format!("{} exists but empty", &toml_file.display())).into());
     }
 
     print_separator("Cargo");
@@ -46,31 +50,200 @@ pub fn run_cargo_update(ctx: &ExecutionContext) -> Result<()> {
         None => {
             let message = String::from("cargo-update isn't installed so Topgrade can't upgrade cargo packages.\nInstall cargo-update by running `cargo install cargo-update`");
             print_warning(&message);
-            return Err(SkipStep(message).into());
+            return Err(SkipStep::not_installed(message).into());
         }
     };
 
     ctx.run_type()
         .execute(cargo_update)
         .args(&["install-update", "--git", "--all"])
+        .check_run()?;
+
+    if ctx.config().cargo_audit() {
+        if let Some(warnings) = run_cargo_audit(ctx, &cargo_dir)? {
+            return Ok(StepReport::with_warnings(warnings));
+        }
+    }
+
+    Ok(StepReport::default())
+}
+
+/// Audit the installed binaries with cargo-audit and summarize the advisories it found.
+///
+/// The audit is advisory, so vulnerable crates don't make the step fail.
+fn run_cargo_audit(ctx: &ExecutionContext, cargo_dir: &Path) -> Result<Option<String>> {
+    let cargo_audit = match utils::require("cargo-audit")
+        .ok()
+        .or_else(|| cargo_dir.join("bin/cargo-audit").if_exists())
+    {
+        Some(cargo_audit) => cargo_audit,
+        None => return Ok(None),
+    };
+
+    let binaries: Vec<PathBuf> = fs::read_dir(cargo_dir.join("bin"))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file())
+        .collect();
+
+    let output = match ctx
+        .run_type()
+        .execute(cargo_audit)
+        .args(["audit", "bin"])
+        .args(&binaries)
+        .output()?
+    {
+        ExecutorOutput::Wet(output) => output,
+        ExecutorOutput::Dry => return Ok(None),
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    debug!("cargo audit: {}", stdout);
+
+    let advisories = stdout.lines().filter(|line| line.starts_with("ID:")).count();
+    Ok(match advisories {
+        0 => None,
+        1 => Some(String::from("cargo audit found 1 advisory")),
+        n => Some(format!("cargo audit found {} advisories", n)),
+    })
+}
+
+/// Refresh the lockfile of a local Cargo project
+pub fn run_cargo_project_update(ctx: &ExecutionContext, project: &str) -> Result<()> {
+    let cargo = utils::require("cargo")?;
+    let manifest = Path::new(project).join("Cargo.toml").require()?;
+
+    print_separator(format!("Cargo ({})", project));
+    ctx.run_type()
+        .execute(cargo)
+        .arg("update")
+        .arg("--manifest-path")
+        .arg(manifest)
         .check_run()
 }
 
-pub fn run_flutter_upgrade(run_type: RunType) -> Result<()> {
+pub fn run_android_sdk(ctx: &ExecutionContext) -> Result<()> {
+    let sdk_root = require_option(
+        env::var_os("ANDROID_HOME")
+            .or_else(|| env::var_os("ANDROID_SDK_ROOT"))
+            .map(PathBuf::from),
+        String::from("Neither ANDROID_HOME nor ANDROID_SDK_ROOT is set"),
+    )?;
+
+    let sdkmanager_name = if cfg!(windows) { "sdkmanager.bat" } else { "sdkmanager" };
+    let sdkmanager = match sdk_root
+        .join("cmdline-tools/latest/bin")
+        .join(sdkmanager_name)
+        .if_exists()
+        .or_else(|| sdk_root.join("tools/bin").join(sdkmanager_name).if_exists())
+    {
+        Some(sdkmanager) => sdkmanager,
+        None => utils::require(sdkmanager_name)?,
+    };
+
+    print_separator("Android SDK");
+
+    #[cfg(unix)]
+    if ctx.config().yes(Step::AndroidSdk) {
+        ctx.run_type()
+            .execute("sh")
+            .args(["-c", "yes | \"$0\" --licenses > /dev/null"])
+            .arg(&sdkmanager)
+            .check_run()?;
+    }
+
+    ctx.run_type().execute(&sdkmanager).arg("--update").check_run()
+}
+
+/// The version manager a Flutter SDK at `path` belongs to, if any. Those refuse `flutter upgrade` or get
+/// out of sync with it
+fn flutter_manager(path: &Path) -> Option<&'static str> {
+    let path = path.to_string_lossy();
+    if path.contains("/fvm/") || path.contains("/.fvm/") {
+        Some("FVM")
+    } else if path.contains("/.asdf/") {
+        Some("asdf")
+    } else if path.starts_with("/snap/") {
+        Some("snap")
+    } else {
+        None
+    }
+}
+
+/// The globally activated packages in the output of `dart pub global list` which come from pub.dev.
+/// Packages activated from a path or a Git repository are left alone
+fn hosted_pub_packages(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .filter_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, _version] => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Activate the latest version of every globally activated pub package with `pub` (`dart` or `flutter`)
+fn upgrade_pub_global_packages(ctx: &ExecutionContext, pub_: &Path) -> Result<()> {
+    let output = Command::new(pub_).args(["pub", "global", "list"]).check_output()?;
+    for package in hosted_pub_packages(&output) {
+        ctx.run_type()
+            .execute(pub_)
+            .args(["pub", "global", "activate", package])
+            .check_run()?;
+    }
+
+    Ok(())
+}
+
+pub fn run_flutter(ctx: &ExecutionContext) -> Result<()> {
     let flutter = utils::require("flutter")?;
+    if let Some(manager) = flutter_manager(&flutter.canonicalize().unwrap_or_else(|_| flutter.clone())) {
+        return Err(SkipStep::not_applicable(format!("Flutter is managed by {}", manager)).into());
+    }
 
     print_separator("Flutter");
-    run_type.execute(&flutter).arg("upgrade").check_run()
+    ctx.run_type().execute(&flutter).arg("upgrade").check_run()?;
+    upgrade_pub_global_packages(ctx, &flutter)
 }
 
-pub fn run_go(run_type: RunType) -> Result<()> {
+pub fn run_dart(ctx: &ExecutionContext) -> Result<()> {
+    let dart = utils::require("dart")?;
+    if utils::which("flutter").is_some() {
+        return Err(SkipStep::not_applicable(String::from("Dart comes with Flutter, which updates it")).into());
+    }
+
+    print_separator("Dart");
+    upgrade_pub_global_packages(ctx, &dart)
+}
+
+pub fn run_go(ctx: &ExecutionContext) -> Result<()> {
     let go = utils::require("go")?;
-    let go_output = run_type.execute(&go).args(&["env", "GOPATH"]).check_output()?;
+    let run_type = ctx.run_type();
+
+    if let Some(binaries) = ctx.config().go_binaries().filter(|b| !b.is_empty()) {
+        print_separator("Go");
+
+        for binary in binaries {
+            run_type
+                .execute(&go)
+                .arg("install")
+                .arg(format!("{}@latest", binary))
+                .check_run()?;
+        }
+
+        return Ok(());
+    }
+
+    let go_output = run_type.execute(&go).args(["env", "GOPATH"]).check_output()?;
     let gopath = go_output.trim();
 
     let go_global_update = utils::require("go-global-update")
         .unwrap_or_else(|_| PathBuf::from(gopath).join("bin/go-global-update"))
-        .require()?;
+        .require()
+        .map_err(|_| {
+            SkipStep::nothing_to_do(String::from(
+                "No Go binaries to update. List them in `go_binaries` or install go-global-update",
+            ))
+        })?;
 
     print_separator("Go");
 
@@ -108,9 +281,7 @@ pub fn run_haxelib_update(ctx: &ExecutionContext) -> Result<()> {
     let mut command = if directory_writable {
         ctx.run_type().execute(&haxelib)
     } else {
-        let mut c = ctx
-            .run_type()
-            .execute(ctx.sudo().as_ref().ok_or(TopgradeError::SudoRequired)?);
+        let mut c = ctx.run_type().execute(ctx.require_sudo()?);
         c.arg(&haxelib);
         c
     };
@@ -222,17 +393,26 @@ pub fn run_rtcl(ctx: &ExecutionContext) -> Result<()> {
 
 pub fn run_opam_update(ctx: &ExecutionContext) -> Result<()> {
     let opam = utils::require("opam")?;
+    env::var_os("OPAMROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ctx.base_dirs().home_dir().join(".opam"))
+        .require()?;
 
     print_separator("OCaml Package Manager");
 
     ctx.run_type().execute(&opam).arg("update").check_run()?;
-    ctx.run_type().execute(&opam).arg("upgrade").check_run()?;
 
-    if ctx.config().cleanup() {
-        ctx.run_type().execute(&opam).arg("clean").check_run()?;
+    let mut command = ctx.run_type().execute(&opam);
+    command.arg("upgrade");
+    if ctx.config().yes(Step::Opam) {
+        command.arg("-y");
     }
+    command.check_run()
+}
 
-    Ok(())
+pub fn clean_opam(ctx: &ExecutionContext) -> Result<()> {
+    let opam = utils::require("opam")?;
+    ctx.run_type().execute(&opam).arg("clean").check_run()
 }
 
 pub fn run_vcpkg_update(run_type: RunType) -> Result<()> {
@@ -258,7 +438,7 @@ pub fn run_conda_update(ctx: &ExecutionContext) -> Result<()> {
     let string_output = String::from_utf8(output.stdout)?;
     debug!("Conda output: {}", string_output);
     if string_output.contains("False") {
-        return Err(SkipStep("auto_activate_base is set to False".to_string()).into());
+        return Err(SkipStep::disabled("auto_activate_base is set to False".to_string()).into());
     }
 
     print_separator("Conda");
@@ -274,12 +454,12 @@ pub fn run_pip3_update(run_type: RunType) -> Result<()> {
     Command::new(&python3)
         .args(&["-m", "pip"])
         .check_output()
-        .map_err(|_| SkipStep("pip does not exists".to_string()))?;
+        .map_err(|_| SkipStep::not_installed("pip does not exists".to_string()))?;
 
     print_separator("pip3");
     if std::env::var("VIRTUAL_ENV").is_ok() {
         print_warning("This step is will be skipped when running inside a virtual environment");
-        return Err(SkipStep("Does not run inside a virtual environment".to_string()).into());
+        return Err(SkipStep::not_applicable("Does not run inside a virtual environment".to_string()).into());
     }
 
     run_type
@@ -299,7 +479,7 @@ pub fn run_tlmgr_update(ctx: &ExecutionContext) -> Result<()> {
     cfg_if::cfg_if! {
         if #[cfg(any(target_os = "linux", target_os = "android"))] {
             if !ctx.config().enable_tlmgr_linux() {
-                return Err(SkipStep(String::from("tlmgr must be explicity enabled in the configuration to run in Android/Linux")).into());
+                return Err(SkipStep::disabled(String::from("tlmgr must be explicity enabled in the configuration to run in Android/Linux")).into());
             }
         }
     }
@@ -329,9 +509,7 @@ pub fn run_tlmgr_update(ctx: &ExecutionContext) -> Result<()> {
     let mut command = if directory_writable {
         ctx.run_type().execute(&tlmgr)
     } else {
-        let mut c = ctx
-            .run_type()
-            .execute(ctx.sudo().as_ref().ok_or(TopgradeError::SudoRequired)?);
+        let mut c = ctx.run_type().execute(ctx.require_sudo()?);
         c.arg(&tlmgr);
         c
     };
@@ -369,9 +547,50 @@ pub fn run_myrepos_update(base_dirs: &BaseDirs, run_type: RunType) -> Result<()>
         .check_run()
 }
 
-pub fn run_custom_command(name: &str, command: &str, ctx: &ExecutionContext) -> Result<()> {
+pub fn run_custom_command(name: &str, command: &CustomCommand, ctx: &ExecutionContext) -> Result<()> {
     print_separator(name);
-    ctx.run_type().execute(shell()).arg("-c").arg(command).check_run()
+
+    let mut executor = match command.shell() {
+        Some("none") => {
+            // Quoted arguments are kept together like in a shell, but nothing is expanded
+            let words = shell_words::split(command.command())
+                .map_err(|e| anyhow::anyhow!("Invalid quoting in the command of {}: {}", name, e))?;
+            let (program, args) = words
+                .split_first()
+                .ok_or_else(|| anyhow::anyhow!("The command of {} is empty", name))?;
+            let mut executor = ctx.run_type().execute(program);
+            executor.args(args);
+            executor
+        }
+        shell_name => {
+            let mut executor = match shell_name {
+                Some(shell_name) => ctx.run_type().execute(shell_name),
+                None => ctx.run_type().execute(shell()),
+            };
+            executor.arg("-c").arg(command.command());
+            executor
+        }
+    };
+
+    if let Some(cwd) = command.cwd() {
+        let cwd = PathBuf::from(shellexpand::tilde(cwd).into_owned());
+        if !cwd.is_dir() {
+            let message = format!("The working directory {} of {} doesn't exist", cwd.display(), name);
+            print_warning(&message);
+            return Err(anyhow::anyhow!(message));
+        }
+        executor.current_dir(cwd);
+    }
+
+    executor.check_run_with(command.timeout()?, command.capture_output())
+}
+
+/// Run the self update of a binary, skipping it when the binary isn't installed
+pub fn run_self_update_binary(ctx: &ExecutionContext, binary: &str, args: &[String]) -> Result<()> {
+    let executable = utils::require(binary)?;
+
+    print_separator(binary);
+    ctx.run_type().execute(executable).args(args).check_run()
 }
 
 pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
@@ -379,12 +598,12 @@ pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
     let composer_home = Command::new(&composer)
         .args(&["global", "config", "--absolute", "--quiet", "home"])
         .check_output()
-        .map_err(|e| (SkipStep(format!("Error getting the composer directory: {}", e))))
+        .map_err(|e| (SkipStep::not_installed(format!("Error getting the composer directory: {}", e))))
         .map(|s| PathBuf::from(s.trim()))?
         .require()?;
 
     if !composer_home.is_descendant_of(ctx.base_dirs().home_dir()) {
-        return Err(SkipStep(format!(
+        return Err(SkipStep::not_applicable(format!(
             "Composer directory {} isn't a decandent of the user's home directory",
             composer_home.display()
         ))
@@ -404,7 +623,7 @@ pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
 
                 if has_update {
                     ctx.run_type()
-                        .execute(ctx.sudo().as_ref().unwrap())
+                        .execute(ctx.require_sudo()?)
                         .arg(&composer)
                         .arg("self-update")
                         .check_run()?;
@@ -422,7 +641,7 @@ pub fn run_composer_update(ctx: &ExecutionContext) -> Result<()> {
     }
     let stdout = String::from_utf8(output.stdout)?;
     let stderr = String::from_utf8(output.stderr)?;
-    print!("{}\n{}", stdout, stderr);
+    print_output(format!("{}\n{}", stdout, stderr));
 
     if stdout.contains("valet") || stderr.contains("valet") {
         if let Some(valet) = utils::which("valet") {
@@ -439,18 +658,18 @@ pub fn run_dotnet_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let output = Command::new(dotnet).args(&["tool", "list", "--global"]).output()?;
 
     if !output.status.success() {
-        return Err(SkipStep(format!("dotnet failed with exit code {:?}", output.status)).into());
+        return Err(SkipStep::custom(format!("dotnet failed with exit code {:?}", output.status)).into());
     }
 
     let output = String::from_utf8(output.stdout)?;
     if !output.starts_with("Package Id") {
-        return Err(SkipStep(String::from("dotnet did not output packages")).into());
+        return Err(SkipStep::nothing_to_do(String::from("dotnet did not output packages")).into());
     }
 
     let mut packages = output.split('\n').skip(2).filter(|line| !line.is_empty()).peekable();
 
     if packages.peek().is_none() {
-        return Err(SkipStep(String::from("No dotnet global tools installed")).into());
+        return Err(SkipStep::nothing_to_do(String::from("No dotnet global tools installed")).into());
     }
 
     print_separator(".NET");
@@ -491,9 +710,15 @@ pub fn spicetify_upgrade(ctx: &ExecutionContext) -> Result<()> {
 pub fn run_ghcli_extensions_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let gh = utils::require("gh")?;
     let result = Command::new(&gh).args(&["extensions", "list"]).check_output();
-    if result.is_err() {
-        debug!("GH result {:?}", result);
-        return Err(SkipStep(String::from("GH failed")).into());
+    match &result {
+        Err(_) => {
+            debug!("GH result {:?}", result);
+            return Err(SkipStep::custom(String::from("GH failed")).into());
+        }
+        Ok(extensions) if extensions.trim().is_empty() => {
+            return Err(SkipStep::nothing_to_do(String::from("No GitHub CLI extensions installed")).into());
+        }
+        Ok(_) => (),
     }
 
     print_separator("GitHub CLI Extensions");
@@ -503,13 +728,173 @@ pub fn run_ghcli_extensions_upgrade(ctx: &ExecutionContext) -> Result<()> {
         .check_run()
 }
 
+/// Whether the first Julia depot has a default environment, e.g. `~/.julia/environments/v1.9`
+fn julia_has_default_environment(ctx: &ExecutionContext) -> bool {
+    let depot = env::var_os("JULIA_DEPOT_PATH")
+        .and_then(|paths| env::split_paths(&paths).find(|path| !path.as_os_str().is_empty()))
+        .unwrap_or_else(|| ctx.base_dirs().home_dir().join(".julia"));
+
+    fs::read_dir(depot.join("environments"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .any(|entry| entry.path().join("Project.toml").exists())
+        })
+        .unwrap_or(false)
+}
+
 pub fn update_julia_packages(ctx: &ExecutionContext) -> Result<()> {
     let julia = utils::require("julia")?;
+    if !julia_has_default_environment(ctx) {
+        return Err(SkipStep::nothing_to_do("Julia has no default environment").into());
+    }
 
     print_separator("Julia Packages");
 
+    // The code is passed as a single argument without a shell, so it needs no quoting
     ctx.run_type()
         .execute(&julia)
-        .args(&["-e", "using Pkg; Pkg.update()"])
+        .args(["-e", "using Pkg; Pkg.update()"])
         .check_run()
 }
+
+/// Remove the package versions which Julia doesn't use anymore
+pub fn gc_julia_packages(ctx: &ExecutionContext) -> Result<()> {
+    let julia = utils::require("julia")?;
+    ctx.run_type()
+        .execute(&julia)
+        .args(["-e", "using Pkg; Pkg.gc()"])
+        .check_run()
+}
+
+/// Compilers and language toolchains, which work the same way on every platform. They come before the
+/// cargo projects, which build with them
+pub fn toolchain_steps() -> Vec<Box<dyn UpdateStep>> {
+    #[allow(unused_mut)]
+    let mut steps = Vec::new();
+    #[cfg(not(any(
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    )))]
+    steps.push(FnStep::boxed(Step::Atom, "apm", |ctx| run_apm(ctx.run_type())));
+    steps.extend([
+        FnStep::boxed(Step::Fossil, "fossil", |ctx| run_fossil(ctx.run_type())),
+        // cargo installs with the toolchain that rustup updates
+        sharing(
+            "rust",
+            FnStep::boxed(Step::Rustup, "rustup", |ctx| {
+                run_rustup(ctx.base_dirs(), ctx.run_type())
+            }),
+        ),
+        FnStep::boxed(Step::Dotnet, ".NET", run_dotnet_upgrade),
+        FnStep::boxed(Step::Choosenim, "choosenim", run_choosenim),
+        sharing(
+            "rust",
+            FnStep::boxed_with_report(Step::Cargo, "cargo", run_cargo_update),
+        ),
+    ]);
+
+    steps
+}
+
+/// Language SDKs and their package managers, which work the same way on every platform
+pub fn language_steps() -> Vec<Box<dyn UpdateStep>> {
+    vec![
+        FnStep::boxed(Step::AndroidSdk, "Android SDK", run_android_sdk),
+        FnStep::boxed(Step::Flutter, "Flutter", run_flutter),
+        FnStep::boxed(Step::Dart, "Dart", run_dart),
+        FnStep::boxed(Step::Go, "Go", run_go),
+        FnStep::boxed_with_cleanup(Step::Opam, "opam", run_opam_update, clean_opam),
+        FnStep::boxed(Step::Vcpkg, "vcpkg", |ctx| run_vcpkg_update(ctx.run_type())),
+        // pip3 and pipx can share the same interpreter, and conda can be the one of both
+        sharing(
+            "python",
+            FnStep::boxed(Step::Pipx, "pipx", |ctx| run_pipx_update(ctx.run_type())),
+        ),
+        sharing("python", FnStep::boxed(Step::Conda, "conda", run_conda_update)),
+        sharing(
+            "python",
+            FnStep::boxed(Step::Pip3, "pip3", |ctx| run_pip3_update(ctx.run_type())),
+        ),
+        FnStep::boxed(Step::Stack, "stack", |ctx| run_stack_update(ctx.run_type())),
+        elevated(FnStep::boxed(Step::Tlmgr, "tlmgr", run_tlmgr_update)),
+        FnStep::boxed(Step::Myrepos, "myrepos", |ctx| {
+            run_myrepos_update(ctx.base_dirs(), ctx.run_type())
+        }),
+        FnStep::boxed(Step::Chezmoi, "chezmoi", |ctx| {
+            run_chezmoi_update(ctx.base_dirs(), ctx.run_type())
+        }),
+        FnStep::boxed(Step::Jetpack, "jetpack", |ctx| run_jetpack(ctx.run_type())),
+    ]
+}
+
+/// Package managers of other tools, which work the same way on every platform
+pub fn package_steps() -> Vec<Box<dyn UpdateStep>> {
+    vec![
+        elevated(FnStep::boxed(Step::Composer, "composer", run_composer_update)),
+        FnStep::boxed(Step::Krew, "krew", |ctx| run_krew_upgrade(ctx.run_type())),
+        FnStep::boxed(Step::Gem, "gem", |ctx| run_gem(ctx.base_dirs(), ctx.run_type())),
+        FnStep::boxed_with_cleanup(Step::Julia, "julia", update_julia_packages, gc_julia_packages),
+        elevated(FnStep::boxed(Step::Haxelib, "haxelib", run_haxelib_update)),
+        FnStep::boxed(Step::Sheldon, "sheldon", run_sheldon),
+        FnStep::boxed(Step::Rtcl, "rtcl", run_rtcl),
+        FnStep::boxed(Step::Bin, "bin", bin_update),
+        FnStep::boxed(Step::Gcloud, "gcloud", |ctx| {
+            run_gcloud_components_update(ctx.run_type())
+        }),
+        FnStep::boxed(Step::Micro, "micro", |ctx| run_micro(ctx.run_type())),
+        FnStep::boxed(Step::Raco, "raco", |ctx| run_raco_update(ctx.run_type())),
+        FnStep::boxed(Step::Spicetify, "spicetify", spicetify_upgrade),
+        FnStep::boxed(
+            Step::GithubCliExtensions,
+            "GitHub CLI Extensions",
+            run_ghcli_extensions_upgrade,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_hosted_pub_packages() {
+        let output = r#"devtools 2.28.1
+flutterfire_cli 0.2.7
+my_tool 0.1.0 at path "/home/user/src/my_tool"
+other_tool 1.0.0 from Git repository "https://github.com/user/other_tool.git"
+"#;
+        assert_eq!(hosted_pub_packages(output), ["devtools", "flutterfire_cli"]);
+    }
+
+    #[test]
+    fn test_flutter_manager() {
+        assert_eq!(
+            flutter_manager(Path::new("/home/user/fvm/versions/3.13.0/bin/flutter")),
+            Some("FVM")
+        );
+        assert_eq!(
+            flutter_manager(Path::new("/snap/flutter/current/bin/flutter")),
+            Some("snap")
+        );
+        assert_eq!(flutter_manager(Path::new("/home/user/flutter/bin/flutter")), None);
+    }
+
+    #[test]
+    fn test_registries() {
+        let names: Vec<String> = toolchain_steps()
+            .iter()
+            .chain(language_steps().iter())
+            .chain(package_steps().iter())
+            .map(|step| step.name().to_string())
+            .collect();
+        let unique: HashSet<&String> = names.iter().collect();
+        assert_eq!(unique.len(), names.len());
+        assert!(names.iter().any(|name| name == "rustup"));
+        assert!(names.iter().any(|name| name == "GitHub CLI Extensions"));
+    }
+}
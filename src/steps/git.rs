@@ -1,4 +1,5 @@
 use std::collections::HashSet;
+use std::future::Future;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output, Stdio};
@@ -12,9 +13,11 @@ use log::{debug, error};
 use tokio::process::Command as AsyncCommand;
 use tokio::runtime;
 
+use crate::config::GitPullStrategy;
 use crate::execution_context::ExecutionContext;
-use crate::executor::{CommandExt, RunType};
-use crate::terminal::print_separator;
+use crate::executor::{self, CommandExt, RunType};
+use crate::report::StepReport;
+use crate::terminal::{print_output, print_separator};
 use crate::utils::{which, PathExt};
 use crate::{error::SkipStep, terminal::print_warning};
 
@@ -42,43 +45,217 @@ fn check_output(output: Output) -> Result<()> {
     }
 }
 
-async fn pull_repository(repo: String, git: &Path, ctx: &ExecutionContext<'_>) -> Result<()> {
-    let path = repo.to_string();
+/// What pulling a repository did
+enum Pulled {
+    /// The repository was pulled, with a short summary of the changes if there were any
+    Updated(Option<String>),
+    /// The `ff-only` strategy couldn't fast-forward the local branch
+    Diverged(String),
+    /// The stashed local changes conflicted with the pulled ones, and were left in the stash
+    Unresolved(String),
+}
+
+/// Run `future` on the runtime, dropping it and the commands it runs when the step runs out of time
+fn block_on<T>(rt: &runtime::Runtime, future: impl Future<Output = T>) -> Result<T> {
+    match executor::time_left() {
+        Some(left) => rt.block_on(tokio::time::timeout(left, future)).map_err(|_| {
+            executor::record_timeout();
+            anyhow!("Killed after running out of time")
+        }),
+        None => Ok(rt.block_on(future)),
+    }
+}
+
+/// The arguments aborting the merge or the rebase which a pull with `strategy` can leave conflicted
+fn abort_arguments(strategy: GitPullStrategy) -> Option<[&'static str; 2]> {
+    match strategy {
+        GitPullStrategy::Merge => Some(["merge", "--abort"]),
+        GitPullStrategy::Rebase => Some(["rebase", "--abort"]),
+        GitPullStrategy::FfOnly => None,
+    }
+}
+
+/// The local changes stashed by `pull_repository`. When the pull is dropped before they're popped, because
+/// the step ran out of time, they're popped when this is dropped instead of staying in the stash
+struct Autostash<'a> {
+    repo: String,
+    git: &'a Path,
+    strategy: GitPullStrategy,
+    pending: bool,
+}
+
+impl Drop for Autostash<'_> {
+    fn drop(&mut self) {
+        if !self.pending {
+            return;
+        }
+
+        let git = |args: &[&str]| {
+            Command::new(self.git)
+                .stdin(Stdio::null())
+                .current_dir(&self.repo)
+                .args(args)
+                .output()
+        };
+        // The killed pull can have stopped in the middle of a merge or a rebase
+        if let Some(abort) = abort_arguments(self.strategy) {
+            git(&abort).ok();
+        }
+        match git(&["stash", "pop"]) {
+            Ok(pop) if pop.status.success() => debug!("Popped the local changes of {}", self.repo),
+            _ => error!(
+                "The pull of {} was interrupted and its local changes are kept in `git stash`",
+                self.repo
+            ),
+        }
+    }
+}
+
+/// Pull the repository, stashing its local changes around the pull if it's in `autostash_repos`.
+///
+/// A merge or a rebase left conflicted by the pull is aborted before the changes are popped. When popping
+/// them conflicts, the changes stay in the stash to be resolved by hand, like with `git pull --autostash`.
+async fn pull_repository(repo: String, git: &Path, ctx: &ExecutionContext<'_>) -> Result<Pulled> {
+    if !ctx.config().git_autostash(&repo) {
+        return pull_clean_repository(repo, git, ctx).await;
+    }
+
+    let git_command = |args: &[&str]| {
+        let mut command = AsyncCommand::new(git);
+        command
+            .stdin(Stdio::null())
+            .current_dir(&repo)
+            .args(args)
+            .kill_on_drop(true);
+        command
+    };
+
+    let status = git_command(&["status", "--porcelain"]).output().await?;
+    if !status.status.success() || status.stdout.is_empty() {
+        return pull_clean_repository(repo, git, ctx).await;
+    }
+
+    check_output(
+        git_command(&[
+            "stash",
+            "push",
+            "--include-untracked",
+            "--message",
+            "topgrade autostash",
+        ])
+        .output()
+        .await?,
+    )
+    .map_err(|e| anyhow!("Failed stashing the changes of {}: {}", repo, e))?;
+    print_output(format!(
+        "{} the local changes of {}\n",
+        style("Stashed").cyan().bold(),
+        repo
+    ));
+
+    let strategy = ctx.config().git_pull_strategy(&repo);
+    let mut autostash = Autostash {
+        repo: repo.clone(),
+        git,
+        strategy,
+        pending: true,
+    };
+
+    let pulled = pull_clean_repository(repo.clone(), git, ctx).await;
+
+    if pulled.is_err() {
+        // Fails when the pull failed before merging or rebasing anything
+        if let Some(abort) = abort_arguments(strategy) {
+            let aborted = git_command(&abort).output().await?;
+            debug!(
+                "git {} in {}: {}",
+                abort.join(" "),
+                repo,
+                String::from_utf8_lossy(&aborted.stderr)
+            );
+        }
+    }
+
+    let pop = git_command(&["stash", "pop"]).output().await?;
+    autostash.pending = false;
+    if pop.status.success() {
+        return pulled;
+    }
+
+    debug!("git stash pop in {}: {}", repo, String::from_utf8_lossy(&pop.stderr));
+    print_output(format!(
+        "{} {}: its local changes conflict with the pulled ones and are kept in `git stash`\n",
+        style("Needs manual resolution").red().bold(),
+        repo
+    ));
+    // The failure of the pull is worse than the stash left to resolve
+    pulled?;
+    Ok(Pulled::Unresolved(repo))
+}
+
+/// Pull the repository as it is.
+///
+/// The output is buffered and printed at once so that repositories pulled concurrently don't interleave.
+async fn pull_clean_repository(repo: String, git: &Path, ctx: &ExecutionContext<'_>) -> Result<Pulled> {
     let before_revision = get_head_revision(git, &repo);
 
-    println!("{} {}", style("Pulling").cyan().bold(), path);
+    let mut output = format!("{} {}\n", style("Pulling").cyan().bold(), repo);
 
     let mut command = AsyncCommand::new(git);
 
+    let strategy = ctx.config().git_pull_strategy(&repo);
     command
         .stdin(Stdio::null())
         .current_dir(&repo)
-        .args(&["pull", "--ff-only"]);
+        .kill_on_drop(true)
+        // The English messages of git tell a branch which can't be fast-forwarded
+        .env("LC_ALL", "C")
+        .arg("pull")
+        .arg(match strategy {
+            GitPullStrategy::Merge => "--no-rebase",
+            GitPullStrategy::Rebase => "--rebase",
+            GitPullStrategy::FfOnly => "--ff-only",
+        });
 
     if let Some(extra_arguments) = ctx.config().git_arguments() {
         command.args(extra_arguments.split_whitespace());
     }
 
     let pull_output = command.output().await?;
+    if strategy == GitPullStrategy::FfOnly
+        && !pull_output.status.success()
+        && String::from_utf8_lossy(&pull_output.stderr).contains("Not possible to fast-forward")
+    {
+        output.push_str(&format!("{} {} (diverged)\n", style("Skipped").yellow().bold(), repo));
+        print_output(output);
+        return Ok(Pulled::Diverged(repo));
+    }
+
     let submodule_output = AsyncCommand::new(git)
         .args(&["submodule", "update", "--recursive"])
         .current_dir(&repo)
         .stdin(Stdio::null())
+        .kill_on_drop(true)
         .output()
         .await?;
     let result = check_output(pull_output).and_then(|_| check_output(submodule_output));
+    let mut changes = None;
 
     if let Err(message) = &result {
-        println!("{} pulling {}", style("Failed").red().bold(), &repo);
-        print!("{}", message);
+        output.push_str(&format!(
+            "{} pulling {}\n{}",
+            style("Failed").red().bold(),
+            &repo,
+            message
+        ));
     } else {
         let after_revision = get_head_revision(git, &repo);
 
         match (&before_revision, &after_revision) {
             (Some(before), Some(after)) if before != after => {
-                println!("{} {}:", style("Changed").yellow().bold(), &repo);
+                output.push_str(&format!("{} {}:\n", style("Changed").yellow().bold(), &repo));
 
-                Command::new(&git)
+                let log = Command::new(git)
                     .stdin(Stdio::null())
                     .current_dir(&repo)
                     .args(&[
@@ -88,19 +265,30 @@ async fn pull_repository(repo: String, git: &Path, ctx: &ExecutionContext<'_>) -
                         "--oneline",
                         &format!("{}..{}", before, after),
                     ])
-                    .spawn()
-                    .unwrap()
-                    .wait()
-                    .unwrap();
-                println!();
+                    .check_output()
+                    .unwrap_or_default();
+                output.push_str(&log);
+                output.push('\n');
+
+                changes = Command::new(git)
+                    .stdin(Stdio::null())
+                    .current_dir(&repo)
+                    .args(["--no-pager", "diff", "--shortstat", &format!("{}..{}", before, after)])
+                    .check_output()
+                    .map(|stat| format!("{}: {}", repo, stat.trim()))
+                    .ok();
             }
             _ => {
-                println!("{} {}", style("Up-to-date").green().bold(), &repo);
+                output.push_str(&format!("{} {}\n", style("Up-to-date").green().bold(), &repo));
             }
         }
     }
 
-    result.map(|_| ())
+    print_output(output);
+
+    result
+        .map(|_| Pulled::Updated(changes))
+        .map_err(|_| anyhow!("Failed pulling {}", repo))
 }
 
 fn get_head_revision(git: &Path, repo: &str) -> Option<String> {
@@ -132,6 +320,105 @@ fn has_remotes(git: &Path, repo: &str) -> Option<bool> {
         .ok()
 }
 
+/// Linked worktrees of the repository which can be pulled.
+///
+/// Detached and dirty worktrees, and worktrees whose branch doesn't track a remote, are skipped.
+fn pullable_worktrees(git: &Path, repo: &str) -> Vec<String> {
+    let output = match Command::new(git)
+        .stdin(Stdio::null())
+        .current_dir(repo)
+        .args(["worktree", "list", "--porcelain"])
+        .check_output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            error!("Error listing worktrees for {}: {}", repo, e);
+            return Vec::new();
+        }
+    };
+
+    // The first entry is always the main worktree, which is pulled as the repository itself
+    output
+        .split("\n\n")
+        .skip(1)
+        .filter_map(|entry| {
+            let mut lines = entry.lines();
+            let worktree = lines.next()?.strip_prefix("worktree ")?;
+            if lines.any(|line| line == "bare") {
+                return None;
+            }
+
+            let skip_reason = if entry.lines().any(|line| line == "detached") {
+                Some("it's detached")
+            } else if !Command::new(git)
+                .stdin(Stdio::null())
+                .current_dir(worktree)
+                .args(["status", "--porcelain"])
+                .check_output()
+                .map(|status| status.trim().is_empty())
+                .unwrap_or(false)
+            {
+                Some("it has local changes")
+            } else if Command::new(git)
+                .stdin(Stdio::null())
+                .current_dir(worktree)
+                .args(["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{upstream}"])
+                .check_output()
+                .is_err()
+            {
+                Some("its branch doesn't track a remote")
+            } else {
+                None
+            };
+
+            match skip_reason {
+                Some(reason) => {
+                    print_output(format!(
+                        "{} {} because {}\n",
+                        style("Skipping").yellow().bold(),
+                        worktree,
+                        reason
+                    ));
+                    None
+                }
+                None => Some(worktree.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// Describe what pulling the repository would do, using only what the last fetch knows about
+fn preview_pull(git: &Path, repo: &str) {
+    let local_changes = Command::new(git)
+        .stdin(Stdio::null())
+        .current_dir(repo)
+        .args(["status", "--porcelain"])
+        .check_output()
+        .map(|status| status.lines().count())
+        .unwrap_or(0);
+    let behind = Command::new(git)
+        .stdin(Stdio::null())
+        .current_dir(repo)
+        .args(["rev-list", "--count", "HEAD..@{upstream}"])
+        .check_output()
+        .ok()
+        .and_then(|count| count.trim().parse::<usize>().ok());
+
+    let mut notes = Vec::new();
+    if let Some(behind) = behind.filter(|&behind| behind > 0) {
+        notes.push(format!("{} commits behind as of the last fetch", behind));
+    }
+    if local_changes > 0 {
+        notes.push(format!("{} files with local changes", local_changes));
+    }
+
+    if notes.is_empty() {
+        print_output(format!("Would pull {}\n", repo));
+    } else {
+        print_output(format!("Would pull {} ({})\n", repo, notes.join(", ")));
+    }
+}
+
 impl Git {
     pub fn new() -> Self {
         Self { git: which("git") }
@@ -180,9 +467,9 @@ impl Git {
 
         None
     }
-    pub fn multi_pull_step(&self, repositories: &Repositories, ctx: &ExecutionContext) -> Result<()> {
+    pub fn multi_pull_step(&self, repositories: &Repositories, ctx: &ExecutionContext) -> Result<StepReport> {
         if repositories.repositories.is_empty() {
-            return Err(SkipStep(String::from("No repositories to pull")).into());
+            return Err(SkipStep::nothing_to_do(String::from("No repositories to pull")).into());
         }
 
         print_separator("Git repositories");
@@ -190,19 +477,57 @@ impl Git {
             .bad_patterns
             .iter()
             .for_each(|pattern| print_warning(format!("Path {} did not contain any git repositories", pattern)));
-        self.multi_pull(repositories, ctx)
+
+        let (changes, diverged, unresolved) = self.pull_repositories(repositories, ctx)?;
+        let warnings: Vec<String> = (!diverged.is_empty())
+            .then(|| format!("Skipped (diverged): {}", diverged.join(", ")))
+            .into_iter()
+            .chain((!unresolved.is_empty()).then(|| {
+                format!(
+                    "Needs manual resolution (changes kept in git stash): {}",
+                    unresolved.join(", ")
+                )
+            }))
+            .collect();
+        Ok(StepReport::new(
+            (!changes.is_empty()).then(|| changes.join("\n")),
+            (!warnings.is_empty()).then(|| warnings.join("\n")),
+        ))
     }
 
+    #[cfg(unix)]
     pub fn multi_pull(&self, repositories: &Repositories, ctx: &ExecutionContext) -> Result<()> {
+        self.pull_repositories(repositories, ctx).map(|_| ())
+    }
+
+    /// Pull all repositories, returning a summary line for each one that changed, the diverged ones and the
+    /// ones whose stashed changes conflicted
+    fn pull_repositories(
+        &self,
+        repositories: &Repositories,
+        ctx: &ExecutionContext,
+    ) -> Result<(Vec<String>, Vec<String>, Vec<String>)> {
         let git = self.git.as_ref().unwrap();
 
+        let worktrees: Vec<String> = if ctx.config().git_update_worktrees() {
+            repositories
+                .repositories
+                .iter()
+                .flat_map(|repo| pullable_worktrees(git, repo))
+                .filter(|worktree| !repositories.repositories.contains(worktree))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
         if let RunType::Dry = ctx.run_type() {
             repositories
                 .repositories
                 .iter()
-                .for_each(|repo| println!("Would pull {}", &repo));
+                .chain(worktrees.iter())
+                .for_each(|repo| preview_pull(git, repo));
 
-            return Ok(());
+            return Ok((Vec::new(), Vec::new(), Vec::new()));
         }
 
         let futures_iterator = repositories
@@ -210,11 +535,11 @@ impl Git {
             .iter()
             .filter(|repo| match has_remotes(git, repo) {
                 Some(false) => {
-                    println!(
-                        "{} {} because it has no remotes",
+                    print_output(format!(
+                        "{} {} because it has no remotes\n",
                         style("Skipping").yellow().bold(),
                         repo
-                    );
+                    ));
                     false
                 }
                 _ => true, // repo has remotes or command to check for remotes has failed. proceed to pull anyway.
@@ -228,10 +553,31 @@ impl Git {
         };
 
         let basic_rt = runtime::Runtime::new()?;
-        let results = basic_rt.block_on(async { stream_of_futures.collect::<Vec<Result<()>>>().await });
+        let mut results = block_on(&basic_rt, stream_of_futures.collect::<Vec<Result<Pulled>>>())?;
+
+        // Worktrees share the objects and refs of their repository, so pull them one at a time
+        for worktree in worktrees {
+            results.push(block_on(&basic_rt, pull_repository(worktree, git, ctx))?);
+        }
 
-        let error = results.into_iter().find(|r| r.is_err());
-        error.unwrap_or(Ok(()))
+        let mut changes = Vec::new();
+        let mut diverged = Vec::new();
+        let mut unresolved = Vec::new();
+        let mut failures = Vec::new();
+        for result in results {
+            match result {
+                Ok(Pulled::Updated(repo_changes)) => changes.extend(repo_changes),
+                Ok(Pulled::Diverged(repo)) => diverged.push(repo),
+                Ok(Pulled::Unresolved(repo)) => unresolved.push(repo),
+                Err(e) => failures.push(e.to_string()),
+            }
+        }
+
+        if failures.is_empty() {
+            Ok((changes, diverged, unresolved))
+        } else {
+            Err(anyhow!(failures.join("\n")))
+        }
     }
 }
 
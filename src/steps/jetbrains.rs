@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use directories::BaseDirs;
+use log::debug;
+
+use crate::error::SkipStep;
+use crate::execution_context::ExecutionContext;
+use crate::terminal::print_separator;
+use crate::utils::{which, PathExt};
+
+/// Directory where JetBrains Toolbox keeps its binary and the IDE launcher scripts
+#[cfg(windows)]
+fn toolbox_dir(base_dirs: &BaseDirs) -> PathBuf {
+    base_dirs.data_local_dir().join("JetBrains\\Toolbox")
+}
+
+#[cfg(not(windows))]
+fn toolbox_dir(base_dirs: &BaseDirs) -> PathBuf {
+    base_dirs.data_dir().join("JetBrains/Toolbox")
+}
+
+#[cfg(windows)]
+fn toolbox_binary(toolbox_dir: &Path) -> PathBuf {
+    toolbox_dir.join("bin\\jetbrains-toolbox.exe")
+}
+
+#[cfg(target_os = "macos")]
+fn toolbox_binary(_toolbox_dir: &Path) -> PathBuf {
+    PathBuf::from("/Applications/JetBrains Toolbox.app/Contents/MacOS/jetbrains-toolbox")
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn toolbox_binary(toolbox_dir: &Path) -> PathBuf {
+    toolbox_dir.join("bin/jetbrains-toolbox")
+}
+
+/// Launchers of the installed IDEs
+fn installed_ides(toolbox_dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(toolbox_dir.join("scripts"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn run_jetbrains(ctx: &ExecutionContext) -> Result<()> {
+    let toolbox_dir = toolbox_dir(ctx.base_dirs());
+
+    if let Some(toolbox) = which("jetbrains-toolbox").or_else(|| toolbox_binary(&toolbox_dir).if_exists()) {
+        print_separator("JetBrains");
+        return ctx.run_type().execute(toolbox).arg("--update").check_run();
+    }
+
+    let ides = installed_ides(&toolbox_dir);
+    debug!("JetBrains IDEs: {:?}", ides);
+    if ides.is_empty() {
+        return Err(SkipStep::not_installed(String::from("No JetBrains installation found")).into());
+    }
+
+    print_separator("JetBrains");
+
+    for ide in ides {
+        ctx.run_type().execute(ide).arg("update").check_run()?;
+    }
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use crate::error::TopgradeError;
-use crate::terminal::print_separator;
+use crate::terminal::{print_output, print_separator};
 use crate::utils::require;
 use anyhow::Result;
 
@@ -23,7 +23,7 @@ pub fn upgrade_kak_plug(ctx: &ExecutionContext) -> Result<()> {
         if !status.success() {
             return Err(TopgradeError::ProcessFailed(status).into());
         } else {
-            println!("Plugins upgraded")
+            print_output("Plugins upgraded\n")
         }
     }
 
@@ -1,12 +1,20 @@
+pub mod backup;
 pub mod containers;
+#[cfg(target_os = "linux")]
+pub mod distrobox;
 pub mod emacs;
 pub mod generic;
 pub mod git;
+pub mod jetbrains;
 pub mod kakoune;
 pub mod node;
 pub mod os;
+pub mod plugins;
 pub mod powershell;
+pub mod registry;
 pub mod remote;
+#[cfg(target_os = "linux")]
+pub mod snapshot;
 #[cfg(unix)]
 pub mod tmux;
 #[cfg(target_os = "linux")]
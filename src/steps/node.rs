@@ -13,8 +13,9 @@ use nix::unistd::Uid;
 use semver::Version;
 
 use crate::executor::{CommandExt, RunType};
+use crate::report::StepReport;
 use crate::terminal::print_separator;
-use crate::utils::{require, PathExt};
+use crate::utils::{require, shell_quote, PathExt};
 use crate::{error::SkipStep, execution_context::ExecutionContext};
 
 #[allow(clippy::upper_case_acronyms)]
@@ -49,7 +50,7 @@ impl NPM {
         Version::parse(&version_str?).map_err(|err| err.into())
     }
 
-    fn upgrade(&self, run_type: RunType, use_sudo: bool) -> Result<()> {
+    fn upgrade(&self, ctx: &ExecutionContext, use_sudo: bool) -> Result<()> {
         print_separator("Node Package Manager");
         let version = self.version()?;
         let args = if version < Version::new(8, 11, 0) {
@@ -58,9 +59,9 @@ impl NPM {
             ["update", "--location=global"]
         };
         if use_sudo {
-            run_type.execute("sudo").args(args).check_run()?;
+            ctx.execute_elevated(&self.command, false)?.args(args).check_run()?;
         } else {
-            run_type.execute(&self.command).args(args).check_run()?;
+            ctx.run_type().execute(&self.command).args(args).check_run()?;
         }
 
         Ok(())
@@ -70,7 +71,7 @@ impl NPM {
     pub fn should_use_sudo(&self) -> Result<bool> {
         let npm_root = self.root()?;
         if !npm_root.exists() {
-            return Err(SkipStep(format!("NPM root at {} doesn't exist", npm_root.display(),)).into());
+            return Err(SkipStep::not_installed(format!("NPM root at {} doesn't exist", npm_root.display(),)).into());
         }
 
         let metadata = std::fs::metadata(&npm_root)?;
@@ -102,18 +103,16 @@ impl Yarn {
             .map(|s| PathBuf::from(s.trim()))
     }
 
-    fn upgrade(&self, run_type: RunType, use_sudo: bool) -> Result<()> {
+    fn upgrade(&self, ctx: &ExecutionContext, use_sudo: bool) -> Result<()> {
         print_separator("Yarn Package Manager");
         let args = ["global", "upgrade"];
 
         if use_sudo {
-            run_type
-                .execute("sudo")
-                .arg(self.yarn.as_ref().unwrap_or(&self.command))
+            ctx.execute_elevated(self.yarn.as_ref().unwrap_or(&self.command), false)?
                 .args(args)
                 .check_run()?;
         } else {
-            run_type.execute(&self.command).args(args).check_run()?;
+            ctx.run_type().execute(&self.command).args(args).check_run()?;
         }
 
         Ok(())
@@ -123,7 +122,7 @@ impl Yarn {
     pub fn should_use_sudo(&self) -> Result<bool> {
         let yarn_root = self.root()?;
         if !yarn_root.exists() {
-            return Err(SkipStep(format!("NPM root at {} doesn't exist", yarn_root.display(),)).into());
+            return Err(SkipStep::not_installed(format!("NPM root at {} doesn't exist", yarn_root.display(),)).into());
         }
 
         let metadata = std::fs::metadata(&yarn_root)?;
@@ -139,7 +138,7 @@ fn should_use_sudo(npm: &NPM, ctx: &ExecutionContext) -> Result<bool> {
         if ctx.config().npm_use_sudo() {
             Ok(true)
         } else {
-            Err(SkipStep("NPM root is owned by another user which is not the current user. Set use_sudo = true under the NPM section in your configuration to run NPM as sudo".to_string())
+            Err(SkipStep::not_applicable("NPM root is owned by another user which is not the current user. Set use_sudo = true under the NPM section in your configuration to run NPM as sudo".to_string())
                 .into())
         }
     } else {
@@ -153,7 +152,7 @@ fn should_use_sudo_yarn(yarn: &Yarn, ctx: &ExecutionContext) -> Result<bool> {
         if ctx.config().yarn_use_sudo() {
             Ok(true)
         } else {
-            Err(SkipStep("NPM root is owned by another user which is not the current user. Set use_sudo = true under the NPM section in your configuration to run NPM as sudo".to_string())
+            Err(SkipStep::not_applicable("NPM root is owned by another user which is not the current user. Set use_sudo = true under the NPM section in your configuration to run NPM as sudo".to_string())
                 .into())
         }
     } else {
@@ -166,12 +165,12 @@ pub fn run_npm_upgrade(ctx: &ExecutionContext) -> Result<()> {
 
     #[cfg(target_os = "linux")]
     {
-        npm.upgrade(ctx.run_type(), should_use_sudo(&npm, ctx)?)
+        npm.upgrade(ctx, should_use_sudo(&npm, ctx)?)
     }
 
     #[cfg(not(target_os = "linux"))]
     {
-        npm.upgrade(ctx.run_type(), false)
+        npm.upgrade(ctx, false)
     }
 }
 
@@ -180,12 +179,12 @@ pub fn run_yarn_upgrade(ctx: &ExecutionContext) -> Result<()> {
 
     #[cfg(target_os = "linux")]
     {
-        yarn.upgrade(ctx.run_type(), should_use_sudo_yarn(&yarn, ctx)?)
+        yarn.upgrade(ctx, should_use_sudo_yarn(&yarn, ctx)?)
     }
 
     #[cfg(not(target_os = "linux"))]
     {
-        yarn.upgrade(ctx.run_type(), false)
+        yarn.upgrade(ctx, false)
     }
 }
 
@@ -194,10 +193,58 @@ pub fn deno_upgrade(ctx: &ExecutionContext) -> Result<()> {
     let deno_dir = ctx.base_dirs().home_dir().join(".deno");
 
     if !deno.canonicalize()?.is_descendant_of(&deno_dir) {
-        let skip_reason = SkipStep("Deno installed outside of .deno directory".to_string());
+        let skip_reason = SkipStep::not_applicable("Deno installed outside of .deno directory".to_string());
         return Err(skip_reason.into());
     }
 
     print_separator("Deno");
     ctx.run_type().execute(&deno).arg("upgrade").check_run()
 }
+
+pub fn run_fnm(ctx: &ExecutionContext) -> Result<StepReport> {
+    let fnm = require("fnm")?;
+
+    print_separator("fnm");
+
+    ctx.run_type().execute(&fnm).args(["install", "--lts"]).check_run()?;
+    ctx.run_type()
+        .execute(&fnm)
+        .args(["default", "lts-latest"])
+        .check_run()?;
+
+    // fnm can't update itself. Package managers take care of it, but an installation
+    // made by the install script has to be updated by running the script again
+    let base_dirs = ctx.base_dirs();
+    let script_dirs = [base_dirs.data_dir().join("fnm"), base_dirs.home_dir().join(".fnm")];
+    let fnm = fnm.canonicalize().unwrap_or(fnm);
+    debug!("fnm binary: {}", fnm.display());
+    if script_dirs.iter().any(|dir| fnm.is_descendant_of(dir)) {
+        return Ok(StepReport::with_warnings(String::from(
+            "fnm was installed by its install script, run the script again to update fnm itself",
+        )));
+    }
+
+    Ok(StepReport::default())
+}
+
+#[cfg(unix)]
+pub fn run_nvm(ctx: &ExecutionContext) -> Result<()> {
+    let bash = require("bash")?;
+
+    let nvm_script = std::env::var("NVM_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| ctx.base_dirs().home_dir().join(".nvm"))
+        .join("nvm.sh")
+        .require()?;
+
+    print_separator("nvm");
+
+    let cmd_install = format!(
+        "source {} && nvm install --lts --reinstall-packages-from=current",
+        shell_quote(&nvm_script.display().to_string())
+    );
+    ctx.run_type()
+        .execute(&bash)
+        .args(["-c", cmd_install.as_str()])
+        .check_run()
+}
@@ -4,10 +4,13 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use anyhow::Result;
+use log::debug;
 use walkdir::WalkDir;
 
 use crate::error::TopgradeError;
 use crate::execution_context::ExecutionContext;
+use crate::executor::RunType;
+use crate::terminal::print_output;
 use crate::utils::which;
 use crate::{config, Step};
 
@@ -29,11 +32,7 @@ pub struct YayParu {
 impl ArchPackageManager for YayParu {
     fn upgrade(&self, ctx: &ExecutionContext) -> Result<()> {
         if ctx.config().show_arch_news() {
-            Command::new(&self.executable)
-                .arg("-Pw")
-                .spawn()
-                .and_then(|mut p| p.wait())
-                .ok();
+            RunType::Wet.execute(&self.executable).arg("-Pw").check_run().ok();
         }
 
         let mut command = ctx.run_type().execute(&self.executable);
@@ -254,9 +253,43 @@ pub fn get_arch_package_manager(ctx: &ExecutionContext) -> Option<Box<dyn ArchPa
 }
 
 pub fn upgrade_arch_linux(ctx: &ExecutionContext) -> Result<()> {
+    // The AUR helpers run pacman through sudo themselves
+    ctx.require_sudo()?;
     let package_manager =
         get_arch_package_manager(ctx).ok_or_else(|| anyhow::Error::from(TopgradeError::FailedGettingPackageManager))?;
-    package_manager.upgrade(ctx)
+    package_manager.upgrade(ctx)?;
+
+    if ctx.config().cleanup() && ctx.config().arch_remove_orphans() {
+        remove_orphans(ctx)?;
+    }
+
+    Ok(())
+}
+
+fn remove_orphans(ctx: &ExecutionContext) -> Result<()> {
+    let pacman = which("pacman").unwrap_or_else(|| PathBuf::from("pacman"));
+
+    // pacman -Qtdq exits with 1 when there are no orphans
+    let output = Command::new(&pacman).arg("-Qtdq").output()?;
+    let orphans = String::from_utf8_lossy(&output.stdout);
+    let orphans: Vec<&str> = orphans.split_whitespace().collect();
+    debug!("Orphaned packages: {:?}", orphans);
+
+    if orphans.is_empty() {
+        return Ok(());
+    }
+
+    let sudo = ctx.require_sudo()?;
+    let mut command = ctx.run_type().execute(sudo);
+    command.arg(&pacman).arg("-Rns").args(&orphans);
+    if ctx.config().yes(Step::System) {
+        command.arg("--noconfirm");
+    }
+    command.check_run()?;
+
+    print_output(format!("Removed {} orphaned packages\n", orphans.len()));
+
+    Ok(())
 }
 
 pub fn show_pacnew() {
@@ -272,10 +305,10 @@ pub fn show_pacnew() {
         .peekable();
 
     if iter.peek().is_some() {
-        println!("\nPacman backup configuration files found:");
+        print_output("\nPacman backup configuration files found:\n");
 
         for entry in iter {
-            println!("{}", entry.path().display());
+            print_output(format!("{}\n", entry.path().display()));
         }
     }
 }
@@ -1,12 +1,12 @@
+use crate::execution_context::ExecutionContext;
 use crate::executor::RunType;
-use crate::terminal::print_separator;
-use crate::utils::require_option;
+use crate::terminal::{print_output, print_separator};
 use anyhow::Result;
 use std::path::PathBuf;
-use std::process::Command;
 
-pub fn upgrade_packages(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()> {
-    let sudo = require_option(sudo, String::from("No sudo detected"))?;
+pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let run_type = ctx.run_type();
     print_separator("DrgaonFly BSD Packages");
     run_type
         .execute(sudo)
@@ -16,11 +16,12 @@ pub fn upgrade_packages(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()>
 
 pub fn audit_packages(sudo: &Option<PathBuf>) -> Result<()> {
     if let Some(sudo) = sudo {
-        println!();
-        Command::new(sudo)
+        print_output("\n");
+        RunType::Wet
+            .execute(sudo)
             .args(&["/usr/local/sbin/pkg", "audit", "-Fr"])
-            .spawn()?
-            .wait()?;
+            // pkg audit exits with 1 when it found vulnerable packages, which it only reports
+            .check_run_with_codes(&[1])?;
     }
     Ok(())
 }
@@ -1,12 +1,14 @@
+use crate::execution_context::ExecutionContext;
 use crate::executor::RunType;
-use crate::terminal::print_separator;
-use crate::utils::require_option;
+use crate::terminal::{print_output, print_separator};
+use crate::utils::which;
+use crate::Step;
 use anyhow::Result;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 
-pub fn upgrade_freebsd(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()> {
-    let sudo = require_option(sudo, String::from("No sudo detected"))?;
+pub fn upgrade_freebsd(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let run_type = ctx.run_type();
     print_separator("FreeBSD Update");
     run_type
         .execute(sudo)
@@ -14,19 +16,84 @@ pub fn upgrade_freebsd(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()>
         .check_run()
 }
 
-pub fn upgrade_packages(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()> {
-    let sudo = require_option(sudo, String::from("No sudo detected"))?;
+pub fn upgrade_packages(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let yes = ctx.config().yes(Step::FreebsdPkg);
+    let run_type = ctx.run_type();
     print_separator("FreeBSD Packages");
-    run_type.execute(sudo).args(&["/usr/sbin/pkg", "upgrade"]).check_run()
+
+    run_type.execute(sudo).args(["/usr/sbin/pkg", "update"]).check_run()?;
+
+    let mut command = run_type.execute(sudo);
+    command.args(["/usr/sbin/pkg", "upgrade"]);
+    if yes {
+        command.arg("-y");
+    }
+    command.check_run()?;
+
+    if ctx.config().cleanup() {
+        let mut command = run_type.execute(sudo);
+        command.args(["/usr/sbin/pkg", "autoremove"]);
+        if yes {
+            command.arg("-y");
+        }
+        command.check_run()?;
+
+        let mut command = run_type.execute(sudo);
+        command.args(["/usr/sbin/pkg", "clean"]);
+        if yes {
+            command.arg("-y");
+        }
+        command.check_run()?;
+    }
+
+    if ctx.config().freebsd_update_ports() {
+        upgrade_ports_tree(ctx, sudo)?;
+    }
+
+    Ok(())
+}
+
+fn upgrade_ports_tree(ctx: &ExecutionContext, sudo: &Path) -> Result<()> {
+    let ports = Path::new("/usr/ports");
+    if !ports.exists() {
+        print_output(format!("No ports tree found in {}\n", ports.display()));
+        return Ok(());
+    }
+
+    print_output("\n");
+    print_output("Updating the ports tree\n");
+    if ports.join(".git").exists() {
+        ctx.run_type()
+            .execute(sudo)
+            .arg("git")
+            .arg("-C")
+            .arg(ports)
+            .arg("pull")
+            .check_run()
+    } else if let Some(portsnap) = which("portsnap") {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(portsnap)
+            .args(["--interactive", "fetch", "update"])
+            .check_run()
+    } else {
+        print_output(format!(
+            "The ports tree in {} is not managed by git or portsnap\n",
+            ports.display()
+        ));
+        Ok(())
+    }
 }
 
 pub fn audit_packages(sudo: &Option<PathBuf>) -> Result<()> {
     if let Some(sudo) = sudo {
-        println!();
-        Command::new(sudo)
+        print_output("\n");
+        RunType::Wet
+            .execute(sudo)
             .args(&["/usr/sbin/pkg", "audit", "-Fr"])
-            .spawn()?
-            .wait()?;
+            // pkg audit exits with 1 when it found vulnerable packages, which it only reports
+            .check_run_with_codes(&[1])?;
     }
     Ok(())
 }
@@ -1,16 +1,18 @@
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::{env, fs};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ini::Ini;
 use log::{debug, warn};
 
 use crate::error::{SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
-use crate::executor::{CommandExt, RunType};
+use crate::executor::CommandExt;
+use crate::report::StepReport;
 use crate::steps::os::archlinux;
-use crate::terminal::{print_separator, print_warning};
-use crate::utils::{require, require_option, which, PathExt};
+use crate::terminal::{print_info, print_output, print_separator, print_warning};
+use crate::utils::{require, which, PathExt};
 use crate::Step;
 
 static OS_RELEASE_PATH: &str = "/etc/os-release";
@@ -24,6 +26,7 @@ pub enum Distribution {
     CentOS,
     ClearLinux,
     Fedora,
+    FedoraSilverblue,
     Debian,
     Gentoo,
     OpenMandriva,
@@ -40,11 +43,15 @@ impl Distribution {
         let section = os_release.general_section();
         let id = section.get("ID");
         let id_like: Option<Vec<&str>> = section.get("ID_LIKE").map(|s| s.split_whitespace().collect());
+        let variant = section.get("VARIANT_ID");
 
         Ok(match id {
             Some("alpine") => Distribution::Alpine,
             Some("centos") | Some("rhel") | Some("ol") => Distribution::CentOS,
             Some("clear-linux-os") => Distribution::ClearLinux,
+            Some("fedora") if matches!(variant, Some("silverblue" | "kinoite" | "sericea" | "onyx")) => {
+                Distribution::FedoraSilverblue
+            }
             Some("fedora") | Some("nobara") => Distribution::Fedora,
             Some("void") => Distribution::Void,
             Some("debian") | Some("pureos") => Distribution::Debian,
@@ -97,6 +104,7 @@ impl Distribution {
             Distribution::Alpine => upgrade_alpine_linux(ctx),
             Distribution::Arch => archlinux::upgrade_arch_linux(ctx),
             Distribution::CentOS | Distribution::Fedora => upgrade_redhat(ctx),
+            Distribution::FedoraSilverblue => upgrade_fedora_silverblue(ctx),
             Distribution::ClearLinux => upgrade_clearlinux(ctx),
             Distribution::Debian => upgrade_debian(ctx),
             Distribution::Gentoo => upgrade_gentoo(ctx),
@@ -123,7 +131,7 @@ impl Distribution {
 }
 
 fn update_bedrock(ctx: &ExecutionContext) -> Result<()> {
-    let sudo = require_option(ctx.sudo().as_ref(), String::from("Sudo required"))?;
+    let sudo = ctx.require_sudo()?;
 
     ctx.run_type().execute(sudo).args(&["brl", "update"]);
 
@@ -155,7 +163,7 @@ fn is_wsl() -> Result<bool> {
 
 fn upgrade_alpine_linux(ctx: &ExecutionContext) -> Result<()> {
     let apk = require("apk")?;
-    let sudo = ctx.sudo().as_ref().unwrap();
+    let sudo = ctx.require_sudo()?;
 
     ctx.run_type().execute(sudo).arg(&apk).arg("update").check_run()?;
     ctx.run_type().execute(sudo).arg(&apk).arg("upgrade").check_run()
@@ -170,97 +178,128 @@ fn upgrade_redhat(ctx: &ExecutionContext) -> Result<()> {
         }
     };
 
-    if let Some(sudo) = &ctx.sudo() {
-        let mut command = ctx.run_type().execute(&sudo);
-        command
-            .arg(which("dnf").unwrap_or_else(|| Path::new("yum").to_path_buf()))
-            .arg(if ctx.config().redhat_distro_sync() {
-                "distro-sync"
-            } else {
-                "upgrade"
-            });
-
-        if let Some(args) = ctx.config().dnf_arguments() {
-            command.args(args.split_whitespace());
-        }
+    let sudo = ctx.require_sudo()?;
+    let package_manager = which("dnf").unwrap_or_else(|| Path::new("yum").to_path_buf());
+    let mut command = ctx.run_type().execute(&sudo);
+    command.arg(&package_manager).arg(if ctx.config().redhat_distro_sync() {
+        "distro-sync"
+    } else {
+        "upgrade"
+    });
 
-        if ctx.config().yes(Step::System) {
-            command.arg("-y");
+    if ctx.config().dnf_security_only() && !ctx.config().redhat_distro_sync() {
+        if supports_security_upgrades(&package_manager) {
+            command.arg("--security");
+        } else {
+            debug!(
+                "{} doesn't support --security. Applying all updates",
+                package_manager.display()
+            );
         }
+    }
 
-        command.check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
+    if let Some(args) = ctx.config().dnf_arguments() {
+        command.args(args.split_whitespace());
+    }
+
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
     }
 
+    command.check_run()?;
+
     Ok(())
 }
 
+/// Tell whether `upgrade --security` is available, which requires a security plugin on older versions
+fn supports_security_upgrades(package_manager: &Path) -> bool {
+    Command::new(package_manager)
+        .args(["upgrade", "--help"])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout).contains("--security")
+                || String::from_utf8_lossy(&output.stderr).contains("--security")
+        })
+        .unwrap_or(false)
+}
+
 fn upgrade_bedrock_strata(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = ctx.sudo() {
-        ctx.run_type().execute(&sudo).args(&["brl", "update"]).check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
-    }
+    let sudo = ctx.require_sudo()?;
+    ctx.run_type().execute(&sudo).args(&["brl", "update"]).check_run()?;
 
     Ok(())
 }
 
 fn upgrade_suse(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = ctx.sudo() {
-        ctx.run_type().execute(&sudo).args(&["zypper", "refresh"]).check_run()?;
+    let sudo = ctx.require_sudo()?;
+    let yes = ctx.config().yes(Step::System);
+    let zypper = |args: &[&str]| {
+        let mut command = ctx.run_type().execute(sudo);
+        command.arg("zypper");
+        if yes {
+            command.arg("--non-interactive");
+        }
+        command.args(args).check_run()
+    };
 
-        ctx.run_type()
-            .execute(&sudo)
-            .args(&["zypper", "dist-upgrade"])
-            .check_run()?;
+    zypper(&["refresh"])?;
+
+    if ctx.config().zypper_dist_upgrade() {
+        zypper(&["dist-upgrade"])?;
     } else {
-        print_warning("No sudo detected. Skipping system upgrade");
+        zypper(&["update"])?;
+    }
+
+    if ctx.config().cleanup() {
+        zypper(&["clean"])?;
     }
 
     Ok(())
 }
 
-fn upgrade_openmandriva(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = &ctx.sudo() {
-        let mut command = ctx.run_type().execute(&sudo);
+/// Immutable Fedora variants are upgraded with rpm-ostree, never with dnf
+fn upgrade_fedora_silverblue(ctx: &ExecutionContext) -> Result<()> {
+    let ostree = require("rpm-ostree")?;
+    ctx.run_type().execute(ostree).arg("upgrade").check_run()?;
+    print_info("rpm-ostree updates are applied on the next reboot");
 
-        command.arg(which("dnf").unwrap().to_path_buf()).arg("upgrade");
+    Ok(())
+}
 
-        if let Some(args) = ctx.config().dnf_arguments() {
-            command.args(args.split_whitespace());
-        }
+fn upgrade_openmandriva(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let mut command = ctx.run_type().execute(&sudo);
 
-        if ctx.config().yes(Step::System) {
-            command.arg("-y");
-        }
+    command.arg(which("dnf").unwrap().to_path_buf()).arg("upgrade");
 
-        command.check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
+    if let Some(args) = ctx.config().dnf_arguments() {
+        command.args(args.split_whitespace());
     }
 
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
+    }
+
+    command.check_run()?;
+
     Ok(())
 }
 
 fn upgrade_void(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = ctx.sudo() {
-        let mut command = ctx.run_type().execute(&sudo);
-        command.args(&["xbps-install", "-Su", "xbps"]);
-        if ctx.config().yes(Step::System) {
-            command.arg("-y");
-        }
-        command.check_run()?;
+    let sudo = ctx.require_sudo()?;
+    let mut command = ctx.run_type().execute(&sudo);
+    command.args(&["xbps-install", "-Su", "xbps"]);
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
+    }
+    command.check_run()?;
 
-        let mut command = ctx.run_type().execute(&sudo);
-        command.args(&["xbps-install", "-u"]);
-        if ctx.config().yes(Step::System) {
-            command.arg("-y");
-        }
-        command.check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
+    let mut command = ctx.run_type().execute(&sudo);
+    command.args(&["xbps-install", "-u"]);
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
     }
+    command.check_run()?;
 
     Ok(())
 }
@@ -268,82 +307,92 @@ fn upgrade_void(ctx: &ExecutionContext) -> Result<()> {
 fn upgrade_gentoo(ctx: &ExecutionContext) -> Result<()> {
     let run_type = ctx.run_type();
 
-    if let Some(sudo) = &ctx.sudo() {
-        if let Some(layman) = which("layman") {
-            run_type.execute(&sudo).arg(layman).args(&["-s", "ALL"]).check_run()?;
-        }
-
-        println!("Syncing portage");
-        run_type
-            .execute(&sudo)
-            .args(&["emerge", "--sync"])
-            .args(
-                ctx.config()
-                    .emerge_sync_flags()
-                    .map(|s| s.split_whitespace().collect())
-                    .unwrap_or_else(|| vec!["-q"]),
-            )
-            .check_run()?;
-
-        if let Some(eix_update) = which("eix-update") {
-            run_type.execute(&sudo).arg(eix_update).check_run()?;
-        }
+    let sudo = ctx.require_sudo()?;
+    if let Some(layman) = which("layman") {
+        run_type.execute(&sudo).arg(layman).args(&["-s", "ALL"]).check_run()?;
+    }
 
-        run_type
-            .execute(&sudo)
-            .arg("emerge")
-            .args(
-                ctx.config()
-                    .emerge_update_flags()
-                    .map(|s| s.split_whitespace().collect())
-                    .unwrap_or_else(|| vec!["-uDNa", "--with-bdeps=y", "world"]),
-            )
-            .check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
+    print_output("Syncing portage\n");
+    run_type
+        .execute(&sudo)
+        .args(&["emerge", "--sync"])
+        .args(
+            ctx.config()
+                .emerge_sync_flags()
+                .map(|s| s.split_whitespace().collect())
+                .unwrap_or_else(|| vec!["-q"]),
+        )
+        .check_run()?;
+
+    if let Some(eix_update) = which("eix-update") {
+        run_type.execute(&sudo).arg(eix_update).check_run()?;
     }
 
+    run_type
+        .execute(&sudo)
+        .arg("emerge")
+        .args(
+            ctx.config()
+                .emerge_update_flags()
+                .map(|s| s.split_whitespace().collect())
+                .unwrap_or_else(|| vec!["-uDNa", "--with-bdeps=y", "world"]),
+        )
+        .check_run()?;
+
     Ok(())
 }
 
 fn upgrade_debian(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = &ctx.sudo() {
-        let apt = which("apt-fast")
-            .or_else(|| which("nala"))
-            .unwrap_or_else(|| PathBuf::from("apt-get"));
-
-        let is_nala = apt.ends_with("nala");
-        if !is_nala {
-            ctx.run_type().execute(&sudo).arg(&apt).arg("update").check_run()?;
-        }
+    let sudo = ctx.require_sudo()?;
+    let apt = which("apt-fast")
+        .or_else(|| which("nala"))
+        .unwrap_or_else(|| PathBuf::from("apt-get"));
+
+    let is_nala = apt.ends_with("nala");
+    if !is_nala {
+        ctx.run_type().execute(&sudo).arg(&apt).arg("update").check_run()?;
+    }
+
+    let mut command = ctx.run_type().execute(&sudo);
+    command.arg(&apt);
+    if is_nala {
+        command.arg("upgrade");
+    } else {
+        command.arg("dist-upgrade");
+    };
+    if ctx.config().yes(Step::System) {
+        command.arg("-y");
+    }
+    if let Some(args) = ctx.config().apt_arguments() {
+        command.args(args.split_whitespace());
+    }
+    command.check_run()?;
+
+    if ctx.run_type().dry() {
+        preview_apt_upgrades()?;
+    }
+
+    if ctx.config().cleanup() {
+        ctx.run_type().execute(&sudo).arg(&apt).arg("clean").check_run()?;
 
         let mut command = ctx.run_type().execute(&sudo);
-        command.arg(&apt);
-        if is_nala {
-            command.arg("upgrade");
-        } else {
-            command.arg("dist-upgrade");
-        };
+        command.arg(&apt).arg("autoremove");
         if ctx.config().yes(Step::System) {
             command.arg("-y");
         }
-        if let Some(args) = ctx.config().apt_arguments() {
-            command.args(args.split_whitespace());
-        }
         command.check_run()?;
+    }
 
-        if ctx.config().cleanup() {
-            ctx.run_type().execute(&sudo).arg(&apt).arg("clean").check_run()?;
+    Ok(())
+}
 
-            let mut command = ctx.run_type().execute(&sudo);
-            command.arg(&apt).arg("autoremove");
-            if ctx.config().yes(Step::System) {
-                command.arg("-y");
-            }
-            command.check_run()?;
-        }
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
+/// Show the packages apt would upgrade according to the package lists of the last update
+fn preview_apt_upgrades() -> Result<()> {
+    let upgradable = Command::new("apt").args(["list", "--upgradable"]).check_output()?;
+
+    // The first line is "Listing..."
+    for package in upgradable.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+        print_output(format!("Would upgrade {}\n", package));
     }
 
     Ok(())
@@ -365,15 +414,49 @@ pub fn run_deb_get(ctx: &ExecutionContext) -> Result<()> {
 }
 
 fn upgrade_solus(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = ctx.sudo() {
-        ctx.run_type().execute(&sudo).args(&["eopkg", "upgrade"]).check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
-    }
+    let sudo = ctx.require_sudo()?;
+    ctx.run_type().execute(&sudo).args(&["eopkg", "upgrade"]).check_run()?;
 
     Ok(())
 }
 
+pub fn run_packagekit(ctx: &ExecutionContext) -> Result<()> {
+    let pkcon = require("pkcon")?;
+
+    if matches!(Distribution::detect(), Ok(Distribution::KDENeon)) {
+        return Err(
+            SkipStep::not_applicable(String::from("The system step already updates KDE neon with pkcon")).into(),
+        );
+    }
+
+    // Fails when the PackageKit daemon can't be reached or has no backend
+    if Command::new(&pkcon).arg("backend-details").check_output().is_err() {
+        return Err(SkipStep::not_installed(String::from("PackageKit isn't available")).into());
+    }
+
+    print_separator("PackageKit");
+
+    // PackageKit authorizes through polkit, so sudo is only used to avoid its prompts
+    let execute = || match ctx.sudo() {
+        Some(sudo) => {
+            let mut command = ctx.run_type().execute(sudo);
+            command.arg(&pkcon);
+            command
+        }
+        None => ctx.run_type().execute(&pkcon),
+    };
+
+    execute().arg("refresh").check_run()?;
+
+    let mut command = execute();
+    command.arg("update");
+    if ctx.config().yes(Step::PackageKit) {
+        command.arg("-y");
+    }
+    // from pkcon man, exit code 5 is 'Nothing useful was done.'
+    command.check_run_with_codes(&[5])
+}
+
 pub fn run_pacdef(ctx: &ExecutionContext) -> Result<()> {
     let pacdef = require("pacdef")?;
 
@@ -381,7 +464,7 @@ pub fn run_pacdef(ctx: &ExecutionContext) -> Result<()> {
 
     ctx.run_type().execute(&pacdef).arg("sync").check_run()?;
 
-    println!();
+    print_output("\n");
     ctx.run_type().execute(&pacdef).arg("review").check_run()
 }
 
@@ -395,62 +478,53 @@ pub fn run_pacstall(ctx: &ExecutionContext) -> Result<()> {
 }
 
 fn upgrade_clearlinux(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = &ctx.sudo() {
-        ctx.run_type().execute(&sudo).args(&["swupd", "update"]).check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
-    }
+    let sudo = ctx.require_sudo()?;
+    ctx.run_type().execute(&sudo).args(&["swupd", "update"]).check_run()?;
 
     Ok(())
 }
 
 fn upgrade_exherbo(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = ctx.sudo() {
-        ctx.run_type().execute(&sudo).args(&["cave", "sync"]).check_run()?;
-
-        ctx.run_type()
-            .execute(&sudo)
-            .args(&["cave", "resolve", "world", "-c1", "-Cs", "-km", "-Km", "-x"])
-            .check_run()?;
+    let sudo = ctx.require_sudo()?;
+    ctx.run_type().execute(&sudo).args(&["cave", "sync"]).check_run()?;
 
-        if ctx.config().cleanup() {
-            ctx.run_type()
-                .execute(&sudo)
-                .args(&["cave", "purge", "-x"])
-                .check_run()?;
-        }
-
-        ctx.run_type()
-            .execute(&sudo)
-            .args(&["cave", "fix-linkage", "-x", "--", "-Cs"])
-            .check_run()?;
+    ctx.run_type()
+        .execute(&sudo)
+        .args(&["cave", "resolve", "world", "-c1", "-Cs", "-km", "-Km", "-x"])
+        .check_run()?;
 
+    if ctx.config().cleanup() {
         ctx.run_type()
             .execute(&sudo)
-            .args(&["eclectic", "config", "interactive"])
+            .args(&["cave", "purge", "-x"])
             .check_run()?;
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
     }
 
+    ctx.run_type()
+        .execute(&sudo)
+        .args(&["cave", "fix-linkage", "-x", "--", "-Cs"])
+        .check_run()?;
+
+    ctx.run_type()
+        .execute(&sudo)
+        .args(&["eclectic", "config", "interactive"])
+        .check_run()?;
+
     Ok(())
 }
 
 fn upgrade_nixos(ctx: &ExecutionContext) -> Result<()> {
-    if let Some(sudo) = ctx.sudo() {
+    let sudo = ctx.require_sudo()?;
+    ctx.run_type()
+        .execute(&sudo)
+        .args(&["/run/current-system/sw/bin/nixos-rebuild", "switch", "--upgrade"])
+        .check_run()?;
+
+    if ctx.config().cleanup() {
         ctx.run_type()
             .execute(&sudo)
-            .args(&["/run/current-system/sw/bin/nixos-rebuild", "switch", "--upgrade"])
+            .args(&["/run/current-system/sw/bin/nix-collect-garbage", "-d"])
             .check_run()?;
-
-        if ctx.config().cleanup() {
-            ctx.run_type()
-                .execute(&sudo)
-                .args(&["/run/current-system/sw/bin/nix-collect-garbage", "-d"])
-                .check_run()?;
-        }
-    } else {
-        print_warning("No sudo detected. Skipping system upgrade");
     }
 
     Ok(())
@@ -462,32 +536,127 @@ fn upgrade_neon(ctx: &ExecutionContext) -> Result<()> {
     // in theory rpm based distributions use pkcon as well, though that
     // seems rare
     // if that comes up we need to create a Distribution::PackageKit or some such
-    if let Some(sudo) = &ctx.sudo() {
-        let pkcon = which("pkcon").unwrap();
-        // pkcon ignores update with update and refresh provided together
-        ctx.run_type().execute(&sudo).arg(&pkcon).arg("refresh").check_run()?;
-        let mut exe = ctx.run_type().execute(&sudo);
-        let cmd = exe.arg(&pkcon).arg("update");
-        if ctx.config().yes(Step::System) {
-            cmd.arg("-y");
+    let sudo = ctx.require_sudo()?;
+    let pkcon = which("pkcon").unwrap();
+    // pkcon ignores update with update and refresh provided together
+    ctx.run_type().execute(&sudo).arg(&pkcon).arg("refresh").check_run()?;
+    let mut exe = ctx.run_type().execute(&sudo);
+    let cmd = exe.arg(&pkcon).arg("update");
+    if ctx.config().yes(Step::System) {
+        cmd.arg("-y");
+    }
+    if ctx.config().cleanup() {
+        cmd.arg("--autoremove");
+    }
+    // from pkcon man, exit code 5 is 'Nothing useful was done.'
+    cmd.check_run_with_codes(&[5])?;
+
+    Ok(())
+}
+
+/// Tell whether the system has to be rebooted to finish applying the upgrades
+pub fn reboot_required() -> bool {
+    if Path::new("/var/run/reboot-required").exists() {
+        debug!("Found /var/run/reboot-required");
+        return true;
+    }
+
+    if let Some(needs_restarting) = which("needs-restarting") {
+        // needs-restarting -r exits with 1 when a reboot is required
+        return Command::new(needs_restarting)
+            .arg("-r")
+            .output()
+            .map(|output| output.status.code() == Some(1))
+            .unwrap_or(false);
+    }
+
+    // Containers and WSL run the kernel of the host, whose modules aren't installed in the system
+    if in_container() || is_wsl().unwrap_or(false) {
+        return false;
+    }
+
+    // The modules of the running kernel are removed when another kernel gets installed
+    let modules = Path::new("/lib/modules");
+    let installed = fs::read_dir(modules)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    match fs::read_to_string("/proc/sys/kernel/osrelease") {
+        Ok(release) if installed => {
+            let release = release.trim();
+            debug!("Running kernel {}", release);
+            !modules.join(release).exists()
         }
-        if ctx.config().cleanup() {
-            cmd.arg("--autoremove");
+        _ => false,
+    }
+}
+
+/// Whether topgrade runs in a Docker, Podman or systemd-nspawn container
+fn in_container() -> bool {
+    Path::new("/.dockerenv").exists() || Path::new("/run/.containerenv").exists() || env::var_os("container").is_some()
+}
+
+/// The running kernel first, then the other kernels in /lib/modules which have their headers installed
+fn dkms_kernels() -> Result<Vec<String>> {
+    let running = Command::new("uname").arg("-r").check_output()?.trim().to_string();
+    // Modules can only be built for the kernels whose headers are installed, the running one included
+    let mut kernels: Vec<String> = fs::read_dir("/lib/modules")?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("build").exists())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    kernels.sort();
+    // The running kernel goes first
+    kernels.sort_by_key(|kernel| *kernel != running);
+
+    Ok(kernels)
+}
+
+pub fn run_dkms(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let dkms = require("dkms")?;
+
+    let status = Command::new(&dkms).arg("status").check_output()?;
+    debug!("dkms status: {}", status);
+    if status.trim().is_empty() {
+        return Err(SkipStep::nothing_to_do(String::from("No DKMS modules are registered")).into());
+    }
+
+    let kernels = dkms_kernels()?;
+    if kernels.is_empty() {
+        return Err(SkipStep::not_applicable(String::from("No kernel headers are installed")).into());
+    }
+
+    print_separator("DKMS");
+
+    let mut failed = Vec::new();
+    for kernel in kernels {
+        if let Err(e) = ctx
+            .run_type()
+            .execute(sudo)
+            .arg(&dkms)
+            .args(["autoinstall", "-k", &kernel])
+            .check_run()
+        {
+            print_warning(format!("Failed to build the modules of {}: {}", kernel, e));
+            failed.push(kernel);
         }
-        // from pkcon man, exit code 5 is 'Nothing useful was done.'
-        cmd.check_run_with_codes(&[5])?;
     }
 
-    Ok(())
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("Failed to build the modules of {}", failed.join(", ")))
+    }
 }
 
-pub fn run_needrestart(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()> {
-    let sudo = require_option(sudo, String::from("sudo is not installed"))?;
+pub fn run_needrestart(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let run_type = ctx.run_type();
     let needrestart = require("needrestart")?;
     let distribution = Distribution::detect()?;
 
     if distribution.redhat_based() {
-        return Err(SkipStep(String::from("needrestart will be ran by the package manager")).into());
+        return Err(SkipStep::not_applicable(String::from("needrestart will be ran by the package manager")).into());
     }
 
     print_separator("Check for needed restarts");
@@ -501,7 +670,7 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
     let fwupdmgr = require("fwupdmgr")?;
 
     if is_wsl()? {
-        return Err(SkipStep(String::from("Should not run in WSL")).into());
+        return Err(SkipStep::not_applicable(String::from("Should not run in WSL")).into());
     }
 
     print_separator("Firmware upgrades");
@@ -526,7 +695,6 @@ pub fn run_fwupdmgr(ctx: &ExecutionContext) -> Result<()> {
 
 pub fn flatpak_update(ctx: &ExecutionContext) -> Result<()> {
     let flatpak = require("flatpak")?;
-    let sudo = require_option(ctx.sudo().as_ref(), String::from("sudo is not installed"))?;
     let cleanup = ctx.config().cleanup();
     let yes = ctx.config().yes(Step::Flatpak);
     let run_type = ctx.run_type();
@@ -548,6 +716,7 @@ pub fn flatpak_update(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("Flatpak System Packages");
     if ctx.config().flatpak_use_sudo() || std::env::var("SSH_CLIENT").is_ok() {
+        let sudo = ctx.require_sudo()?;
         let mut update_args = vec!["update", "--system"];
         if yes {
             update_args.push("-y");
@@ -578,20 +747,22 @@ pub fn flatpak_update(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
-pub fn run_snap(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()> {
-    let sudo = require_option(sudo, String::from("sudo is not installed"))?;
+pub fn run_snap(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let run_type = ctx.run_type();
     let snap = require("snap")?;
 
     if !PathBuf::from("/var/snapd.socket").exists() && !PathBuf::from("/run/snapd.socket").exists() {
-        return Err(SkipStep(String::from("Snapd socket does not exist")).into());
+        return Err(SkipStep::not_installed(String::from("Snapd socket does not exist")).into());
     }
     print_separator("snap");
 
     run_type.execute(sudo).arg(snap).arg("refresh").check_run()
 }
 
-pub fn run_pihole_update(sudo: Option<&PathBuf>, run_type: RunType) -> Result<()> {
-    let sudo = require_option(sudo, String::from("sudo is not installed"))?;
+pub fn run_pihole_update(ctx: &ExecutionContext) -> Result<()> {
+    let sudo = ctx.require_sudo()?;
+    let run_type = ctx.run_type();
     let pihole = require("pihole")?;
     Path::new("/opt/pihole/update.sh").require()?;
 
@@ -605,14 +776,108 @@ pub fn run_protonup_update(ctx: &ExecutionContext) -> Result<()> {
 
     print_separator("protonup");
 
-    ctx.run_type().execute(protonup).check_run()?;
-    Ok(())
+    // Without a tag protonup installs the latest Proton-GE release
+    let mut command = ctx.run_type().execute(protonup);
+    if ctx.config().yes(Step::Protonup) {
+        command.arg("--yes");
+    }
+    command.check_run()
+}
+
+fn is_appimage(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("appimage"))
+}
+
+/// The AppImages among `paths`, which are either AppImages or directories containing them
+fn appimage_files(paths: &[String]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        if path.is_dir() {
+            match fs::read_dir(path) {
+                Ok(entries) => {
+                    let mut found: Vec<PathBuf> = entries
+                        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .filter(|path| path.is_file() && is_appimage(path))
+                        .collect();
+                    found.sort();
+                    files.extend(found);
+                }
+                Err(e) => print_warning(format!("Failed to read {}: {}", path.display(), e)),
+            }
+        } else if path.is_file() {
+            files.push(path.to_path_buf());
+        } else {
+            debug!("{} does not exist", path.display());
+        }
+    }
+    files
+}
+
+pub fn run_appimage(ctx: &ExecutionContext) -> Result<StepReport> {
+    let appimageupdatetool = require("appimageupdatetool")?;
+    let paths = ctx
+        .config()
+        .appimage_paths()
+        .ok_or_else(|| SkipStep::not_applicable("No appimage_paths in the configuration"))?;
+
+    let files = appimage_files(paths);
+    if files.is_empty() {
+        return Err(SkipStep::nothing_to_do(String::from("No AppImages found")).into());
+    }
+
+    print_separator("AppImage");
+
+    let mut updated = Vec::new();
+    let mut failed = Vec::new();
+    for file in &files {
+        let name = file.file_name().unwrap_or(file.as_os_str()).to_string_lossy();
+
+        // Exits with 1 when an update is available, and with another error when the AppImage doesn't
+        // embed update information
+        let check = Command::new(&appimageupdatetool)
+            .arg("--check-for-update")
+            .arg(file)
+            .output()?;
+        match check.status.code() {
+            Some(0) => {
+                debug!("{} is up to date", file.display());
+                continue;
+            }
+            Some(1) => (),
+            _ => {
+                debug!(
+                    "Skipping {}, which has no update information: {}",
+                    file.display(),
+                    String::from_utf8_lossy(&check.stderr).trim()
+                );
+                continue;
+            }
+        }
+
+        match ctx.run_type().execute(&appimageupdatetool).arg(file).check_run() {
+            Ok(()) => updated.push(name),
+            Err(e) => {
+                print_warning(format!("Failed to update {}: {}", name, e));
+                failed.push(name);
+            }
+        }
+    }
+
+    if updated.is_empty() && !failed.is_empty() {
+        return Err(anyhow!("Failed to update {}", failed.join(", ")));
+    }
+
+    Ok(StepReport::new(
+        (!updated.is_empty()).then(|| format!("Updated {}", updated.join(", "))),
+        (!failed.is_empty()).then(|| format!("Failed to update {}", failed.join(", "))),
+    ))
 }
 
 pub fn run_config_update(ctx: &ExecutionContext) -> Result<()> {
-    let sudo = require_option(ctx.sudo().as_ref(), String::from("sudo is not installed"))?;
+    let sudo = ctx.require_sudo()?;
     if ctx.config().yes(Step::ConfigUpdate) {
-        return Err(SkipStep("Skipped in --yes".to_string()).into());
+        return Err(SkipStep::disabled("Skipped in --yes").into());
     }
 
     if let Ok(etc_update) = require("etc-update") {
@@ -642,6 +907,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_is_appimage() {
+        assert!(is_appimage(Path::new("/opt/Obsidian-1.4.16.AppImage")));
+        assert!(is_appimage(Path::new("krita.appimage")));
+        assert!(!is_appimage(Path::new("Obsidian-1.4.16.AppImage.zs-old")));
+        assert!(!is_appimage(Path::new("AppImage")));
+    }
+
     #[test]
     fn test_arch_linux() {
         test_template(include_str!("os_release/arch"), Distribution::Arch);
@@ -693,6 +966,14 @@ mod tests {
         test_template(include_str!("os_release/fedora"), Distribution::Fedora);
     }
 
+    #[test]
+    fn test_fedora_silverblue() {
+        test_template(
+            include_str!("os_release/fedorasilverblue"),
+            Distribution::FedoraSilverblue,
+        );
+    }
+
     #[test]
     fn test_antergos() {
         test_template(include_str!("os_release/antergos"), Distribution::Arch);
@@ -1,6 +1,7 @@
+use crate::error::SkipStep;
 use crate::execution_context::ExecutionContext;
-use crate::executor::{CommandExt, RunType};
-use crate::terminal::{print_separator, prompt_yesno};
+use crate::executor::CommandExt;
+use crate::terminal::{print_output, print_separator, prompt_yesno};
 use crate::{error::TopgradeError, utils::require, Step};
 use anyhow::Result;
 use log::debug;
@@ -9,7 +10,7 @@ use std::process::Command;
 
 pub fn run_macports(ctx: &ExecutionContext) -> Result<()> {
     require("port")?;
-    let sudo = ctx.sudo().as_ref().unwrap();
+    let sudo = ctx.require_sudo()?;
     print_separator("MacPorts");
     ctx.run_type().execute(sudo).args(&["port", "selfupdate"]).check_run()?;
     ctx.run_type()
@@ -26,11 +27,43 @@ pub fn run_macports(ctx: &ExecutionContext) -> Result<()> {
     Ok(())
 }
 
-pub fn run_mas(run_type: RunType) -> Result<()> {
+/// App IDs from the output of `mas outdated`, which lists one `<id> <name> (<versions>)` per line
+fn outdated_app_ids(outdated: &str) -> Vec<u64> {
+    outdated
+        .lines()
+        .filter_map(|line| line.split_whitespace().next()?.parse().ok())
+        .collect()
+}
+
+pub fn run_mas(ctx: &ExecutionContext) -> Result<()> {
     let mas = require("mas")?;
+
+    let output = Command::new(&mas).arg("outdated").output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stdout.contains("Not signed in") || stderr.contains("Not signed in") {
+        return Err(SkipStep::not_applicable(String::from("Not signed in to the App Store")).into());
+    }
+
     print_separator("macOS App Store");
 
-    run_type.execute(mas).arg("upgrade").check_run()
+    let ignored = match ctx.config().mas_ignore() {
+        Some(ignored) if !ignored.is_empty() => ignored,
+        _ => return ctx.run_type().execute(mas).arg("upgrade").check_run(),
+    };
+
+    let apps: Vec<String> = outdated_app_ids(&stdout)
+        .into_iter()
+        .filter(|id| !ignored.contains(id))
+        .map(|id| id.to_string())
+        .collect();
+    debug!("App Store apps to upgrade: {:?}", apps);
+    if apps.is_empty() {
+        print_output("No App Store apps to upgrade\n");
+        return Ok(());
+    }
+
+    ctx.run_type().execute(mas).arg("upgrade").args(apps).check_run()
 }
 
 pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
@@ -38,15 +71,15 @@ pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
 
     let should_ask = !(ctx.config().yes(Step::System)) || (ctx.config().dry_run());
     if should_ask {
-        println!("Finding available software");
+        print_output("Finding available software\n");
         if system_update_available()? {
             let answer = prompt_yesno("A system update is available. Do you wish to install it?")?;
             if !answer {
                 return Ok(());
             }
-            println!();
+            print_output("\n");
         } else {
-            println!("No new software available.");
+            print_output("No new software available.\n");
             return Ok(());
         }
     }
@@ -61,6 +94,39 @@ pub fn upgrade_macos(ctx: &ExecutionContext) -> Result<()> {
     command.check_run()
 }
 
+/// Labels of the Command Line Tools updates in the output of `softwareupdate --list`
+fn command_line_tools_labels(list: &str) -> Vec<&str> {
+    list.lines()
+        .filter_map(|line| {
+            let item = line.trim().strip_prefix("* ")?;
+            Some(item.strip_prefix("Label: ").unwrap_or(item).trim())
+        })
+        .filter(|label| label.starts_with("Command Line Tools"))
+        .collect()
+}
+
+/// Install the Command Line Tools updates offered by softwareupdate, leaving the other updates alone
+pub fn run_xcode_command_line_tools(ctx: &ExecutionContext) -> Result<()> {
+    let output = Command::new("softwareupdate").arg("--list").check_output()?;
+    let labels = command_line_tools_labels(&output);
+    debug!("Command Line Tools updates: {:?}", labels);
+    if labels.is_empty() {
+        return Err(SkipStep::nothing_to_do("No Command Line Tools update available").into());
+    }
+    let sudo = ctx.require_sudo()?;
+
+    print_separator("Xcode Command Line Tools");
+
+    for label in labels {
+        ctx.run_type()
+            .execute(sudo)
+            .args(["softwareupdate", "--install", label])
+            .check_run()?;
+    }
+
+    Ok(())
+}
+
 fn system_update_available() -> Result<bool> {
     let output = Command::new("softwareupdate").arg("--list").output()?;
     debug!("{:?}", output);
@@ -88,8 +154,41 @@ pub fn run_sparkle(ctx: &ExecutionContext) -> Result<()> {
             let mut command = ctx.run_type().execute(&sparkle);
             command.args(&["bundle", "--check-immediately", "--application"]);
             command.arg(application.path());
-            command.spawn()?.wait()?;
+            // A failed check of one application doesn't stop the others
+            if let Err(e) = command.check_run() {
+                debug!("Sparkle failed for {}: {}", application.path().display(), e);
+            }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outdated_app_ids() {
+        let outdated = "497799835 Xcode (13.0 -> 13.1)\n409183694 Keynote (12.0 -> 12.1)\n";
+        assert_eq!(outdated_app_ids(outdated), [497799835, 409183694]);
+    }
+
+    #[test]
+    fn test_command_line_tools_labels() {
+        let list = "Software Update Tool
+
+Finding available software
+Software Update found the following new or updated software:
+* Label: Command Line Tools for Xcode-14.3
+	Title: Command Line Tools for Xcode, Version: 14.3, Size: 711782KiB, Recommended: YES,
+* Label: macOS Ventura 13.4-22F66
+	Title: macOS Ventura 13.4, Version: 13.4, Size: 1224960KiB, Recommended: YES, Action: restart,
+";
+        assert_eq!(command_line_tools_labels(list), ["Command Line Tools for Xcode-14.3"]);
+        assert_eq!(
+            command_line_tools_labels("   * Command Line Tools (macOS Mojave version 10.14) for Xcode-10.3\n"),
+            ["Command Line Tools (macOS Mojave version 10.14) for Xcode-10.3"]
+        );
+        assert!(command_line_tools_labels("Software Update Tool\n\nFinding available software\n").is_empty());
+    }
+}
@@ -1,23 +1,29 @@
+use crate::config::RosBuildTool;
 use crate::error::{SkipStep, TopgradeError};
 use crate::execution_context::ExecutionContext;
-use crate::executor::{CommandExt, Executor, ExecutorExitStatus, RunType};
-use crate::terminal::print_separator;
+use crate::executor::{CommandExt, Executor, ExecutorOutput, RunType};
+use crate::report::StepReport;
+use crate::steps::registry::{elevated, sharing, FnStep, UpdateStep};
+use crate::terminal::{print_output, print_separator, print_warning};
 #[cfg(not(target_os = "macos"))]
 use crate::utils::require_option;
-use crate::utils::{require, PathExt};
+use crate::utils::{is_reachable, require, shell_quote, PathExt};
 use crate::Step;
 use anyhow::Result;
 use directories::BaseDirs;
 use ini::Ini;
 use log::debug;
+use std::collections::BTreeMap;
 use std::fs;
 use std::os::unix::fs::MetadataExt;
 use std::path::PathBuf;
 use std::process::Command;
+use std::time::Duration;
 use std::{env, path::Path};
 
 const INTEL_BREW: &str = "/usr/local/bin/brew";
 const ARM_BREW: &str = "/opt/homebrew/bin/brew";
+const SDKMAN_BROADCAST: &str = "https://api.sdkman.io/2/broadcast/latest";
 
 #[derive(Copy, Clone, Debug)]
 #[allow(dead_code)]
@@ -54,8 +60,9 @@ impl BrewVariant {
         }
     }
 
-    fn execute(self, run_type: RunType) -> Executor {
-        match self {
+    /// Run brew without its automatic update, since the step runs `brew update` itself
+    fn execute(self, ctx: &ExecutionContext, run_type: RunType) -> Executor {
+        let mut command = match self {
             BrewVariant::MacIntel if cfg!(target_arch = "aarch64") => {
                 let mut command = run_type.execute("arch");
                 command.arg("-x86_64").arg(self.binary_name());
@@ -67,7 +74,13 @@ impl BrewVariant {
                 command
             }
             _ => run_type.execute(self.binary_name()),
+        };
+
+        command.env("HOMEBREW_NO_AUTO_UPDATE", "1");
+        if ctx.config().brew_no_analytics() {
+            command.env("HOMEBREW_NO_ANALYTICS", "1");
         }
+        command
     }
 
     #[cfg(target_os = "macos")]
@@ -117,14 +130,14 @@ pub fn run_oh_my_fish(ctx: &ExecutionContext) -> Result<()> {
 pub fn run_pkgin(ctx: &ExecutionContext) -> Result<()> {
     let pkgin = require("pkgin")?;
 
-    let mut command = ctx.run_type().execute(ctx.sudo().as_ref().unwrap());
+    let mut command = ctx.run_type().execute(ctx.require_sudo()?);
     command.arg(&pkgin).arg("update");
     if ctx.config().yes(Step::Pkgin) {
         command.arg("-y");
     }
     command.check_run()?;
 
-    let mut command = ctx.run_type().execute(ctx.sudo().as_ref().unwrap());
+    let mut command = ctx.run_type().execute(ctx.require_sudo()?);
     command.arg(&pkgin).arg("upgrade");
     if ctx.config().yes(Step::Pkgin) {
         command.arg("-y");
@@ -166,7 +179,7 @@ pub fn upgrade_gnome_extensions(ctx: &ExecutionContext) -> Result<()> {
 
     debug!("Checking for gnome extensions: {}", output);
     if !output.contains("org.gnome.Shell.Extensions") {
-        return Err(SkipStep(String::from("Gnome shell extensions are unregistered in DBus")).into());
+        return Err(SkipStep::not_installed(String::from("Gnome shell extensions are unregistered in DBus")).into());
     }
 
     print_separator("Gnome Shell extensions");
@@ -186,32 +199,543 @@ pub fn upgrade_gnome_extensions(ctx: &ExecutionContext) -> Result<()> {
         .check_run()
 }
 
-pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<StepReport> {
     #[allow(unused_variables)]
     let binary_name = require(variant.binary_name())?;
 
     #[cfg(target_os = "macos")]
     {
         if variant.is_path() && !BrewVariant::is_macos_custom(binary_name) {
-            return Err(SkipStep("Not a custom brew for macOS".to_string()).into());
+            return Err(SkipStep::not_applicable("Not a custom brew for macOS".to_string()).into());
         }
     }
 
     print_separator(variant.step_title());
     let run_type = ctx.run_type();
 
-    variant.execute(run_type).arg("update").check_run()?;
-    variant
-        .execute(run_type)
-        .args(&["upgrade", "--ignore-pinned", "--formula"])
-        .check_run()?;
+    let taps_before = if ctx.config().brew_update_taps() {
+        Some(brew_tap_counts(ctx, variant)?)
+    } else {
+        None
+    };
+
+    variant.execute(ctx, run_type).arg("update").check_run()?;
+
+    let tap_changes = match taps_before {
+        Some(before) => brew_tap_changes(&before, &brew_tap_counts(ctx, variant)?),
+        None => None,
+    };
+    if let Some(tap_changes) = &tap_changes {
+        print_output(format!("{}\n", tap_changes));
+    }
+    // Formulae installed with --HEAD aren't listed as outdated without fetching them. Only the upgrade is
+    // skipped, the cleanup and the reports below still run
+    let upgrade = !(ctx.config().brew_skip_if_nothing_outdated()
+        && !ctx.config().brew_fetch_head()
+        && nothing_outdated(ctx, variant, &["--formula"])?);
+
+    let (upgrade_warnings, version_changes) = if upgrade {
+        upgrade_formulae(ctx, variant)?
+    } else {
+        print_output("No outdated formulae\n");
+        (None, None)
+    };
+
+    let (head_changes, head_warnings) = if ctx.config().brew_fetch_head() {
+        fetch_head_formulae(ctx, variant)?
+    } else {
+        (None, None)
+    };
+
+    if ctx.config().brew_report_pinned() {
+        report_pinned_formulae(ctx, variant)?;
+    }
 
     if ctx.config().cleanup() {
-        variant.execute(run_type).arg("cleanup").check_run()?;
+        variant.execute(ctx, run_type).arg("cleanup").check_run()?;
     }
 
     if ctx.config().brew_autoremove() {
-        variant.execute(run_type).arg("autoremove").check_run()?;
+        variant.execute(ctx, run_type).arg("autoremove").check_run()?;
+    }
+
+    if ctx.config().brew_restart_services() {
+        restart_brew_services(ctx, variant)?;
+    }
+
+    let relink_warnings = match ctx.config().brew_relink() {
+        Some(formulae) => relink_formulae(ctx, variant, formulae)?,
+        None => None,
+    };
+
+    let doctor_warnings = if ctx.config().brew_doctor() {
+        run_brew_doctor(ctx, variant)?
+    } else {
+        None
+    };
+
+    let changes: Vec<String> = tap_changes.into_iter().chain(head_changes).collect();
+    let warnings: Vec<String> = upgrade_warnings
+        .into_iter()
+        .chain(head_warnings)
+        .chain(relink_warnings)
+        .chain(doctor_warnings)
+        .collect();
+    Ok(StepReport::new(
+        (!changes.is_empty()).then(|| changes.join("\n")),
+        (!warnings.is_empty()).then(|| warnings.join("\n")),
+    )
+    .details(version_changes))
+}
+
+/// Upgrade the outdated formulae, returning the warnings of the upgrade and the version changes for the JSON report
+fn upgrade_formulae(
+    ctx: &ExecutionContext,
+    variant: BrewVariant,
+) -> Result<(Option<String>, Option<serde_json::Value>)> {
+    let run_type = ctx.run_type();
+
+    if run_type.dry() {
+        preview_outdated_formulae(ctx, variant)?;
+    } else if ctx.config().brew_prefetch() {
+        prefetch_outdated_formulae(ctx, variant)?;
+    }
+
+    let versions_before = if ctx.config().brew_json_report() && !run_type.dry() {
+        brew_outdated_versions(ctx, variant)
+    } else {
+        None
+    };
+
+    let upgrade_warnings = if ctx.config().brew_isolate_failures() {
+        upgrade_formulae_individually(ctx, variant)?
+    } else if ctx.config().brew_overwrite_link_conflicts() {
+        upgrade_formulae_overwriting_conflicts(ctx, variant)?
+    } else {
+        variant
+            .execute(ctx, run_type)
+            .args(&["upgrade", "--ignore-pinned", "--formula"])
+            .check_run()?;
+        None
+    };
+
+    let version_changes = versions_before.and_then(|before| {
+        let after = variant
+            .execute(ctx, RunType::Wet)
+            .args(["list", "--versions", "--formula"])
+            .check_output()
+            .map_err(|e| debug!("Failed to list the formula versions: {}", e))
+            .ok()?;
+        Some(brew_version_changes(&before, &brew_installed_versions(&after)))
+    });
+
+    Ok((upgrade_warnings, version_changes))
+}
+
+/// The installed version of each outdated formula, or `None` when brew can't tell
+fn brew_outdated_versions(ctx: &ExecutionContext, variant: BrewVariant) -> Option<BTreeMap<String, String>> {
+    let output = variant
+        .execute(ctx, RunType::Wet)
+        .args(["outdated", "--formula", "--json=v2"])
+        .check_output()
+        .map_err(|e| debug!("Failed to list the outdated formulae: {}", e))
+        .ok()?;
+    parse_outdated_versions(&output)
+}
+
+fn parse_outdated_versions(output: &str) -> Option<BTreeMap<String, String>> {
+    let outdated: serde_json::Value = serde_json::from_str(output)
+        .map_err(|e| debug!("Failed to parse brew outdated: {}", e))
+        .ok()?;
+    let versions = outdated["formulae"]
+        .as_array()?
+        .iter()
+        .filter_map(|formula| {
+            let name = formula["name"].as_str()?;
+            let installed = latest_version(
+                formula["installed_versions"]
+                    .as_array()?
+                    .iter()
+                    .filter_map(|v| v.as_str()),
+            )?;
+            Some((name.to_string(), installed.to_string()))
+        })
+        .collect();
+    Some(versions)
+}
+
+/// The latest installed version of each formula in the output of `brew list --versions`
+fn brew_installed_versions(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let name = words.next()?;
+            Some((name.to_string(), latest_version(words)?.to_string()))
+        })
+        .collect()
+}
+
+/// The highest of `versions`, which brew doesn't list in order. The numeric parts are compared as numbers,
+/// so that 1.10 comes after 1.9
+fn latest_version<'a>(versions: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let key = |version: &str| -> Vec<(u64, String)> {
+        version
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .map(|part| {
+                let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                (part[..digits].parse().unwrap_or(0), part[digits..].to_string())
+            })
+            .collect()
+    };
+    versions.max_by_key(|version| key(version))
+}
+
+/// `{formula: {from, to}}` for the formulae whose version changed
+fn brew_version_changes(before: &BTreeMap<String, String>, after: &BTreeMap<String, String>) -> serde_json::Value {
+    before
+        .iter()
+        .filter_map(|(name, from)| {
+            let to = after.get(name).filter(|to| *to != from)?;
+            Some((name.clone(), serde_json::json!({ "from": from, "to": to })))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Number of formulae and casks in each installed tap
+fn brew_tap_counts(ctx: &ExecutionContext, variant: BrewVariant) -> Result<BTreeMap<String, (usize, usize)>> {
+    let output = variant
+        .execute(ctx, RunType::Wet)
+        .args(["tap-info", "--json", "--installed"])
+        .check_output()?;
+    let taps: Vec<serde_json::Value> = serde_json::from_str(&output)?;
+
+    let count = |tap: &serde_json::Value, key: &str| tap[key].as_array().map(|a| a.len()).unwrap_or(0);
+    Ok(taps
+        .iter()
+        .filter_map(|tap| {
+            let name = tap["name"].as_str()?;
+            Some((
+                name.to_string(),
+                (count(tap, "formula_names"), count(tap, "cask_tokens")),
+            ))
+        })
+        .collect())
+}
+
+/// Describe the taps whose number of formulae or casks changed
+fn brew_tap_changes(
+    before: &BTreeMap<String, (usize, usize)>,
+    after: &BTreeMap<String, (usize, usize)>,
+) -> Option<String> {
+    let changes: Vec<String> = after
+        .iter()
+        .filter_map(|(tap, &(formulae, casks))| match before.get(tap) {
+            None => Some(format!("{}: new tap", tap)),
+            Some(&counts) if counts == (formulae, casks) => None,
+            Some(&(formulae_before, casks_before)) => Some(format!(
+                "{}: {} -> {} formulae, {} -> {} casks",
+                tap, formulae_before, formulae, casks_before, casks
+            )),
+        })
+        .collect();
+
+    if changes.is_empty() {
+        None
+    } else {
+        Some(changes.join("\n"))
+    }
+}
+
+/// Run `brew doctor` and summarize the warnings it found.
+///
+/// `brew doctor` exits with an error when it finds issues, which is only advisory here.
+fn run_brew_doctor(ctx: &ExecutionContext, variant: BrewVariant) -> Result<Option<String>> {
+    let output = match variant.execute(ctx, ctx.run_type()).arg("doctor").output()? {
+        ExecutorOutput::Wet(output) => output,
+        ExecutorOutput::Dry => return Ok(None),
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    debug!("brew doctor: {} {}", stdout, stderr);
+
+    let warnings = stdout
+        .lines()
+        .chain(stderr.lines())
+        .filter(|line| line.starts_with("Warning:"))
+        .count();
+    Ok(match warnings {
+        0 => None,
+        1 => Some(String::from("brew doctor found 1 warning")),
+        n => Some(format!("brew doctor found {} warnings", n)),
+    })
+}
+
+/// Formulae installed with `--HEAD`, from the output of `brew list --versions`
+fn head_formulae(versions: &str) -> Vec<&str> {
+    versions
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let name = words.next()?;
+            words.any(|version| version.starts_with("HEAD")).then_some(name)
+        })
+        .collect()
+}
+
+/// Rebuild the formulae installed with `--HEAD` from their latest upstream commit,
+/// describing the ones which were refreshed and the ones which failed
+fn fetch_head_formulae(ctx: &ExecutionContext, variant: BrewVariant) -> Result<(Option<String>, Option<String>)> {
+    let versions = variant
+        .execute(ctx, RunType::Wet)
+        .args(["list", "--formula", "--versions"])
+        .check_output()?;
+
+    let mut refreshed = Vec::new();
+    let mut failed = Vec::new();
+    for formula in head_formulae(&versions) {
+        match variant
+            .execute(ctx, ctx.run_type())
+            .args(["upgrade", "--fetch-HEAD", formula])
+            .check_run()
+        {
+            Ok(()) => refreshed.push(format!("Refreshed HEAD of {}", formula)),
+            Err(e) => {
+                print_warning(format!("Failed refreshing HEAD of {}: {}", formula, e));
+                failed.push(format!("Failed refreshing HEAD of {}", formula));
+            }
+        }
+    }
+
+    Ok((
+        (!refreshed.is_empty()).then(|| refreshed.join("\n")),
+        (!failed.is_empty()).then(|| failed.join("\n")),
+    ))
+}
+
+/// Whether `brew outdated` with `args` (`--formula` or `--cask`) lists nothing
+fn nothing_outdated(ctx: &ExecutionContext, variant: BrewVariant, args: &[&str]) -> Result<bool> {
+    let outdated = variant
+        .execute(ctx, RunType::Wet)
+        .arg("outdated")
+        .args(args)
+        .arg("--quiet")
+        .check_output()?;
+    debug!("brew outdated: {}", outdated);
+    Ok(outdated.trim().is_empty())
+}
+
+/// Show which formulae an upgrade would bump, without touching anything
+fn preview_outdated_formulae(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    let outdated = variant
+        .execute(ctx, RunType::Wet)
+        .args(["outdated", "--formula", "--verbose"])
+        .check_output()?;
+
+    for formula in outdated.lines().filter(|line| !line.trim().is_empty()) {
+        print_output(format!("Would upgrade {}\n", formula.trim()));
+    }
+
+    Ok(())
+}
+
+/// Download the bottles of all outdated formulae at once before upgrading them.
+///
+/// A failed download isn't fatal, the upgrade downloads what's still missing.
+fn prefetch_outdated_formulae(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    let outdated = variant
+        .execute(ctx, RunType::Wet)
+        .args(["outdated", "--formula", "--quiet"])
+        .check_output()?;
+    let formulae: Vec<&str> = outdated.split_whitespace().collect();
+    if formulae.is_empty() {
+        debug!("No outdated formulae to prefetch");
+        return Ok(());
+    }
+
+    if let Err(e) = variant
+        .execute(ctx, ctx.run_type())
+        .args(["fetch", "--formula"])
+        .args(&formulae)
+        .check_run()
+    {
+        print_warning(format!("Failed prefetching the outdated formulae: {}", e));
+    }
+
+    Ok(())
+}
+
+/// Upgrade each outdated formula on its own, describing the ones that failed
+fn upgrade_formulae_individually(ctx: &ExecutionContext, variant: BrewVariant) -> Result<Option<String>> {
+    let output = variant
+        .execute(ctx, RunType::Wet)
+        .args(["outdated", "--formula", "--json=v2"])
+        .check_output()?;
+    let outdated: serde_json::Value = serde_json::from_str(&output)?;
+    let formulae: Vec<&str> = outdated["formulae"]
+        .as_array()
+        .map(|formulae| {
+            formulae
+                .iter()
+                .filter(|formula| !formula["pinned"].as_bool().unwrap_or(false))
+                .filter_map(|formula| formula["name"].as_str())
+                .collect()
+        })
+        .unwrap_or_default();
+    debug!("Outdated formulae: {:?}", formulae);
+
+    let mut failed = Vec::new();
+    for formula in formulae {
+        if let Err(e) = variant
+            .execute(ctx, ctx.run_type())
+            .args(["upgrade", "--formula", formula])
+            .check_run()
+        {
+            print_warning(format!("Failed upgrading {}: {}", formula, e));
+            failed.push(formula);
+        }
+    }
+
+    Ok((!failed.is_empty()).then(|| format!("Failed upgrading {}", failed.join(", "))))
+}
+
+/// Link the installed formulae among `formulae` again, describing the links that failed
+fn relink_formulae(ctx: &ExecutionContext, variant: BrewVariant, formulae: &[String]) -> Result<Option<String>> {
+    let installed = variant
+        .execute(ctx, RunType::Wet)
+        .args(["list", "--formula", "-1"])
+        .check_output()?;
+    let installed: Vec<&str> = installed.split_whitespace().collect();
+
+    let mut failed = Vec::new();
+    for formula in formulae.iter().filter(|formula| installed.contains(&formula.as_str())) {
+        if let Err(e) = variant
+            .execute(ctx, ctx.run_type())
+            .args(["link", "--overwrite", formula])
+            .check_run()
+        {
+            print_warning(format!("Failed linking {}: {}", formula, e));
+            failed.push(formula.as_str());
+        }
+    }
+
+    Ok((!failed.is_empty()).then(|| format!("Failed linking {}", failed.join(", "))))
+}
+
+/// Formulae which brew failed to link, from its `brew link --overwrite <formula>` hints
+fn link_conflicts(output: &str) -> Vec<&str> {
+    let mut formulae: Vec<&str> = Vec::new();
+    for line in output.lines() {
+        let formula = line
+            .trim()
+            .strip_prefix("brew link --overwrite ")
+            .and_then(|rest| rest.split_whitespace().find(|arg| !arg.starts_with('-')));
+        if let Some(formula) = formula.filter(|formula| !formulae.contains(formula)) {
+            formulae.push(formula);
+        }
+    }
+    formulae
+}
+
+/// Upgrade the formulae, and when some of them fail to link because of leftover files, overwrite
+/// those files and upgrade again.
+///
+/// The output of the first upgrade is captured to find the conflicts, so it's only shown once it finished.
+fn upgrade_formulae_overwriting_conflicts(ctx: &ExecutionContext, variant: BrewVariant) -> Result<Option<String>> {
+    let output = match variant
+        .execute(ctx, ctx.run_type())
+        .args(["upgrade", "--ignore-pinned", "--formula"])
+        .output()?
+    {
+        ExecutorOutput::Wet(output) => output,
+        ExecutorOutput::Dry => return Ok(None),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    print_output(&stdout);
+    print_output(&stderr);
+    if output.status.success() {
+        return Ok(None);
+    }
+
+    let conflicts = link_conflicts(&stderr)
+        .into_iter()
+        .chain(link_conflicts(&stdout))
+        .collect::<Vec<_>>();
+    if conflicts.is_empty() {
+        return Err(TopgradeError::ProcessFailed(output.status).into());
+    }
+
+    for formula in &conflicts {
+        print_warning(format!(
+            "{} failed to link, overwriting the files which are in the way",
+            formula
+        ));
+        variant
+            .execute(ctx, ctx.run_type())
+            .args(["link", "--overwrite", formula])
+            .check_run()?;
+    }
+    variant
+        .execute(ctx, ctx.run_type())
+        .args(["upgrade", "--ignore-pinned", "--formula"])
+        .check_run()?;
+
+    Ok(Some(format!(
+        "Overwrote files in the way of linking {}",
+        conflicts.join(", ")
+    )))
+}
+
+/// Remind which formulae are held back by a pin
+fn report_pinned_formulae(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    let pinned = variant
+        .execute(ctx, RunType::Wet)
+        .args(["list", "--pinned"])
+        .check_output()?;
+    let pinned: Vec<&str> = pinned.split_whitespace().collect();
+
+    if !pinned.is_empty() {
+        print_output(format!("Pinned formulae: {}\n", pinned.join(", ")));
+    }
+
+    Ok(())
+}
+
+/// Restart the started services so they pick up the upgraded formulae
+fn restart_brew_services(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
+    let services = variant
+        .execute(ctx, RunType::Wet)
+        .args(["services", "list"])
+        .check_output()?;
+    debug!("brew services list: {}", services);
+
+    let started = services
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut elements = line.split_whitespace();
+            let name = elements.next()?;
+            (elements.next() == Some("started")).then_some(name)
+        })
+        .filter(|name| {
+            ctx.config()
+                .brew_services()
+                .map(|services| services.iter().any(|s| s == name))
+                .unwrap_or(true)
+        });
+
+    for service in started {
+        match variant
+            .execute(ctx, ctx.run_type())
+            .args(["services", "restart", service])
+            .check_run()
+        {
+            Ok(()) => print_output(format!("Restarted service {}\n", service)),
+            Err(e) => print_warning(format!("Failed restarting service {}: {}", service, e)),
+        }
     }
 
     Ok(())
@@ -221,13 +745,13 @@ pub fn run_brew_formula(ctx: &ExecutionContext, variant: BrewVariant) -> Result<
 pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()> {
     let binary_name = require(variant.binary_name())?;
     if variant.is_path() && !BrewVariant::is_macos_custom(binary_name) {
-        return Err(SkipStep("Not a custom brew for macOS".to_string()).into());
+        return Err(SkipStep::not_applicable("Not a custom brew for macOS".to_string()).into());
     }
     print_separator(format!("{} - Cask", variant.step_title()));
     let run_type = ctx.run_type();
 
     let cask_upgrade_exists = variant
-        .execute(RunType::Wet)
+        .execute(ctx, RunType::Wet)
         .args(&["--repository", "buo/cask-upgrade"])
         .check_output()
         .map(|p| Path::new(p.trim()).exists())?;
@@ -246,10 +770,44 @@ pub fn run_brew_cask(ctx: &ExecutionContext, variant: BrewVariant) -> Result<()>
         }
     }
 
-    variant.execute(run_type).args(&brew_args).check_run()?;
+    if ctx.config().brew_cask_no_quarantine() {
+        // Older versions of brew and brew cu don't know the flag
+        let help = variant
+            .execute(ctx, RunType::Wet)
+            .args(&brew_args[..1])
+            .arg("--help")
+            .check_output()
+            .unwrap_or_default();
+        // Current brew lists the flag as `--[no-]quarantine`
+        if help.contains("--no-quarantine") || help.contains("--[no-]quarantine") {
+            brew_args.push("--no-quarantine");
+        } else {
+            print_warning(format!(
+                "brew {} doesn't support --no-quarantine, upgrading with quarantine",
+                brew_args[0]
+            ));
+        }
+    }
+
+    let mut upgrade = true;
+    if ctx.config().brew_skip_if_nothing_outdated() {
+        let outdated_args: &[&str] = if ctx.config().brew_cask_greedy() {
+            &["--cask", "--greedy"]
+        } else {
+            &["--cask"]
+        };
+        upgrade = !nothing_outdated(ctx, variant, outdated_args)?;
+    }
+
+    // Only the upgrade is skipped when nothing is outdated, the cleanup still runs
+    if upgrade {
+        variant.execute(ctx, run_type).args(&brew_args).check_run()?;
+    } else {
+        print_output("No outdated casks\n");
+    }
 
     if ctx.config().cleanup() {
-        variant.execute(run_type).arg("cleanup").check_run()?;
+        variant.execute(ctx, run_type).arg("cleanup").check_run()?;
     }
 
     Ok(())
@@ -270,7 +828,7 @@ pub fn run_guix(ctx: &ExecutionContext) -> Result<()> {
     if should_upgrade {
         return run_type.execute(&guix).args(&["package", "-u"]).check_run();
     }
-    Err(SkipStep(String::from("Guix Pull Failed, Skipping")).into())
+    Err(SkipStep::custom(String::from("Guix Pull Failed, Skipping")).into())
 }
 
 pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
@@ -286,20 +844,27 @@ pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
 
     let multi_user = fs::metadata(&nix)?.uid() == 0;
     debug!("Multi user nix: {}", multi_user);
+    let collect_garbage = ctx.config().cleanup() && ctx.config().nix_gc();
 
     #[cfg(target_os = "linux")]
     {
         use super::linux::Distribution;
 
         if let Ok(Distribution::NixOS) = Distribution::detect() {
-            return Err(SkipStep(String::from("Nix on NixOS must be upgraded via nixos-rebuild switch")).into());
+            if collect_garbage {
+                return run_nix_collect_garbage(ctx, multi_user);
+            }
+            return Err(SkipStep::not_applicable(String::from(
+                "Nix on NixOS must be upgraded via nixos-rebuild switch",
+            ))
+            .into());
         }
     }
 
     #[cfg(target_os = "macos")]
     {
         if let Ok(..) = require("darwin-rebuild") {
-            return Err(SkipStep(String::from(
+            return Err(SkipStep::not_applicable(String::from(
                 "Nix-darwin on macOS must be upgraded via darwin-rebuild switch",
             ))
             .into());
@@ -317,7 +882,30 @@ pub fn run_nix(ctx: &ExecutionContext) -> Result<()> {
     }
 
     run_type.execute(&nix_channel).arg("--update").check_run()?;
-    run_type.execute(&nix_env).arg("--upgrade").check_run()
+    run_type.execute(&nix_env).arg("--upgrade").check_run()?;
+
+    if collect_garbage {
+        run_nix_collect_garbage(ctx, multi_user)?;
+    }
+
+    Ok(())
+}
+
+/// Delete old generations and collect garbage in the Nix store.
+///
+/// Multi user stores also get a run as root to delete the old generations of the system profiles.
+fn run_nix_collect_garbage(ctx: &ExecutionContext, multi_user: bool) -> Result<()> {
+    let nix_collect_garbage = require("nix-collect-garbage")?;
+
+    ctx.run_type().execute(&nix_collect_garbage).arg("-d").check_run()?;
+
+    if multi_user {
+        ctx.execute_elevated(&nix_collect_garbage, true)?
+            .arg("-d")
+            .check_run()?;
+    }
+
+    Ok(())
 }
 
 pub fn run_yadm(ctx: &ExecutionContext) -> Result<()> {
@@ -328,18 +916,120 @@ pub fn run_yadm(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type().execute(&yadm).arg("pull").check_run()
 }
 
-pub fn run_asdf(run_type: RunType) -> Result<()> {
+pub fn run_asdf(ctx: &ExecutionContext) -> Result<StepReport> {
     let asdf = require("asdf")?;
+    let run_type = ctx.run_type();
 
     print_separator("asdf");
-    let exit_status = run_type.execute(&asdf).arg("update").spawn()?.wait()?;
+    // asdf exits with 42 when it's already up to date
+    run_type.execute(&asdf).arg("update").check_run_with_codes(&[42])?;
+    run_type
+        .execute(&asdf)
+        .args(&["plugin", "update", "--all"])
+        .check_run()?;
 
-    if let ExecutorExitStatus::Wet(e) = exit_status {
-        if !(e.success() || e.code().map(|c| c == 42).unwrap_or(false)) {
-            return Err(TopgradeError::ProcessFailed(e).into());
+    if !ctx.config().asdf_install_latest() {
+        return Ok(StepReport::default());
+    }
+
+    let plugins = Command::new(&asdf).args(["plugin", "list"]).check_output()?;
+    let mut installed = Vec::new();
+    let mut failed = Vec::new();
+    for plugin in plugins.lines().map(str::trim).filter(|plugin| !plugin.is_empty()) {
+        let result = run_type
+            .execute(&asdf)
+            .args(["install", plugin, "latest"])
+            .check_run()
+            .and_then(|()| run_type.execute(&asdf).args(["global", plugin, "latest"]).check_run());
+        match result {
+            Ok(()) => installed.push(plugin),
+            Err(e) => {
+                print_warning(format!("Failed installing the latest {}: {}", plugin, e));
+                failed.push(plugin);
+            }
+        }
+    }
+    run_type.execute(&asdf).arg("reshim").check_run()?;
+
+    Ok(StepReport::new(
+        (!installed.is_empty()).then(|| format!("Latest versions of {}", installed.join(", "))),
+        (!failed.is_empty()).then(|| format!("Failed installing the latest {}", failed.join(", "))),
+    ))
+}
+
+/// Parse a plain `X.Y.Z` CPython version, ignoring prereleases, virtualenvs and other interpreters
+fn python_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim().split('.').map(|part| part.parse::<u32>().ok());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => Some((major, minor, patch)),
+        _ => None,
+    }
+}
+
+/// The versions from `pyenv install --list` which are newer patches of the installed series
+fn latest_python_patches(installed: &str, available: &str) -> Vec<String> {
+    let mut newest_installed: BTreeMap<(u32, u32), u32> = BTreeMap::new();
+    for (major, minor, patch) in installed.lines().filter_map(python_version) {
+        let newest = newest_installed.entry((major, minor)).or_insert(patch);
+        *newest = (*newest).max(patch);
+    }
+
+    let mut newest_available: BTreeMap<(u32, u32), u32> = BTreeMap::new();
+    for (major, minor, patch) in available.lines().filter_map(python_version) {
+        if newest_installed.contains_key(&(major, minor)) {
+            let newest = newest_available.entry((major, minor)).or_insert(patch);
+            *newest = (*newest).max(patch);
         }
     }
-    run_type.execute(&asdf).args(&["plugin", "update", "--all"]).check_run()
+
+    newest_available
+        .into_iter()
+        .filter(|(series, patch)| newest_installed.get(series).is_some_and(|installed| installed < patch))
+        .map(|((major, minor), patch)| format!("{}.{}.{}", major, minor, patch))
+        .collect()
+}
+
+pub fn run_pyenv(ctx: &ExecutionContext) -> Result<StepReport> {
+    let pyenv_root = env::var_os("PYENV_ROOT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ctx.base_dirs().home_dir().join(".pyenv"));
+    let pyenv = require("pyenv").or_else(|_| pyenv_root.join("bin").join("pyenv").require())?;
+    let run_type = ctx.run_type();
+
+    print_separator("pyenv");
+
+    if pyenv_root.join("plugins").join("pyenv-update").exists() {
+        run_type.execute(&pyenv).arg("update").check_run()?;
+    } else {
+        debug!("The pyenv-update plugin isn't installed");
+    }
+
+    if !ctx.config().pyenv_install_latest_patch() {
+        return Ok(StepReport::default());
+    }
+
+    let installed = Command::new(&pyenv).args(["versions", "--bare"]).check_output()?;
+    let available = Command::new(&pyenv).args(["install", "--list"]).check_output()?;
+    let mut installed_versions = Vec::new();
+    let mut failed = Vec::new();
+    for version in latest_python_patches(&installed, &available) {
+        match run_type
+            .execute(&pyenv)
+            .args(["install", "--skip-existing", &version])
+            .check_run()
+        {
+            Ok(()) => installed_versions.push(version),
+            Err(e) => {
+                print_warning(format!("Failed installing Python {}: {}", version, e));
+                failed.push(version);
+            }
+        }
+    }
+
+    Ok(StepReport::new(
+        (!installed_versions.is_empty()).then(|| format!("Installed Python {}", installed_versions.join(", "))),
+        (!failed.is_empty()).then(|| format!("Failed installing Python {}", failed.join(", "))),
+    ))
 }
 
 pub fn run_home_manager(run_type: RunType) -> Result<()> {
@@ -363,8 +1053,13 @@ pub fn run_pearl(run_type: RunType) -> Result<()> {
     run_type.execute(&pearl).arg("update").check_run()
 }
 
-pub fn run_sdkman(base_dirs: &BaseDirs, cleanup: bool, run_type: RunType) -> Result<()> {
-    let bash = require("bash")?;
+pub fn run_sdkman(ctx: &ExecutionContext) -> Result<()> {
+    // SDKMAN! also works in POSIX shells when it isn't used interactively, it's sourced with `.` for them
+    let shell = require("bash")
+        .or_else(|_| require("sh"))
+        .map_err(|_| SkipStep::not_installed("Neither bash nor sh is installed"))?;
+    let base_dirs = ctx.base_dirs();
+    let run_type = ctx.run_type();
 
     let sdkman_init_path = env::var("SDKMAN_DIR")
         .map(PathBuf::from)
@@ -372,7 +1067,11 @@ pub fn run_sdkman(base_dirs: &BaseDirs, cleanup: bool, run_type: RunType) -> Res
         .join("bin")
         .join("sdkman-init.sh")
         .require()
-        .map(|p| format!("{}", &p.display()))?;
+        .map(|p| shell_quote(&p.display().to_string()))?;
+
+    if ctx.config().sdkman_offline_check() && !is_reachable(SDKMAN_BROADCAST, Duration::from_secs(3)) {
+        return Err(SkipStep::offline(String::from("SDKMAN! is offline")).into());
+    }
 
     print_separator("SDKMAN!");
 
@@ -390,32 +1089,35 @@ pub fn run_sdkman(base_dirs: &BaseDirs, cleanup: bool, run_type: RunType) -> Res
         .unwrap_or("false");
 
     if selfupdate_enabled == "true" {
-        let cmd_selfupdate = format!("source {} && sdk selfupdate", &sdkman_init_path);
+        let cmd_selfupdate = format!(". {} && sdk selfupdate", &sdkman_init_path);
         run_type
-            .execute(&bash)
+            .execute(&shell)
             .args(&["-c", cmd_selfupdate.as_str()])
             .check_run()?;
     }
 
-    let cmd_update = format!("source {} && sdk update", &sdkman_init_path);
-    run_type.execute(&bash).args(&["-c", cmd_update.as_str()]).check_run()?;
+    let cmd_update = format!(". {} && sdk update", &sdkman_init_path);
+    run_type
+        .execute(&shell)
+        .args(&["-c", cmd_update.as_str()])
+        .check_run()?;
 
-    let cmd_upgrade = format!("source {} && sdk upgrade", &sdkman_init_path);
+    let cmd_upgrade = format!(". {} && sdk upgrade", &sdkman_init_path);
     run_type
-        .execute(&bash)
+        .execute(&shell)
         .args(&["-c", cmd_upgrade.as_str()])
         .check_run()?;
 
-    if cleanup {
-        let cmd_flush_archives = format!("source {} && sdk flush archives", &sdkman_init_path);
+    if ctx.config().cleanup() {
+        let cmd_flush_archives = format!(". {} && sdk flush archives", &sdkman_init_path);
         run_type
-            .execute(&bash)
+            .execute(&shell)
             .args(&["-c", cmd_flush_archives.as_str()])
             .check_run()?;
 
-        let cmd_flush_temp = format!("source {} && sdk flush temp", &sdkman_init_path);
+        let cmd_flush_temp = format!(". {} && sdk flush temp", &sdkman_init_path);
         run_type
-            .execute(&bash)
+            .execute(&shell)
             .args(&["-c", cmd_flush_temp.as_str()])
             .check_run()?;
     }
@@ -423,6 +1125,62 @@ pub fn run_sdkman(base_dirs: &BaseDirs, cleanup: bool, run_type: RunType) -> Res
     Ok(())
 }
 
+/// The setup script of the configured ROS distribution
+fn ros_setup(ctx: &ExecutionContext) -> Result<PathBuf> {
+    let ros_dir = Path::new("/opt/ros");
+
+    let distro = match ctx.config().ros_distro() {
+        Some(distro) => distro.to_string(),
+        None => match env::var("ROS_DISTRO") {
+            Ok(distro) => distro,
+            Err(_) => {
+                let mut distros: Vec<String> = fs::read_dir(ros_dir)
+                    .map(|entries| {
+                        entries
+                            .filter_map(|entry| entry.ok())
+                            .filter(|entry| entry.path().join("setup.bash").is_file())
+                            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                debug!("ROS distributions: {:?}", distros);
+                match distros.len() {
+                    0 => return Err(SkipStep::not_installed(String::from("No ROS installation found")).into()),
+                    1 => distros.remove(0),
+                    _ => {
+                        return Err(SkipStep::not_applicable(format!(
+                            "Several ROS distributions are installed ({}), set ros_distro to pick one",
+                            distros.join(", ")
+                        ))
+                        .into())
+                    }
+                }
+            }
+        },
+    };
+
+    ros_dir.join(distro).join("setup.bash").require()
+}
+
+pub fn run_ros_workspace(ctx: &ExecutionContext, workspace: &str, build_tool: RosBuildTool) -> Result<()> {
+    let bash = require("bash")?;
+    let setup = ros_setup(ctx)?;
+    let workspace = PathBuf::from(shellexpand::tilde(workspace).into_owned()).require()?;
+
+    print_separator(format!("ROS ({})", workspace.display()));
+
+    let build = match build_tool {
+        RosBuildTool::Colcon => "colcon build",
+        RosBuildTool::Catkin => "catkin build",
+    };
+    let cmd_build = format!("source {} && {}", shell_quote(&setup.display().to_string()), build);
+    ctx.run_type()
+        .execute(&bash)
+        .args(["-c", cmd_build.as_str()])
+        .current_dir(&workspace)
+        .check_run()
+}
+
 pub fn run_bun(ctx: &ExecutionContext) -> Result<()> {
     let bun = require("bun")?;
 
@@ -431,7 +1189,261 @@ pub fn run_bun(ctx: &ExecutionContext) -> Result<()> {
     ctx.run_type().execute(&bun).arg("upgrade").check_run()
 }
 
-pub fn reboot() {
+/// Runs one of the brew steps for the given binary
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+struct BrewStep {
+    step: Step,
+    name: &'static str,
+    variant: BrewVariant,
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+impl UpdateStep for BrewStep {
+    fn step(&self) -> Step {
+        self.step
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    /// The formulae and casks of one brew share its prefix
+    fn resource(&self) -> Option<&'static str> {
+        Some(self.variant.binary_name())
+    }
+
+    fn run(&self, ctx: &ExecutionContext) -> Result<()> {
+        self.run_with_report(ctx).map(|_| ())
+    }
+
+    fn run_with_report(&self, ctx: &ExecutionContext) -> Result<StepReport> {
+        match self.step {
+            #[cfg(target_os = "macos")]
+            Step::BrewCask => run_brew_cask(ctx, self.variant).map(|()| StepReport::default()),
+            _ => run_brew_formula(ctx, self.variant),
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn brew_step(step: Step, name: &'static str, variant: BrewVariant) -> Box<dyn UpdateStep> {
+    Box::new(BrewStep { step, name, variant })
+}
+
+/// Brew formulae and casks, for every brew installation of the platform
+#[cfg(target_os = "macos")]
+pub fn brew_steps() -> Vec<Box<dyn UpdateStep>> {
+    vec![
+        brew_step(Step::BrewFormula, "Brew (ARM)", BrewVariant::MacArm),
+        brew_step(Step::BrewFormula, "Brew (Intel)", BrewVariant::MacIntel),
+        brew_step(Step::BrewFormula, "Brew", BrewVariant::Path),
+        brew_step(Step::BrewCask, "Brew Cask (ARM)", BrewVariant::MacArm),
+        brew_step(Step::BrewCask, "Brew Cask (Intel)", BrewVariant::MacIntel),
+        brew_step(Step::BrewCask, "Brew Cask", BrewVariant::Path),
+    ]
+}
+
+/// The formula steps of the ARM and Intel brews, which `[brew] parallel_variants` runs at the same time,
+/// and the other brew steps. Nothing runs at the same time unless both brews are installed
+#[cfg(target_os = "macos")]
+pub fn parallel_brew_steps() -> (Vec<Box<dyn UpdateStep>>, Vec<Box<dyn UpdateStep>>) {
+    if !BrewVariant::both_both_exist() {
+        return (Vec::new(), brew_steps());
+    }
+
+    (
+        vec![
+            brew_step(Step::BrewFormula, "Brew (ARM)", BrewVariant::MacArm),
+            brew_step(Step::BrewFormula, "Brew (Intel)", BrewVariant::MacIntel),
+        ],
+        vec![
+            brew_step(Step::BrewFormula, "Brew", BrewVariant::Path),
+            brew_step(Step::BrewCask, "Brew Cask (ARM)", BrewVariant::MacArm),
+            brew_step(Step::BrewCask, "Brew Cask (Intel)", BrewVariant::MacIntel),
+            brew_step(Step::BrewCask, "Brew Cask", BrewVariant::Path),
+        ],
+    )
+}
+
+#[cfg(target_os = "linux")]
+pub fn brew_steps() -> Vec<Box<dyn UpdateStep>> {
+    vec![brew_step(Step::BrewFormula, "Brew", BrewVariant::Path)]
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+#[allow(dead_code)]
+pub fn brew_steps() -> Vec<Box<dyn UpdateStep>> {
+    Vec::new()
+}
+
+/// Package managers that work the same way on every unix
+pub fn package_manager_steps() -> Vec<Box<dyn UpdateStep>> {
+    vec![
+        FnStep::boxed(Step::Yadm, "yadm", run_yadm),
+        elevated(FnStep::boxed(Step::Nix, "nix", run_nix)),
+        FnStep::boxed(Step::Guix, "guix", run_guix),
+        FnStep::boxed_with_report(Step::Asdf, "asdf", run_asdf),
+        FnStep::boxed_with_report(Step::Pyenv, "pyenv", run_pyenv),
+        elevated(FnStep::boxed(Step::Pkgin, "pkgin", run_pkgin)),
+        FnStep::boxed(Step::Bun, "bun", run_bun),
+    ]
+}
+
+/// Shell plugin managers
+pub fn shell_steps() -> Vec<Box<dyn UpdateStep>> {
+    vec![
+        sharing(
+            "fish",
+            FnStep::boxed(Step::Shell, "fisher", |ctx| run_fisher(ctx.base_dirs(), ctx.run_type())),
+        ),
+        FnStep::boxed(Step::Shell, "bash-it", run_bashit),
+        sharing("fish", FnStep::boxed(Step::Shell, "oh-my-fish", run_oh_my_fish)),
+        sharing("fish", FnStep::boxed(Step::Shell, "fish-plug", run_fish_plug)),
+    ]
+}
+
+/// Standalone tools that update themselves or their content
+pub fn tool_steps() -> Vec<Box<dyn UpdateStep>> {
+    #[allow(unused_mut)]
+    let mut steps = vec![
+        FnStep::boxed(Step::Tldr, "TLDR", |ctx| run_tldr(ctx.run_type())),
+        FnStep::boxed(Step::Pearl, "pearl", |ctx| run_pearl(ctx.run_type())),
+    ];
+    #[cfg(not(any(target_os = "macos", target_os = "android")))]
+    steps.push(FnStep::boxed(
+        Step::GnomeShellExtensions,
+        "Gnome Shell Extensions",
+        upgrade_gnome_extensions,
+    ));
+    steps.push(FnStep::boxed(Step::Sdkman, "SDKMAN!", run_sdkman));
+
+    steps
+}
+
+pub fn reboot(sudo: Option<&PathBuf>) {
     print!("Rebooting...");
-    Command::new("sudo").arg("reboot").spawn().unwrap().wait().unwrap();
+    let mut command = match sudo {
+        Some(sudo) => {
+            let mut command = Command::new(sudo);
+            command.arg("reboot");
+            command
+        }
+        None => Command::new("reboot"),
+    };
+    command.spawn().unwrap().wait().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(steps: &[Box<dyn UpdateStep>]) -> Vec<&str> {
+        steps.iter().map(|step| step.name()).collect()
+    }
+
+    #[test]
+    fn test_head_formulae() {
+        let versions = "git 2.38.1\nneovim HEAD-4a5ec2d\nhelix 22.08.1 HEAD-1b2c3d4\n";
+        assert_eq!(head_formulae(versions), ["neovim", "helix"]);
+    }
+
+    #[test]
+    fn test_brew_version_changes() {
+        let outdated = r#"{"formulae": [
+            {"name": "git", "installed_versions": ["2.41.0"], "current_version": "2.42.0", "pinned": false},
+            {"name": "node", "installed_versions": ["20.5.0"], "current_version": "20.6.1", "pinned": true}
+        ], "casks": []}"#;
+        let before = parse_outdated_versions(outdated).unwrap();
+        let after = brew_installed_versions("git 2.9.5 2.42.0 2.10.1\nnode 20.5.0\nwget 1.21.4\n");
+        assert_eq!(
+            brew_version_changes(&before, &after),
+            serde_json::json!({"git": {"from": "2.41.0", "to": "2.42.0"}})
+        );
+        assert!(parse_outdated_versions("Error: not json").is_none());
+    }
+
+    #[test]
+    fn test_link_conflicts() {
+        let output = "Error: The `brew link` step did not complete successfully
+The formula built, but is not symlinked into /usr/local
+Could not symlink bin/2to3
+Target /usr/local/bin/2to3
+already exists. You may want to remove it:
+  rm '/usr/local/bin/2to3'
+
+To force the link and overwrite all conflicting files:
+  brew link --overwrite python@3.9
+
+To list all files that would be deleted:
+  brew link --overwrite --dry-run python@3.9
+";
+        assert_eq!(link_conflicts(output), ["python@3.9"]);
+        assert!(link_conflicts("Error: No such keg: /usr/local/Cellar/foo").is_empty());
+    }
+
+    #[test]
+    fn test_latest_python_patches() {
+        let installed = "system\n3.10.4\n3.11.2\n3.11.4\n3.11.4/envs/tools\ntools\n";
+        let available =
+            "Available versions:\n  3.10.4\n  3.10.12\n  3.11.4\n  3.11.5\n  3.12.0\n  3.12.1rc1\n  pypy3.9-7.3.11\n";
+        assert_eq!(latest_python_patches(installed, available), ["3.10.12", "3.11.5"]);
+        assert!(latest_python_patches("3.12.0\n", available).is_empty());
+    }
+
+    #[test]
+    fn test_package_manager_steps() {
+        assert_eq!(
+            names(&package_manager_steps()),
+            ["yadm", "nix", "guix", "asdf", "pyenv", "pkgin", "bun"]
+        );
+    }
+
+    #[test]
+    fn test_shell_steps() {
+        assert!(shell_steps().iter().all(|step| step.step() == Step::Shell));
+        assert_eq!(names(&shell_steps()), ["fisher", "bash-it", "oh-my-fish", "fish-plug"]);
+    }
+
+    #[test]
+    #[cfg(not(any(target_os = "macos", target_os = "android")))]
+    fn test_tool_steps() {
+        assert_eq!(
+            names(&tool_steps()),
+            ["TLDR", "pearl", "Gnome Shell Extensions", "SDKMAN!"]
+        );
+    }
+
+    #[test]
+    #[cfg(any(target_os = "macos", target_os = "android"))]
+    fn test_tool_steps() {
+        assert_eq!(names(&tool_steps()), ["TLDR", "pearl", "SDKMAN!"]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_brew_steps() {
+        assert_eq!(names(&brew_steps()), ["Brew"]);
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn test_brew_steps() {
+        assert_eq!(
+            names(&brew_steps()),
+            [
+                "Brew (ARM)",
+                "Brew (Intel)",
+                "Brew",
+                "Brew Cask (ARM)",
+                "Brew Cask (Intel)",
+                "Brew Cask"
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    fn test_brew_steps() {
+        assert!(brew_steps().is_empty());
+    }
 }
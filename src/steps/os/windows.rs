@@ -1,5 +1,5 @@
 use std::convert::TryFrom;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::{ffi::OsStr, process::Command};
 
 use anyhow::Result;
@@ -7,7 +7,7 @@ use log::debug;
 
 use crate::execution_context::ExecutionContext;
 use crate::executor::{CommandExt, RunType};
-use crate::terminal::{print_separator, print_warning};
+use crate::terminal::{print_output, print_separator, print_warning};
 use crate::utils::require;
 use crate::{error::SkipStep, steps::git::Repositories};
 use crate::{powershell, Step};
@@ -44,7 +44,7 @@ pub fn run_winget(ctx: &ExecutionContext) -> Result<()> {
 
     if !ctx.config().enable_winget() {
         print_warning("Winget is disabled by default. Enable it by setting enable_winget=true in the [windows] section in the configuration.");
-        return Err(SkipStep(String::from("Winget is disabled by default")).into());
+        return Err(SkipStep::disabled(String::from("Winget is disabled by default")).into());
     }
 
     ctx.run_type().execute(&winget).args(&["upgrade", "--all"]).check_run()
@@ -78,7 +78,7 @@ fn upgrade_wsl_distribution(wsl: &Path, dist: &str, ctx: &ExecutionContext) -> R
     let topgrade = Command::new(&wsl)
         .args(&["-d", dist, "bash", "-lc", "which topgrade"])
         .check_output()
-        .map_err(|_| SkipStep(String::from("Could not find Topgrade installed in WSL")))?;
+        .map_err(|_| SkipStep::not_installed(String::from("Could not find Topgrade installed in WSL")))?;
 
     let mut command = ctx.run_type().execute(&wsl);
     command
@@ -113,7 +113,7 @@ pub fn run_wsl_topgrade(ctx: &ExecutionContext) -> Result<()> {
     if ran {
         Ok(())
     } else {
-        Err(SkipStep(String::from("Could not find Topgrade in any WSL disribution")).into())
+        Err(SkipStep::not_installed(String::from("Could not find Topgrade in any WSL disribution")).into())
     }
 }
 
@@ -128,12 +128,13 @@ pub fn windows_update(ctx: &ExecutionContext) -> Result<()> {
     let usoclient = require("UsoClient")?;
 
     print_separator("Windows Update");
-    println!("Running Windows Update. Check the control panel for progress.");
+    print_output("Running Windows Update. Check the control panel for progress.\n");
     ctx.run_type().execute(&usoclient).arg("ScanInstallWait").check_run()?;
     ctx.run_type().execute(&usoclient).arg("StartInstall").check_run()
 }
 
-pub fn reboot() {
+/// Windows doesn't need to be elevated to reboot, the argument is there to match `unix::reboot`
+pub fn reboot(_sudo: Option<&PathBuf>) {
     Command::new("shutdown").args(&["/R", "/T", "0"]).spawn().ok();
 }
 
@@ -0,0 +1,117 @@
+//! Steps defined by the user in `topgrade.d/*.toml`, next to the configuration file
+use std::env::consts::OS;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use directories::BaseDirs;
+use log::{debug, error};
+use serde::Deserialize;
+
+use crate::config::config_directory;
+use crate::execution_context::ExecutionContext;
+use crate::terminal::{print_separator, shell};
+use crate::utils::require;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Plugin {
+    /// The name in the summary (default: the name of the file)
+    name: Option<String>,
+    /// The command running the update, through the default shell
+    command: String,
+    /// A binary which has to be in the PATH for the step to run
+    require: Option<String>,
+    /// The command run with `--cleanup`
+    cleanup: Option<String>,
+    /// Values of `std::env::consts::OS` such as linux, macos or windows (default: all of them)
+    platforms: Option<Vec<String>>,
+}
+
+impl Plugin {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_default()
+    }
+
+    fn supports(&self, os: &str) -> bool {
+        self.platforms
+            .as_ref()
+            .is_none_or(|platforms| platforms.iter().any(|platform| platform == os))
+    }
+}
+
+fn parse(path: &Path) -> Result<Plugin> {
+    let mut plugin: Plugin = toml::from_str(&fs::read_to_string(path)?)?;
+    if plugin.name.is_none() {
+        plugin.name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned());
+    }
+    Ok(plugin)
+}
+
+/// The plugins for this platform, sorted by file name. Plugins which fail to load are skipped
+pub fn load(base_dirs: &BaseDirs) -> Vec<Plugin> {
+    let directory = config_directory(base_dirs).join("topgrade.d");
+    let mut paths: Vec<_> = match fs::read_dir(&directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+            .collect(),
+        Err(e) => {
+            debug!("No plugins in {}: {}", directory.display(), e);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+
+    paths
+        .iter()
+        .filter_map(|path| {
+            parse(path)
+                .map_err(|e| error!("Failed to load the plugin {}: {}", path.display(), e))
+                .ok()
+        })
+        .filter(|plugin| plugin.supports(OS))
+        .collect()
+}
+
+pub fn run_plugin(plugin: &Plugin, ctx: &ExecutionContext) -> Result<()> {
+    if let Some(binary) = &plugin.require {
+        require(binary)?;
+    }
+
+    print_separator(plugin.name());
+
+    ctx.run_type()
+        .execute(shell())
+        .arg("-c")
+        .arg(&plugin.command)
+        .check_run()?;
+
+    if let Some(cleanup) = plugin.cleanup.as_ref().filter(|_| ctx.config().cleanup()) {
+        ctx.run_type().execute(shell()).arg("-c").arg(cleanup).check_run()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_platforms() {
+        let plugin: Plugin = toml::from_str(
+            r#"
+command = "foo update"
+require = "foo"
+platforms = ["linux", "macos"]
+"#,
+        )
+        .unwrap();
+        assert!(plugin.supports("linux"));
+        assert!(!plugin.supports("windows"));
+
+        let plugin: Plugin = toml::from_str(r#"command = "foo update""#).unwrap();
+        assert!(plugin.supports("freebsd"));
+    }
+}
@@ -7,7 +7,7 @@ use anyhow::Result;
 
 use crate::execution_context::ExecutionContext;
 use crate::executor::CommandExt;
-use crate::terminal::{is_dumb, print_separator};
+use crate::terminal::{is_dumb, print_output, print_separator};
 use crate::utils::{require_option, which, PathExt};
 use crate::Step;
 
@@ -76,7 +76,7 @@ impl Powershell {
             cmd.push("-Force")
         }
 
-        println!("Updating modules...");
+        print_output("Updating modules...\n");
         ctx.run_type()
             .execute(&powershell)
             .args(&["-NoProfile", "-Command", &cmd.join(" ")])
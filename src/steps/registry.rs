@@ -0,0 +1,224 @@
+use anyhow::Result;
+
+use crate::config::Step;
+use crate::execution_context::ExecutionContext;
+use crate::report::StepReport;
+
+/// What a step needs to run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Privileges {
+    /// The step only touches what belongs to the user
+    User,
+    /// The step runs sudo, which can prompt for a password, so it never runs at the same time as other steps
+    Sudo,
+}
+
+/// A step updating one tool, described as data and run by the runner.
+///
+/// Steps are `Sync` so that `Runner::execute_parallel` can run several of them at the same time
+pub trait UpdateStep: Sync {
+    /// The step used to decide whether this step is enabled
+    fn step(&self) -> Step;
+
+    /// The key under which the step appears in the summary
+    fn name(&self) -> &str;
+
+    /// Whether the step makes sense in this context at all. Whether its tools are installed is told by
+    /// the `require` calls of `run`, which skip the step
+    fn detect(&self, _ctx: &ExecutionContext) -> bool {
+        true
+    }
+
+    fn privileges(&self) -> Privileges {
+        Privileges::User
+    }
+
+    /// What the step can't share with other steps running at the same time, like a package database.
+    /// Steps using the same resource run one after the other
+    fn resource(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn run(&self, ctx: &ExecutionContext) -> Result<()>;
+
+    /// Like `run`, but for steps that can report what they changed
+    fn run_with_report(&self, ctx: &ExecutionContext) -> Result<StepReport> {
+        self.run(ctx).map(|()| StepReport::default())
+    }
+
+    /// Remove what the update left behind, like old versions or caches. Runs after a successful `run` when
+    /// cleanup is enabled
+    fn cleanup(&self, _ctx: &ExecutionContext) -> Result<()> {
+        Ok(())
+    }
+}
+
+enum StepFn {
+    Plain(fn(&ExecutionContext) -> Result<()>),
+    WithReport(fn(&ExecutionContext) -> Result<StepReport>),
+}
+
+/// A step backed by a plain function
+pub struct FnStep {
+    step: Step,
+    name: &'static str,
+    run: StepFn,
+    cleanup: Option<fn(&ExecutionContext) -> Result<()>>,
+}
+
+impl FnStep {
+    pub fn boxed(step: Step, name: &'static str, run: fn(&ExecutionContext) -> Result<()>) -> Box<dyn UpdateStep> {
+        Box::new(Self {
+            step,
+            name,
+            run: StepFn::Plain(run),
+            cleanup: None,
+        })
+    }
+
+    /// Like `boxed`, with a function for `UpdateStep::cleanup`
+    pub fn boxed_with_cleanup(
+        step: Step,
+        name: &'static str,
+        run: fn(&ExecutionContext) -> Result<()>,
+        cleanup: fn(&ExecutionContext) -> Result<()>,
+    ) -> Box<dyn UpdateStep> {
+        Box::new(Self {
+            step,
+            name,
+            run: StepFn::Plain(run),
+            cleanup: Some(cleanup),
+        })
+    }
+
+    /// Like `boxed`, for functions reporting what they changed
+    pub fn boxed_with_report(
+        step: Step,
+        name: &'static str,
+        run: fn(&ExecutionContext) -> Result<StepReport>,
+    ) -> Box<dyn UpdateStep> {
+        Box::new(Self {
+            step,
+            name,
+            run: StepFn::WithReport(run),
+            cleanup: None,
+        })
+    }
+}
+
+impl UpdateStep for FnStep {
+    fn step(&self) -> Step {
+        self.step
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn run(&self, ctx: &ExecutionContext) -> Result<()> {
+        self.run_with_report(ctx).map(|_| ())
+    }
+
+    fn run_with_report(&self, ctx: &ExecutionContext) -> Result<StepReport> {
+        match self.run {
+            StepFn::Plain(run) => run(ctx).map(|()| StepReport::default()),
+            StepFn::WithReport(run) => run(ctx),
+        }
+    }
+
+    fn cleanup(&self, ctx: &ExecutionContext) -> Result<()> {
+        match self.cleanup {
+            Some(cleanup) => cleanup(ctx),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A step using a resource, see `UpdateStep::resource`
+struct Sharing {
+    resource: &'static str,
+    step: Box<dyn UpdateStep>,
+}
+
+/// Mark `step` as using `resource`
+pub fn sharing(resource: &'static str, step: Box<dyn UpdateStep>) -> Box<dyn UpdateStep> {
+    Box::new(Sharing { resource, step })
+}
+
+impl UpdateStep for Sharing {
+    fn step(&self) -> Step {
+        self.step.step()
+    }
+
+    fn name(&self) -> &str {
+        self.step.name()
+    }
+
+    fn detect(&self, ctx: &ExecutionContext) -> bool {
+        self.step.detect(ctx)
+    }
+
+    fn privileges(&self) -> Privileges {
+        self.step.privileges()
+    }
+
+    fn resource(&self) -> Option<&'static str> {
+        Some(self.resource)
+    }
+
+    fn run(&self, ctx: &ExecutionContext) -> Result<()> {
+        self.step.run(ctx)
+    }
+
+    fn run_with_report(&self, ctx: &ExecutionContext) -> Result<StepReport> {
+        self.step.run_with_report(ctx)
+    }
+
+    fn cleanup(&self, ctx: &ExecutionContext) -> Result<()> {
+        self.step.cleanup(ctx)
+    }
+}
+
+/// A step running sudo, see `Privileges::Sudo`
+struct Elevated {
+    step: Box<dyn UpdateStep>,
+}
+
+/// Mark `step` as running sudo
+pub fn elevated(step: Box<dyn UpdateStep>) -> Box<dyn UpdateStep> {
+    Box::new(Elevated { step })
+}
+
+impl UpdateStep for Elevated {
+    fn step(&self) -> Step {
+        self.step.step()
+    }
+
+    fn name(&self) -> &str {
+        self.step.name()
+    }
+
+    fn detect(&self, ctx: &ExecutionContext) -> bool {
+        self.step.detect(ctx)
+    }
+
+    fn privileges(&self) -> Privileges {
+        Privileges::Sudo
+    }
+
+    fn resource(&self) -> Option<&'static str> {
+        self.step.resource()
+    }
+
+    fn run(&self, ctx: &ExecutionContext) -> Result<()> {
+        self.step.run(ctx)
+    }
+
+    fn run_with_report(&self, ctx: &ExecutionContext) -> Result<StepReport> {
+        self.step.run_with_report(ctx)
+    }
+
+    fn cleanup(&self, ctx: &ExecutionContext) -> Result<()> {
+        self.step.cleanup(ctx)
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Result};
+use clap::crate_version;
+use log::{debug, error};
+
+use crate::execution_context::ExecutionContext;
+use crate::executor::CommandExt;
+use crate::terminal::print_info;
+use crate::utils::{shell_quote, which};
+
+/// Where the releases of topgrade are downloaded from, by version
+const RELEASES: &str = concat!(env!("CARGO_PKG_REPOSITORY"), "/releases/download");
+
+/// The target of the release for the system described by `uname -sm`
+fn release_target(uname: &str) -> Option<&'static str> {
+    let mut parts = uname.split_whitespace();
+    let target = match (parts.next()?, parts.next()?) {
+        ("Linux", "x86_64") => "x86_64-unknown-linux-musl",
+        ("Linux", "aarch64") | ("Linux", "arm64") => "aarch64-unknown-linux-musl",
+        ("Linux", "armv7l") => "armv7-unknown-linux-gnueabihf",
+        ("Darwin", "x86_64") => "x86_64-apple-darwin",
+        ("Darwin", "arm64") => "aarch64-apple-darwin",
+        ("FreeBSD", "amd64") => "x86_64-unknown-freebsd",
+        _ => return None,
+    };
+    Some(target)
+}
+
+/// The topgrade binary for `target`, downloaded from the releases unless it's in the cache already
+fn local_binary(ctx: &ExecutionContext, target: &str) -> Result<PathBuf> {
+    let cache = ctx
+        .config()
+        .remote_deploy_cache()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| ctx.base_dirs().cache_dir().join("topgrade").join("remote"));
+    let directory = cache.join(crate_version!()).join(target);
+    let binary = directory.join("topgrade");
+    if binary.exists() {
+        debug!("Deploying {}", binary.display());
+        return Ok(binary);
+    }
+
+    let curl = which("curl").ok_or_else(|| anyhow!("curl is required to download topgrade for {}", target))?;
+    let url = format!(
+        "{}/v{}/topgrade-v{}-{}.tar.gz",
+        RELEASES,
+        crate_version!(),
+        crate_version!(),
+        target
+    );
+    print_info(format!("Downloading {}", url));
+
+    fs::create_dir_all(&directory)?;
+    let archive = directory.join("topgrade.tar.gz");
+    Command::new(curl)
+        .args(["--fail", "--location", "--silent", "--show-error", "--output"])
+        .arg(&archive)
+        .arg(&url)
+        .check_output()?;
+    let extracted = Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&directory)
+        .check_output();
+    fs::remove_file(&archive).ok();
+    extracted?;
+
+    if !binary.exists() {
+        return Err(anyhow!("The release at {} doesn't contain topgrade", url));
+    }
+    Ok(binary)
+}
+
+/// Run `command` with the shell of the user on the host, returning its output
+fn remote_output(ssh: &Path, destination: &[&str], command: &str) -> Result<String> {
+    Command::new(ssh)
+        .arg("-n")
+        .args(destination)
+        .arg(command)
+        .check_output()
+}
+
+/// A topgrade uploaded to a temporary directory of a remote host. The directory is removed when dropped
+pub struct Deployed<'a> {
+    ssh: &'a Path,
+    destination: &'a [&'a str],
+    directory: String,
+}
+
+impl Deployed<'_> {
+    pub fn path(&self) -> String {
+        format!("{}/topgrade", self.directory)
+    }
+}
+
+impl Drop for Deployed<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = remote_output(
+            self.ssh,
+            self.destination,
+            &format!("rm -rf {}", shell_quote(&self.directory)),
+        ) {
+            error!("Failed to remove {} from the remote host: {}", self.directory, e);
+        }
+    }
+}
+
+/// Upload topgrade to the host reached with the `destination` arguments of ssh, unless it's installed there
+pub fn deploy<'a>(ctx: &ExecutionContext, ssh: &'a Path, destination: &'a [&'a str]) -> Result<Option<Deployed<'a>>> {
+    let installed = format!("$SHELL -lc 'command -v {}'", ctx.config().remote_topgrade_path());
+    if remote_output(ssh, destination, &installed).is_ok() {
+        return Ok(None);
+    }
+
+    let uname = remote_output(ssh, destination, "uname -sm")?;
+    let target =
+        release_target(&uname).ok_or_else(|| anyhow!("There is no release of topgrade for {}", uname.trim()))?;
+    let binary = local_binary(ctx, target)?;
+
+    let directory = remote_output(ssh, destination, "mktemp -d")?.trim().to_string();
+    let deployed = Deployed {
+        ssh,
+        destination,
+        directory,
+    };
+    print_info(format!("Uploading topgrade for {} to {}", target, deployed.path()));
+    Command::new(ssh)
+        .args(destination)
+        .arg(format!("cat > {0} && chmod +x {0}", shell_quote(&deployed.path())))
+        .stdin(File::open(&binary)?)
+        .check_output()?;
+
+    Ok(Some(deployed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_target() {
+        assert_eq!(release_target("Linux x86_64\n"), Some("x86_64-unknown-linux-musl"));
+        assert_eq!(release_target("Darwin arm64\n"), Some("aarch64-apple-darwin"));
+        assert_eq!(release_target("SunOS i86pc\n"), None);
+        assert_eq!(release_target(""), None);
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+
+use crate::config::{Kubernetes, KubernetesTarget};
+use crate::error::SkipStep;
+use crate::execution_context::ExecutionContext;
+use crate::terminal::print_separator;
+use crate::utils::require;
+
+/// The arguments of kubectl running `command` in the pod of the target
+fn exec_args<'a>(
+    kubernetes: &'a Kubernetes,
+    name: &'a str,
+    target: &'a KubernetesTarget,
+    command: &'a str,
+) -> Vec<&'a str> {
+    let mut args = Vec::new();
+    if let Some(context) = kubernetes.context(target) {
+        args.extend(["--context", context]);
+    }
+    if let Some(namespace) = kubernetes.namespace(target) {
+        args.extend(["--namespace", namespace]);
+    }
+
+    args.extend(["exec", target.target(name)]);
+    if let Some(container) = target.container() {
+        args.extend(["--container", container]);
+    }
+
+    args.extend(["--", "sh", "-c", command]);
+    args
+}
+
+/// Run the commands of the target `name` of `[kubernetes]` in its pod
+pub fn run_target(
+    ctx: &ExecutionContext,
+    kubernetes: &Kubernetes,
+    name: &str,
+    target: &KubernetesTarget,
+) -> Result<()> {
+    let kubectl = require("kubectl")?;
+    if target.commands().is_empty() {
+        return Err(SkipStep::nothing_to_do(format!("No commands for {}", name)).into());
+    }
+
+    print_separator(format!("Kubernetes ({})", name));
+
+    for command in target.commands() {
+        ctx.run_type()
+            .execute(&kubectl)
+            .args(exec_args(kubernetes, name, target, command))
+            .check_run()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_args() {
+        let kubernetes: Kubernetes = toml::from_str(
+            r#"
+            context = "homelab"
+            namespace = "default"
+
+            [targets.pihole]
+            target = "deployment/pihole"
+            namespace = "dns"
+            container = "pihole"
+            commands = ["apt-get update"]
+
+            [targets.shell]
+            commands = ["apk upgrade"]
+            "#,
+        )
+        .unwrap();
+        let targets: Vec<_> = kubernetes.targets().collect();
+
+        let (name, target) = targets[0];
+        assert_eq!(
+            exec_args(&kubernetes, name, target, "apt-get update"),
+            [
+                "--context",
+                "homelab",
+                "--namespace",
+                "dns",
+                "exec",
+                "deployment/pihole",
+                "--container",
+                "pihole",
+                "--",
+                "sh",
+                "-c",
+                "apt-get update"
+            ]
+        );
+
+        let (name, target) = targets[1];
+        assert_eq!(
+            exec_args(&kubernetes, name, target, "apk upgrade"),
+            [
+                "--context",
+                "homelab",
+                "--namespace",
+                "default",
+                "exec",
+                "shell",
+                "--",
+                "sh",
+                "-c",
+                "apk upgrade"
+            ]
+        );
+    }
+}
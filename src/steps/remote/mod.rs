@@ -1,2 +1,4 @@
+pub mod deploy;
+pub mod kubernetes;
 pub mod ssh;
 pub mod vagrant;
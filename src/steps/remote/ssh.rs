@@ -1,31 +1,163 @@
 use anyhow::Result;
+use clap::ArgEnum;
 
-use crate::{error::SkipStep, execution_context::ExecutionContext, terminal::print_separator, utils};
+use crate::config::{RemoteHost, Step};
+use crate::error::{FailedWithOutput, SkipStep, TopgradeError};
+use crate::execution_context::ExecutionContext;
+use crate::executor::{Executor, ExecutorOutput};
+use crate::report::StepReport;
+use crate::steps::registry::UpdateStep;
+use crate::steps::remote::deploy::{deploy, Deployed};
+use crate::terminal::{self, print_output, print_separator};
+use crate::utils::{self, shell_quote};
 
 fn prepare_async_ssh_command(args: &mut Vec<&str>) {
     args.insert(0, "ssh");
     args.push("--keep");
 }
 
-pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<()> {
+/// The steps that failed on the remote host, from the summary at the end of its output
+fn failed_steps(output: &str) -> Vec<&str> {
+    let summary = output
+        .rfind("Summary")
+        .map(|start| &output[start..])
+        .unwrap_or_default();
+    summary
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(_, status)| status.trim_start().starts_with("FAILED"))
+        .map(|(key, _)| key.trim())
+        .collect()
+}
+
+/// Run the remote topgrade with its output held back, with the hostname in front of each line so that the
+/// hosts upgraded at the same time can be told apart. The steps which failed on the host go to the summary
+fn run_held_back(command: &mut Executor, hostname: &str) -> Result<StepReport> {
+    let output = match command.output()? {
+        ExecutorOutput::Wet(output) => output,
+        ExecutorOutput::Dry => return Ok(StepReport::default()),
+    };
+
+    let text = console::strip_ansi_codes(&String::from_utf8_lossy(&output.stdout)).into_owned()
+        + &console::strip_ansi_codes(&String::from_utf8_lossy(&output.stderr));
+    let prefixed: String = text.lines().map(|line| format!("{}: {}\n", hostname, line)).collect();
+    print_output(prefixed);
+
+    if output.status.success() {
+        return Ok(StepReport::default());
+    }
+
+    let failed = failed_steps(&text);
+    Err(FailedWithOutput {
+        message: TopgradeError::ProcessFailed(output.status).to_string(),
+        output: if failed.is_empty() {
+            String::new()
+        } else {
+            format!("Failed steps: {}", failed.join(", "))
+        },
+        exit_code: output.status.code(),
+    }
+    .into())
+}
+
+/// Quote `text` as a PowerShell string literal
+fn powershell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Encode a script for `powershell -EncodedCommand`. Nothing in it has to be quoted for cmd.exe, the default
+/// shell of the OpenSSH server of Windows, and then for PowerShell
+fn encode_powershell(script: &str) -> String {
+    let utf16: Vec<u8> = script.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect();
+    base64::encode(utf16)
+}
+
+/// The arguments of topgrade on the host from its `[remote.hosts."name"]`
+fn topgrade_arguments(host: &RemoteHost) -> Vec<String> {
+    let mut arguments = Vec::new();
+    for (flag, steps) in [("--only", host.only()), ("--disable", host.disable())] {
+        if !steps.is_empty() {
+            arguments.push(flag.to_string());
+            arguments.extend(
+                steps
+                    .iter()
+                    .filter_map(Step::to_possible_value)
+                    .map(|value| value.get_name().to_string()),
+            );
+        }
+    }
+    arguments.extend(
+        host.arguments()
+            .into_iter()
+            .flat_map(str::split_whitespace)
+            .map(String::from),
+    );
+    arguments
+}
+
+/// The command running `topgrade` on the host, in a login shell or in PowerShell on Windows
+fn remote_command(ctx: &ExecutionContext, hostname: &str, topgrade: &str) -> Vec<String> {
+    let host = ctx.config().remote_host(hostname);
+    let env: Vec<(&String, &String)> = host.into_iter().flat_map(RemoteHost::env).collect();
+    let arguments = host.map(topgrade_arguments).unwrap_or_default();
+
+    if ctx.config().remote_windows(hostname) {
+        let mut script = format!("$env:TOPGRADE_PREFIX = {}; ", powershell_quote(hostname));
+        for (key, value) in env {
+            script += &format!("$env:{} = {}; ", key, powershell_quote(value));
+        }
+        script += &format!("& {}", powershell_quote(topgrade));
+        for argument in &arguments {
+            script += &format!(" {}", powershell_quote(argument));
+        }
+        script += "; exit $LASTEXITCODE";
+
+        vec![
+            String::from("powershell"),
+            String::from("-NoProfile"),
+            String::from("-EncodedCommand"),
+            encode_powershell(&script),
+        ]
+    } else {
+        let mut command = vec![String::from("env"), format!("TOPGRADE_PREFIX={}", hostname)];
+        command.extend(
+            env.into_iter()
+                .map(|(key, value)| format!("{}={}", key, shell_quote(value))),
+        );
+        command.extend([String::from("$SHELL"), String::from("-lc")]);
+        if arguments.is_empty() {
+            command.push(topgrade.to_string());
+        } else {
+            // ssh joins its arguments, so the whole command line is a single word for the remote shell
+            command.push(shell_quote(&format!("{} {}", topgrade, arguments.join(" "))));
+        }
+        command
+    }
+}
+
+pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<StepReport> {
     let ssh = utils::require("ssh")?;
 
-    let topgrade = ctx.config().remote_topgrade_path();
+    let topgrade = ctx.config().remote_topgrade_path_of(hostname);
     let mut args = vec!["-t", hostname];
 
     if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
         args.extend(ssh_arguments.split_whitespace());
     }
+    if let Some(ssh_arguments) = ctx.config().remote_host(hostname).and_then(RemoteHost::ssh_arguments) {
+        args.extend(ssh_arguments.split_whitespace());
+    }
 
-    let env = format!("TOPGRADE_PREFIX={}", hostname);
-    args.extend(&["env", &env, "$SHELL", "-lc", topgrade]);
+    let remote = remote_command(ctx, hostname, topgrade);
+    args.extend(remote.iter().map(String::as_str));
 
     if ctx.config().run_in_tmux() && !ctx.run_type().dry() {
         #[cfg(unix)]
         {
             prepare_async_ssh_command(&mut args);
             crate::tmux::run_command(ctx, &args.join(" "))?;
-            Err(SkipStep(String::from("Remote Topgrade launched in Tmux")).into())
+            Err(SkipStep::custom(String::from("Remote Topgrade launched in Tmux")).into())
         }
 
         #[cfg(not(unix))]
@@ -33,20 +165,124 @@ pub fn ssh_step(ctx: &ExecutionContext, hostname: &str) -> Result<()> {
     } else if ctx.config().open_remotes_in_new_terminal() && !ctx.run_type().dry() && cfg!(windows) {
         prepare_async_ssh_command(&mut args);
         ctx.run_type().execute("wt").args(&args).spawn()?;
-        Err(SkipStep(String::from("Remote Topgrade launched in an external terminal")).into())
+        Err(SkipStep::custom(String::from("Remote Topgrade launched in an external terminal")).into())
     } else {
-        let mut args = vec!["-t", hostname];
-
+        let mut destination = vec![hostname];
         if let Some(ssh_arguments) = ctx.config().ssh_arguments() {
-            args.extend(ssh_arguments.split_whitespace());
+            destination.extend(ssh_arguments.split_whitespace());
+        }
+        if let Some(ssh_arguments) = ctx.config().remote_host(hostname).and_then(RemoteHost::ssh_arguments) {
+            destination.extend(ssh_arguments.split_whitespace());
         }
-
-        let env = format!("TOPGRADE_PREFIX={}", hostname);
-        args.extend(&["env", &env, "$SHELL", "-lc", topgrade]);
 
         print_separator(format!("Remote ({})", hostname));
-        println!("Connecting to {}...", hostname);
+        print_output(format!("Connecting to {}...\n", hostname));
+
+        // The uploaded topgrade is removed once it ran
+        let deployed =
+            if ctx.config().remote_deploy() && !ctx.config().remote_windows(hostname) && !ctx.run_type().dry() {
+                deploy(ctx, &ssh, &destination)?
+            } else {
+                None
+            };
+        let deployed_path = deployed.as_ref().map(Deployed::path);
+        let topgrade = deployed_path.as_deref().unwrap_or(topgrade);
+
+        // Several hosts upgraded at the same time can't share the terminal, nor read its input
+        let held_back = terminal::capturing();
+        let mut args = if held_back { vec!["-n"] } else { vec!["-t"] };
+        args.extend(&destination);
+
+        let remote = remote_command(ctx, hostname, topgrade);
+        args.extend(remote.iter().map(String::as_str));
+
+        let mut command = ctx.run_type().execute(&ssh);
+        command.args(&args);
+        if held_back {
+            run_held_back(&mut command, hostname)
+        } else {
+            command.check_run().map(|()| StepReport::default())
+        }
+    }
+}
+
+/// The upgrade of one of the `remote_topgrades`, so that `Runner::execute_parallel` can upgrade several
+/// hosts at the same time
+pub struct RemoteStep {
+    hostname: String,
+    name: String,
+}
+
+impl RemoteStep {
+    pub fn boxed(hostname: &str) -> Box<dyn UpdateStep> {
+        Box::new(Self {
+            hostname: hostname.to_string(),
+            name: format!("Remote ({})", hostname),
+        })
+    }
+}
+
+impl UpdateStep for RemoteStep {
+    fn step(&self) -> Step {
+        Step::Remotes
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, ctx: &ExecutionContext) -> bool {
+        ctx.config().should_execute_remote(&self.hostname)
+    }
+
+    fn run(&self, ctx: &ExecutionContext) -> Result<()> {
+        self.run_with_report(ctx).map(|_| ())
+    }
+
+    fn run_with_report(&self, ctx: &ExecutionContext) -> Result<StepReport> {
+        ssh_step(ctx, &self.hostname)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_steps() {
+        let output = "― 10:00:00 - System update ―\nFAILED\n― 10:00:05 - Summary ―\nSystem update: FAILED\nrustup:        OK\npip3:          FAILED\nGit repositories: SKIPPED: No repositories to pull\n";
+        assert_eq!(failed_steps(output), ["System update", "pip3"]);
+        assert!(failed_steps("Connecting to pi...\n").is_empty());
+    }
+
+    #[test]
+    fn test_powershell() {
+        assert_eq!(
+            powershell_quote("C:\\Program Files\\it's.exe"),
+            "'C:\\Program Files\\it''s.exe'"
+        );
+        // [Convert]::ToBase64String([Text.Encoding]::Unicode.GetBytes('dir'))
+        assert_eq!(encode_powershell("dir"), "ZABpAHIA");
+    }
 
-        ctx.run_type().execute(&ssh).args(&args).check_run()
+    #[test]
+    fn test_topgrade_arguments() {
+        let host: RemoteHost = toml::from_str(
+            r#"
+            disable = ["gnome_shell_extensions", "jetbrains"]
+            arguments = "--cleanup --no-retry"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            topgrade_arguments(&host),
+            [
+                "--disable",
+                "gnome_shell_extensions",
+                "jetbrains",
+                "--cleanup",
+                "--no-retry"
+            ]
+        );
     }
 }
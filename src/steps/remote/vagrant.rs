@@ -9,7 +9,7 @@ use strum::EnumString;
 
 use crate::execution_context::ExecutionContext;
 use crate::executor::CommandExt;
-use crate::terminal::print_separator;
+use crate::terminal::{print_output, print_separator};
 use crate::{error::SkipStep, utils, Step};
 
 #[derive(Debug, Copy, Clone, EnumString)]
@@ -136,7 +136,7 @@ impl<'a> Drop for TemporaryPowerOn<'a> {
             }
         };
 
-        println!();
+        print_output("\n");
         self.ctx
             .run_type()
             .execute(self.vagrant)
@@ -157,7 +157,7 @@ pub fn collect_boxes(ctx: &ExecutionContext) -> Result<Vec<VagrantBox>> {
     };
 
     print_separator("Vagrant");
-    println!("Collecting Vagrant boxes");
+    print_output("Collecting Vagrant boxes\n");
 
     let mut result = Vec::new();
 
@@ -182,7 +182,7 @@ pub fn topgrade_vagrant_box(ctx: &ExecutionContext, vagrant_box: &VagrantBox) ->
     let mut _poweron = None;
     if !vagrant_box.initial_status.powered_on() {
         if !(ctx.config().vagrant_power_on().unwrap_or(true)) {
-            return Err(SkipStep(format!("Skipping powered off box {}", vagrant_box)).into());
+            return Err(SkipStep::not_applicable(format!("Skipping powered off box {}", vagrant_box)).into());
         } else {
             print_separator(seperator);
             _poweron = Some(vagrant.temporary_power_on(vagrant_box, ctx)?);
@@ -226,7 +226,7 @@ pub fn upgrade_vagrant_boxes(ctx: &ExecutionContext) -> Result<()> {
     }
 
     if !found {
-        println!("No outdated boxes")
+        print_output("No outdated boxes\n")
     } else {
         ctx.run_type().execute(&vagrant).args(&["box", "prune"]).check_run()?;
     }
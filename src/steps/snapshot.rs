@@ -0,0 +1,217 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use log::debug;
+
+use crate::config::{Snapshot as SnapshotConfig, SnapshotBackend};
+use crate::execution_context::ExecutionContext;
+use crate::executor::CommandExt;
+use crate::terminal::print_separator;
+use crate::utils::which;
+
+/// The prefix of the names of the btrfs and ZFS snapshots taken by Topgrade, followed by their time
+const PREFIX: &str = "topgrade-";
+
+/// A snapshot taken before the system update
+pub enum Snapshot {
+    Snapper { config: String, number: String },
+    Timeshift(String),
+    Btrfs { subvolume: String, path: String },
+    Zfs(String),
+}
+
+impl fmt::Display for Snapshot {
+    /// The snapshot, with how to roll back to it
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Snapshot::Snapper { config, number } => write!(
+                f,
+                "snapper snapshot {}, roll back with `sudo snapper -c {} rollback {}`",
+                number, config, number
+            ),
+            Snapshot::Timeshift(name) => write!(
+                f,
+                "Timeshift snapshot {}, roll back with `sudo timeshift --restore --snapshot '{}'`",
+                name, name
+            ),
+            Snapshot::Btrfs { subvolume, path } => {
+                write!(f, "btrfs snapshot {} of the subvolume {}", path, subvolume)
+            }
+            Snapshot::Zfs(name) => write!(
+                f,
+                "ZFS snapshot {}, roll back with `sudo zfs rollback -r {}`",
+                name, name
+            ),
+        }
+    }
+}
+
+/// The path of the tool of the backend. Unlike with `require`, the step fails without it, since the system
+/// update doesn't run without the snapshot
+fn require_backend(binary: &str) -> Result<PathBuf> {
+    which(binary).ok_or_else(|| anyhow!("{} is not installed", binary))
+}
+
+/// The names of the snapshots to remove to keep only `keep` of them, the oldest first
+fn expired(mut names: Vec<String>, keep: usize) -> Vec<String> {
+    // The time in the names sorts them from the oldest
+    names.sort();
+    let expired = names.len().saturating_sub(keep);
+    names.truncate(expired);
+    names
+}
+
+/// The name of the timeshift snapshot from the output of `timeshift --create`
+fn timeshift_name(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Tagged snapshot '"))
+        .and_then(|rest| rest.split('\'').next())
+}
+
+fn snapper(ctx: &ExecutionContext, sudo: &Path, config: &SnapshotConfig) -> Result<Snapshot> {
+    let snapper = require_backend("snapper")?;
+
+    // The number cleanup algorithm lets snapper remove the snapshot once there are too many
+    let number = ctx
+        .run_type()
+        .execute(sudo)
+        .arg(snapper)
+        .args(["-c", config.snapper_config(), "create", "--type", "single"])
+        .args([
+            "--cleanup-algorithm",
+            "number",
+            "--print-number",
+            "--description",
+            "topgrade",
+        ])
+        .check_output()?
+        .trim()
+        .to_string();
+
+    Ok(Snapshot::Snapper {
+        config: config.snapper_config().to_string(),
+        number,
+    })
+}
+
+fn timeshift(ctx: &ExecutionContext, sudo: &Path) -> Result<Snapshot> {
+    let timeshift = require_backend("timeshift")?;
+
+    let output = ctx
+        .run_type()
+        .execute(sudo)
+        .arg(timeshift)
+        .args(["--create", "--scripted", "--comments", "topgrade"])
+        .check_output()?;
+    debug!("timeshift output: {}", output);
+
+    timeshift_name(&output)
+        .map(|name| Snapshot::Timeshift(name.to_string()))
+        .ok_or_else(|| anyhow!("Cannot find the name of the snapshot in the output of timeshift"))
+}
+
+fn btrfs(ctx: &ExecutionContext, sudo: &Path, config: &SnapshotConfig, name: &str) -> Result<Snapshot> {
+    let btrfs = require_backend("btrfs")?;
+    let directory = config
+        .directory()
+        .ok_or_else(|| anyhow!("Set the directory of the btrfs snapshots in [snapshot]"))?;
+
+    let path = Path::new(directory).join(name);
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&btrfs)
+        .args(["subvolume", "snapshot", "-r", config.subvolume()])
+        .arg(&path)
+        .check_run()?;
+
+    let names = std::fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(PREFIX))
+        .collect();
+    for name in expired(names, config.keep()) {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(&btrfs)
+            .args(["subvolume", "delete"])
+            .arg(Path::new(directory).join(name))
+            .check_run()?;
+    }
+
+    Ok(Snapshot::Btrfs {
+        subvolume: config.subvolume().to_string(),
+        path: path.to_string_lossy().into_owned(),
+    })
+}
+
+fn zfs(ctx: &ExecutionContext, sudo: &Path, config: &SnapshotConfig, name: &str) -> Result<Snapshot> {
+    let zfs = require_backend("zfs")?;
+    let dataset = config
+        .dataset()
+        .ok_or_else(|| anyhow!("Set the dataset of the ZFS snapshots in [snapshot]"))?;
+
+    let snapshot = format!("{}@{}", dataset, name);
+    ctx.run_type()
+        .execute(sudo)
+        .arg(&zfs)
+        .args(["snapshot", &snapshot])
+        .check_run()?;
+
+    let names = std::process::Command::new(&zfs)
+        .args(["list", "-H", "-t", "snapshot", "-o", "name", dataset])
+        .check_output()?
+        .lines()
+        .filter(|line| line.starts_with(&format!("{}@{}", dataset, PREFIX)))
+        .map(String::from)
+        .collect();
+    for name in expired(names, config.keep()) {
+        ctx.run_type()
+            .execute(sudo)
+            .arg(&zfs)
+            .args(["destroy", &name])
+            .check_run()?;
+    }
+
+    Ok(Snapshot::Zfs(snapshot))
+}
+
+/// Take the snapshot of the `[snapshot]` configuration
+pub fn create(ctx: &ExecutionContext, config: &SnapshotConfig) -> Result<Snapshot> {
+    let sudo = ctx.require_sudo()?;
+    let name = format!("{}{}", PREFIX, Local::now().format("%Y-%m-%d_%H-%M-%S"));
+
+    print_separator("Snapshot");
+
+    match config.backend() {
+        SnapshotBackend::Snapper => snapper(ctx, sudo, config),
+        SnapshotBackend::Timeshift => timeshift(ctx, sudo),
+        SnapshotBackend::Btrfs => btrfs(ctx, sudo, config, &name),
+        SnapshotBackend::Zfs => zfs(ctx, sudo, config, &name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired() {
+        let names = vec![
+            String::from("topgrade-2024-01-03_10-00-00"),
+            String::from("topgrade-2024-01-01_10-00-00"),
+            String::from("topgrade-2024-01-02_10-00-00"),
+        ];
+        assert_eq!(expired(names.clone(), 2), ["topgrade-2024-01-01_10-00-00"]);
+        assert!(expired(names, 5).is_empty());
+    }
+
+    #[test]
+    fn test_timeshift_name() {
+        let output = "Creating new snapshot...(RSYNC)\nSaving to device: /dev/sda1\nTagged snapshot '2024-01-10_12-00-01': ondemand\n";
+        assert_eq!(timeshift_name(output), Some("2024-01-10_12-00-01"));
+        assert_eq!(timeshift_name("E: Failed"), None);
+    }
+}
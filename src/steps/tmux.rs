@@ -1,4 +1,4 @@
-use crate::executor::RunType;
+use crate::executor::{Executor, RunType};
 use crate::terminal::print_separator;
 use crate::{
     execution_context::ExecutionContext,
@@ -46,6 +46,15 @@ impl Tmux {
         command
     }
 
+    /// Like `build`, but the output of the command goes through the executor of the steps
+    fn execute(&self) -> Executor {
+        let mut command = RunType::Wet.execute(&self.tmux);
+        if let Some(args) = self.args.as_ref() {
+            command.args(args).env_remove("TMUX");
+        }
+        command
+    }
+
     fn has_session(&self, session_name: &str) -> Result<bool, io::Error> {
         Ok(self
             .build()
@@ -109,10 +118,8 @@ pub fn run_in_tmux(args: &Option<String>) -> ! {
 
 pub fn run_command(ctx: &ExecutionContext, command: &str) -> Result<()> {
     Tmux::new(ctx.config().tmux_arguments())
-        .build()
+        .execute()
         .args(&["new-window", "-a", "-t", "topgrade:1", command])
         .env_remove("TMUX")
-        .spawn()?
-        .wait()?
-        .check()
+        .check_run()
 }
@@ -2,7 +2,7 @@ use crate::error::{SkipStep, TopgradeError};
 use anyhow::Result;
 
 use crate::executor::{CommandExt, ExecutorOutput, RunType};
-use crate::terminal::print_separator;
+use crate::terminal::{print_output, print_separator};
 use crate::{
     execution_context::ExecutionContext,
     utils::{require, PathExt},
@@ -70,7 +70,7 @@ fn upgrade(vim: &Path, vimrc: &Path, ctx: &ExecutionContext) -> Result<()> {
         if !status.success() {
             return Err(TopgradeError::ProcessFailed(status).into());
         } else {
-            println!("Plugins upgraded")
+            print_output("Plugins upgraded\n")
         }
     }
 
@@ -114,7 +114,7 @@ pub fn upgrade_vim(base_dirs: &BaseDirs, ctx: &ExecutionContext) -> Result<()> {
 
     let output = Command::new(&vim).arg("--version").check_output()?;
     if !output.starts_with("VIM") {
-        return Err(SkipStep(String::from("vim binary might by actually nvim")).into());
+        return Err(SkipStep::not_applicable(String::from("vim binary might by actually nvim")).into());
     }
 
     let vimrc = vimrc(base_dirs)?;
@@ -1,7 +1,7 @@
 use crate::execution_context::ExecutionContext;
 use crate::executor::{CommandExt, RunType};
 use crate::git::Repositories;
-use crate::terminal::print_separator;
+use crate::terminal::{print_output, print_separator};
 use crate::utils::{require, PathExt};
 use anyhow::Result;
 use directories::BaseDirs;
@@ -160,7 +160,7 @@ pub fn run_oh_my_zsh(ctx: &ExecutionContext) -> Result<()> {
 
     custom_repos.remove(&oh_my_zsh.to_string_lossy());
     if !custom_repos.is_empty() {
-        println!("Pulling custom plugins and themes");
+        print_output("Pulling custom plugins and themes\n");
         ctx.git().multi_pull(&custom_repos, ctx)?;
     }
 
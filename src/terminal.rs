@@ -1,14 +1,16 @@
+use std::cell::RefCell;
 use std::cmp::{max, min};
 use std::env;
 use std::io::{self, Write};
 #[cfg(target_os = "linux")]
 use std::path::PathBuf;
 use std::process::Command;
+use std::sync::mpsc::Sender;
 use std::sync::Mutex;
 use std::time::Duration;
 
 use chrono::{Local, Timelike};
-use console::{style, Key, Term};
+use console::{pad_str, style, Alignment, Key, Term};
 use lazy_static::lazy_static;
 use log::{debug, error};
 #[cfg(target_os = "macos")]
@@ -24,6 +26,121 @@ lazy_static! {
     static ref TERMINAL: Mutex<Terminal> = Mutex::new(Terminal::new());
 }
 
+/// Something printed while the output of this thread is held back by `capture_output`
+#[derive(Clone)]
+enum Captured {
+    Separator(String),
+    Warning(String),
+    Info(String),
+    Text(String),
+}
+
+impl Captured {
+    /// The item as it's shown by `--tui`
+    fn into_text(self) -> String {
+        match self {
+            Captured::Separator(message) => format!("―― {} ――\n", message),
+            Captured::Warning(message) | Captured::Info(message) => format!("{}\n", message),
+            Captured::Text(text) => text,
+        }
+    }
+}
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<Captured>>> = const { RefCell::new(None) };
+}
+
+/// What the run prints while `--tui` shows its progress, see `set_live_output`
+pub enum LiveEvent {
+    /// A step started, with its key in the summary
+    Started(String),
+    /// The output of the running step
+    Output(String),
+    /// A step finished, with its status in the summary unless it was only dry run
+    Finished(String, Option<&'static str>),
+}
+
+static LIVE_OUTPUT: Mutex<Option<Sender<LiveEvent>>> = Mutex::new(None);
+
+/// Send what's printed through this module and the executor to `sink` instead of the terminal, or print it
+/// again with `None`
+pub fn set_live_output(sink: Option<Sender<LiveEvent>>) {
+    *LIVE_OUTPUT.lock().unwrap() = sink;
+}
+
+/// Whether the output goes to the sink of `set_live_output`
+pub fn live() -> bool {
+    LIVE_OUTPUT.lock().unwrap().is_some()
+}
+
+/// Hand `event` to the sink of `set_live_output`. Returns whether there is one
+pub fn send_live(event: impl FnOnce() -> LiveEvent) -> bool {
+    match LIVE_OUTPUT.lock().unwrap().as_ref() {
+        Some(sink) => {
+            sink.send(event()).ok();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Hold `item` back if the output of this thread is being captured, or hand it to the sink of
+/// `set_live_output`. Returns whether it was
+fn capture(item: impl FnOnce() -> Captured) -> bool {
+    let captured = CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(captured) => {
+            captured.push(item());
+            None
+        }
+        None => Some(item),
+    });
+
+    match captured {
+        Some(item) => send_live(|| LiveEvent::Output(item().into_text())),
+        None => true,
+    }
+}
+
+/// Whether the output of this thread is held back by `capture_output`
+pub fn capturing() -> bool {
+    CAPTURED.with(|captured| captured.borrow().is_some())
+}
+
+/// The output held back by `capture_output`
+pub struct CapturedOutput(Vec<Captured>);
+
+impl CapturedOutput {
+    /// The output without colors, as kept for the email of the summary
+    pub fn text(&self) -> String {
+        let text: String = self.0.iter().cloned().map(Captured::into_text).collect();
+        console::strip_ansi_codes(&text).into_owned()
+    }
+
+    /// Print the output as it would have been printed without capturing it
+    pub fn print(self) {
+        for item in self.0 {
+            match item {
+                Captured::Separator(message) => print_separator(message),
+                Captured::Warning(message) => print_warning(message),
+                Captured::Info(message) => print_info(message),
+                Captured::Text(text) => print_output(text),
+            }
+        }
+    }
+}
+
+/// Run `f`, holding back what it prints through this module and the executor so that it can be printed at
+/// once later. Used to run steps at the same time without mixing their output, and to show only the output
+/// of failing steps.
+///
+/// Captures can be nested. Printing the inner `CapturedOutput` while the outer one captures adds it to the outer one.
+pub fn capture_output<T>(f: impl FnOnce() -> T) -> (T, CapturedOutput) {
+    let outer = CAPTURED.with(|captured| captured.borrow_mut().replace(Vec::new()));
+    let result = f();
+    let captured = CAPTURED.with(|captured| captured.replace(outer)).unwrap_or_default();
+    (result, CapturedOutput(captured))
+}
+
 #[cfg(unix)]
 pub fn shell() -> String {
     env::var("SHELL").unwrap_or_else(|_| "sh".to_string())
@@ -43,8 +160,63 @@ pub fn run_shell() {
         .unwrap();
 }
 
+/// Width of the summary when the output isn't a terminal
+const NON_TTY_WIDTH: usize = 80;
+
+/// Shows the text of the `TOPGRADE_NOTIFICATION` environment variable in a toast
+#[cfg(windows)]
+const WINDOWS_TOAST: &str = r#"
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null
+$template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$text = $template.GetElementsByTagName('text')
+$text.Item(0).AppendChild($template.CreateTextNode('Topgrade')) > $null
+$text.Item(1).AppendChild($template.CreateTextNode($env:TOPGRADE_NOTIFICATION)) > $null
+$app = '{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe'
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier($app).Show([Windows.UI.Notifications.ToastNotification]::new($template))
+"#;
+
+/// Shorten `s` to `width` characters, ending it with an ellipsis when it's cut
+fn ellipsize(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_string();
+    }
+
+    let mut shortened: String = s.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        shortened.push('…');
+    }
+    shortened
+}
+
+/// Lay out a summary line within `width` columns: the key is padded to `key_width` so that
+/// the status column stays aligned, and both the key and the reason are ellipsized to fit
+fn fit_summary_line(
+    key: &str,
+    status_width: usize,
+    reason: Option<&str>,
+    key_width: usize,
+    width: usize,
+) -> (String, Option<String>) {
+    let key_width = min(key_width, width / 2);
+    let key = pad_str(
+        &format!("{}:", ellipsize(key, key_width)),
+        key_width + 1,
+        Alignment::Left,
+        None,
+    )
+    .into_owned();
+    let reason = reason.map(|reason| {
+        // The space after the key and the ": " after the status
+        let reason_width = width.saturating_sub(key_width + 1 + 1 + status_width + 2);
+        ellipsize(reason, reason_width)
+    });
+
+    (key, reason)
+}
+
 struct Terminal {
     width: Option<u16>,
+    output_width: Option<u16>,
     prefix: String,
     term: Term,
     set_title: bool,
@@ -59,6 +231,7 @@ impl Terminal {
         let term = Term::stdout();
         Self {
             width: term.size_checked().map(|(_, w)| w),
+            output_width: None,
             term,
             prefix: env::var("TOPGRADE_PREFIX")
                 .map(|prefix| format!("({}) ", prefix))
@@ -79,6 +252,10 @@ impl Terminal {
         self.set_title = set_title
     }
 
+    fn set_output_width(&mut self, output_width: Option<u16>) {
+        self.output_width = output_width
+    }
+
     fn display_time(&mut self, display_time: bool) {
         self.display_time = display_time
     }
@@ -108,6 +285,13 @@ impl Terminal {
                     command.arg(message.as_ref());
                     command.output().ok();
                 }
+            } else if #[cfg(windows)] {
+                // A toast, shown as coming from PowerShell since Topgrade has no registered application ID
+                Command::new("powershell")
+                    .args(["-NoProfile", "-NonInteractive", "-Command", WINDOWS_TOAST])
+                    .env("TOPGRADE_NOTIFICATION", message.as_ref())
+                    .output()
+                    .ok();
             }
         }
     }
@@ -179,21 +363,43 @@ impl Terminal {
             .ok();
     }
 
-    fn print_result<P: AsRef<str>>(&mut self, key: P, result: &StepResult) {
-        let key = key.as_ref();
+    fn print_result<P: AsRef<str>>(&mut self, key: P, result: &StepResult, key_width: usize) {
+        let status = result.status();
+        let reason = match result {
+            StepResult::Skipped(reason) => Some(reason.message()),
+            _ => None,
+        };
+        let width = self
+            .output_width
+            .or(self.width)
+            .map(usize::from)
+            .unwrap_or(NON_TTY_WIDTH);
+        let (key, reason) = fit_summary_line(key.as_ref(), status.len(), reason, key_width, width);
+
+        let status = match result {
+            StepResult::Success => style(status).bold().green(),
+            StepResult::Failure => style(status).bold().red(),
+            StepResult::Ignored => style(status).bold().yellow(),
+            StepResult::Skipped(_) => style(status).bold().blue(),
+        };
+
+        match reason {
+            Some(reason) => self.term.write_fmt(format_args!("{} {}: {}\n", key, status, reason)),
+            None => self.term.write_fmt(format_args!("{} {}\n", key, status)),
+        }
+        .ok();
+    }
 
-        self.term
-            .write_fmt(format_args!(
-                "{}: {}\n",
-                key,
-                match result {
-                    StepResult::Success => format!("{}", style("OK").bold().green()),
-                    StepResult::Failure => format!("{}", style("FAILED").bold().red()),
-                    StepResult::Ignored => format!("{}", style("IGNORED").bold().yellow()),
-                    StepResult::Skipped(reason) => format!("{}: {}", style("SKIPPED").bold().blue(), reason),
-                }
-            ))
-            .ok();
+    fn print_changes<P: AsRef<str>>(&mut self, changes: P) {
+        for line in changes.as_ref().lines() {
+            self.term.write_fmt(format_args!("  {}\n", style(line).dim())).ok();
+        }
+    }
+
+    fn print_step_warnings<P: AsRef<str>>(&mut self, warnings: P) {
+        for line in warnings.as_ref().lines() {
+            self.term.write_fmt(format_args!("  {}\n", style(line).yellow())).ok();
+        }
     }
 
     #[allow(dead_code)]
@@ -273,21 +479,43 @@ pub fn should_retry(interrupted: bool, step_name: &str) -> Result<bool, io::Erro
 }
 
 pub fn print_separator<P: AsRef<str>>(message: P) {
-    TERMINAL.lock().unwrap().print_separator(message)
+    if !capture(|| Captured::Separator(message.as_ref().to_string())) {
+        TERMINAL.lock().unwrap().print_separator(message)
+    }
 }
 
 #[allow(dead_code)]
 pub fn print_warning<P: AsRef<str>>(message: P) {
-    TERMINAL.lock().unwrap().print_warning(message)
+    if !capture(|| Captured::Warning(message.as_ref().to_string())) {
+        TERMINAL.lock().unwrap().print_warning(message)
+    }
 }
 
 #[allow(dead_code)]
 pub fn print_info<P: AsRef<str>>(message: P) {
-    TERMINAL.lock().unwrap().print_info(message)
+    if !capture(|| Captured::Info(message.as_ref().to_string())) {
+        TERMINAL.lock().unwrap().print_info(message)
+    }
 }
 
-pub fn print_result<P: AsRef<str>>(key: P, result: &StepResult) {
-    TERMINAL.lock().unwrap().print_result(key, result)
+/// Print the output of a step as is, like `print!`
+pub fn print_output<P: AsRef<str>>(text: P) {
+    if !capture(|| Captured::Text(text.as_ref().to_string())) {
+        print!("{}", text.as_ref());
+        io::stdout().flush().ok();
+    }
+}
+
+pub fn print_result<P: AsRef<str>>(key: P, result: &StepResult, key_width: usize) {
+    TERMINAL.lock().unwrap().print_result(key, result, key_width)
+}
+
+pub fn print_changes<P: AsRef<str>>(changes: P) {
+    TERMINAL.lock().unwrap().print_changes(changes)
+}
+
+pub fn print_step_warnings<P: AsRef<str>>(warnings: P) {
+    TERMINAL.lock().unwrap().print_step_warnings(warnings)
 }
 
 /// Tells whether the terminal is dumb.
@@ -303,6 +531,10 @@ pub fn set_title(set_title: bool) {
     TERMINAL.lock().unwrap().set_title(set_title);
 }
 
+pub fn set_output_width(output_width: Option<u16>) {
+    TERMINAL.lock().unwrap().set_output_width(output_width);
+}
+
 pub fn set_desktop_notifications(desktop_notifications: bool) {
     TERMINAL
         .lock()
@@ -322,3 +554,27 @@ pub fn notify_desktop<P: AsRef<str>>(message: P, timeout: Option<Duration>) {
 pub fn display_time(display_time: bool) {
     TERMINAL.lock().unwrap().display_time(display_time);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_summary_line() {
+        assert_eq!(
+            fit_summary_line("git", 2, None, 8, 80),
+            (String::from("git:     "), None)
+        );
+        assert_eq!(
+            fit_summary_line("pearl", 7, Some("Cannot find \"pearl\" in PATH"), 5, 80),
+            (
+                String::from("pearl:"),
+                Some(String::from("Cannot find \"pearl\" in PATH"))
+            )
+        );
+        assert_eq!(
+            fit_summary_line("a very long step name", 7, Some("Cannot find \"tool\" in PATH"), 21, 30),
+            (String::from("a very long st…:"), Some(String::from("Can…")))
+        );
+    }
+}
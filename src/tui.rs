@@ -0,0 +1,277 @@
+//! The `--tui` view of a run: the steps with their status next to the output of the running step
+use std::io::{self, Stdout};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::crate_version;
+use log::error;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::cursor::{Hide, Show};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use crate::terminal::{self, LiveEvent};
+
+/// How often the elapsed times are redrawn when nothing happens
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Lines of output kept for each step
+const MAX_LINES: usize = 1000;
+
+struct StepView {
+    name: String,
+    /// When the step started, `None` while it's planned
+    started: Option<Instant>,
+    /// The status in the summary and the duration, once finished
+    finished: Option<(Option<&'static str>, Duration)>,
+    lines: Vec<String>,
+}
+
+impl StepView {
+    /// Add output to the step, keeping only what comes after a carriage return, like a terminal would show
+    fn push_output(&mut self, text: &str) {
+        let text = console::strip_ansi_codes(text);
+        let mut segments = text.split('\n');
+        if let Some(first) = segments.next() {
+            match self.lines.last_mut() {
+                Some(last) => last.push_str(first),
+                None => self.lines.push(first.to_string()),
+            }
+        }
+        self.lines.extend(segments.map(String::from));
+
+        for line in self.lines.iter_mut().filter(|line| line.contains('\r')) {
+            let kept = line
+                .rsplit('\r')
+                .find(|segment| !segment.is_empty())
+                .unwrap_or_default();
+            *line = kept.to_string();
+        }
+        let excess = self.lines.len().saturating_sub(MAX_LINES);
+        self.lines.drain(..excess);
+    }
+
+    fn sidebar_line(&self) -> Line<'_> {
+        let started = match self.started {
+            Some(started) => started,
+            None => {
+                return Line::from(vec![
+                    Span::styled("○ ", Style::default().fg(Color::DarkGray)),
+                    Span::styled(self.name.as_str(), Style::default().fg(Color::DarkGray)),
+                ])
+            }
+        };
+        let (symbol, color, duration) = match self.finished {
+            None => ("»", Color::Cyan, started.elapsed()),
+            Some((Some("OK"), duration)) => ("✔", Color::Green, duration),
+            Some((Some("FAILED"), duration)) => ("✘", Color::Red, duration),
+            Some((Some("IGNORED"), duration)) => ("!", Color::Yellow, duration),
+            Some((Some(_), duration)) => ("-", Color::DarkGray, duration),
+            Some((None, duration)) => ("·", Color::DarkGray, duration),
+        };
+
+        Line::from(vec![
+            Span::styled(format!("{} ", symbol), Style::default().fg(color)),
+            Span::raw(self.name.as_str()),
+            Span::styled(
+                format!(" {}s", duration.as_secs()),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ])
+    }
+}
+
+struct View {
+    started: Instant,
+    steps: Vec<StepView>,
+    /// The step whose output is shown
+    current: Option<usize>,
+}
+
+impl View {
+    fn apply(&mut self, event: LiveEvent) {
+        match event {
+            LiveEvent::Started(name) => {
+                // Steps run by `execute_parallel` start on their thread, then again when their output is shown
+                let index = match self
+                    .steps
+                    .iter()
+                    .position(|step| step.name == name && step.finished.is_none())
+                {
+                    Some(index) => index,
+                    None => {
+                        // Not planned, like the commands run after a step
+                        let index = self.current.map_or(self.steps.len(), |current| current + 1);
+                        self.steps.insert(
+                            index,
+                            StepView {
+                                name,
+                                started: None,
+                                finished: None,
+                                lines: Vec::new(),
+                            },
+                        );
+                        index
+                    }
+                };
+                let step = &mut self.steps[index];
+                step.started.get_or_insert_with(Instant::now);
+                self.current = Some(index);
+            }
+            LiveEvent::Output(text) => {
+                if let Some(step) = self.current.and_then(|index| self.steps.get_mut(index)) {
+                    step.push_output(&text);
+                }
+            }
+            LiveEvent::Finished(name, status) => {
+                if let Some(step) = self
+                    .steps
+                    .iter_mut()
+                    .rev()
+                    .find(|step| step.name == name && step.finished.is_none())
+                {
+                    let duration = step.started.map(|started| started.elapsed()).unwrap_or_default();
+                    step.finished = Some((status, duration));
+                }
+            }
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [header, body] = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+        let sidebar_width = self
+            .steps
+            .iter()
+            .map(|step| step.name.chars().count() as u16 + 10)
+            .max()
+            .unwrap_or(20)
+            .clamp(20, 40);
+        let [sidebar, output] = Layout::horizontal([Constraint::Length(sidebar_width), Constraint::Min(0)]).areas(body);
+
+        let done = self.steps.iter().filter(|step| step.finished.is_some()).count();
+        let total = self.steps.len();
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::styled(
+                    format!("Topgrade {}", crate_version!()),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(format!(
+                    " - {}/{} steps done - {}",
+                    done,
+                    total,
+                    humantime::format_duration(Duration::from_secs(self.started.elapsed().as_secs()))
+                )),
+            ])),
+            header,
+        );
+
+        // Keep the running step in the middle of the view, with the next ones below it
+        let visible = sidebar.height.saturating_sub(2) as usize;
+        let first = self
+            .current
+            .unwrap_or(0)
+            .saturating_sub(visible / 2)
+            .min(self.steps.len().saturating_sub(visible));
+        let lines: Vec<Line> = self.steps.iter().skip(first).map(StepView::sidebar_line).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title("Steps")), sidebar);
+
+        let step = self.current.and_then(|index| self.steps.get(index));
+        let visible = output.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = step
+            .map(|step| {
+                step.lines
+                    .iter()
+                    .skip(step.lines.len().saturating_sub(visible))
+                    .map(|line| Line::raw(line.as_str()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let title = step.map(|step| step.name.as_str()).unwrap_or("Output");
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), output);
+    }
+}
+
+fn draw_until_disconnected(
+    mut terminal: Terminal<CrosstermBackend<Stdout>>,
+    events: Receiver<LiveEvent>,
+    planned: Vec<String>,
+) -> Result<()> {
+    let mut view = View {
+        started: Instant::now(),
+        steps: planned
+            .into_iter()
+            .map(|name| StepView {
+                name,
+                started: None,
+                finished: None,
+                lines: Vec::new(),
+            })
+            .collect(),
+        current: None,
+    };
+
+    loop {
+        let mut started = false;
+        match events.recv_timeout(REDRAW_INTERVAL) {
+            Ok(event) => {
+                started = matches!(event, LiveEvent::Started(_));
+                view.apply(event);
+                for event in events.try_iter() {
+                    started |= matches!(event, LiveEvent::Started(_));
+                    view.apply(event);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        // Commands which write to the terminal themselves leave their output behind, so redraw everything
+        // for each new step
+        if started {
+            terminal.clear()?;
+        }
+        terminal.draw(|frame| view.render(frame))?;
+    }
+}
+
+/// Shows the view until it's dropped. The output printed in the meantime goes to the view instead of the
+/// terminal, so the steps can't prompt for input
+pub struct Tui {
+    drawing: Option<JoinHandle<Result<()>>>,
+}
+
+impl Tui {
+    /// Start showing the view, with the `planned` steps listed before they run
+    pub fn start(planned: Vec<String>) -> Result<Self> {
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+        let (sender, receiver) = mpsc::channel();
+        terminal::set_live_output(Some(sender));
+        let drawing = thread::spawn(move || draw_until_disconnected(terminal, receiver, planned));
+
+        Ok(Self { drawing: Some(drawing) })
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        // Dropping the sender stops the drawing thread
+        terminal::set_live_output(None);
+        if let Some(Ok(Err(e))) = self.drawing.take().map(JoinHandle::join) {
+            error!("Failed to draw the TUI: {}", e);
+        }
+
+        if let Err(e) = execute!(io::stdout(), LeaveAlternateScreen, Show) {
+            error!("Failed to restore the terminal: {}", e);
+        }
+    }
+}
@@ -5,8 +5,10 @@ use log::{debug, error};
 use std::env;
 use std::ffi::OsStr;
 use std::fmt::Debug;
+use std::net::{TcpStream, ToSocketAddrs};
 use std::path::{Path, PathBuf};
 use std::process::{ExitStatus, Output};
+use std::time::Duration;
 
 pub trait Check {
     fn check(self) -> Result<()>;
@@ -76,7 +78,7 @@ where
             debug!("Path {:?} exists", self.as_ref());
             Ok(self)
         } else {
-            Err(SkipStep(format!("Path {:?} doesn't exist", self.as_ref())).into())
+            Err(SkipStep::not_installed(format!("Path {:?} doesn't exist", self.as_ref())).into())
         }
     }
 }
@@ -102,10 +104,20 @@ pub fn which<T: AsRef<OsStr> + Debug>(binary_name: T) -> Option<PathBuf> {
     }
 }
 
-pub fn sudo() -> Option<PathBuf> {
+/// The tool running commands as root: `sudo_command` if it's set, otherwise the first one installed
+pub fn sudo(sudo_command: Option<&str>) -> Option<PathBuf> {
+    if let Some(command) = sudo_command {
+        let sudo = which(command);
+        if sudo.is_none() {
+            log::error!("Cannot find the sudo_command {}", command);
+        }
+        return sudo;
+    }
+
     which("doas")
         .or_else(|| which("sudo"))
         .or_else(|| which("gsudo"))
+        .or_else(|| which("run0"))
         .or_else(|| which("pkexec"))
 }
 
@@ -125,7 +137,7 @@ pub fn require<T: AsRef<OsStr> + Debug>(binary_name: T) -> Result<PathBuf> {
         }
         Err(e) => match e {
             which_crate::Error::CannotFindBinaryPath => {
-                Err(SkipStep(format!("Cannot find {:?} in PATH", &binary_name)).into())
+                Err(SkipStep::not_installed(format!("Cannot find {:?} in PATH", &binary_name)).into())
             }
             _ => {
                 panic!("Detecting {:?} failed: {}", &binary_name, e);
@@ -139,6 +151,48 @@ pub fn require_option<T>(option: Option<T>, cause: String) -> Result<T> {
     if let Some(value) = option {
         Ok(value)
     } else {
-        Err(SkipStep(cause).into())
+        Err(SkipStep::not_installed(cause).into())
+    }
+}
+
+/// Tell whether a TCP connection can be opened to the host of the URL within `timeout`
+///
+/// Used as a cheap connectivity check by network heavy steps.
+pub fn is_reachable(url: &str, timeout: Duration) -> bool {
+    let (default_port, rest) = match url.split_once("://") {
+        Some(("http", rest)) => (80, rest),
+        Some((_, rest)) => (443, rest),
+        None => (443, url),
+    };
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:{}", authority, default_port)
+    };
+
+    let reachable = address
+        .to_socket_addrs()
+        .map(|mut addresses| addresses.any(|address| TcpStream::connect_timeout(&address, timeout).is_ok()))
+        .unwrap_or(false);
+    debug!("{} reachable: {}", address, reachable);
+
+    reachable
+}
+
+/// Quote a string so that a POSIX shell passes it on as a single word
+#[allow(dead_code)]
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/home/user/.nvm/nvm.sh"), "'/home/user/.nvm/nvm.sh'");
+        assert_eq!(shell_quote("it's $HOME"), "'it'\\''s $HOME'");
     }
 }